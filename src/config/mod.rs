@@ -4,13 +4,13 @@
 mod socket;
 pub use socket::{
     BindFlags, Config as SocketConfig, ConfigBuilder as SocketConfigBuilder, Interface,
-    LibxdpFlags, XdpFlags,
+    LibxdpFlags, XdpAction, XdpActionError, XdpFlags,
 };
 
 mod umem;
 pub use umem::{
     Config as UmemConfig, ConfigBuildError as UmemConfigBuilderError,
-    ConfigBuilder as UmemConfigBuilder,
+    ConfigBuilder as UmemConfigBuilder, UmemFlags,
 };
 
 use std::{convert::TryFrom, error, fmt};
@@ -42,6 +42,18 @@ impl QueueSize {
     pub fn get(&self) -> u32 {
         self.0
     }
+
+    /// Half this queue size, provided the result is no smaller than
+    /// `min`.
+    pub(crate) fn halved(&self, min: QueueSize) -> Option<Self> {
+        let half = self.0 / 2;
+
+        if half >= min.0 && half > 0 {
+            Some(Self(half))
+        } else {
+            None
+        }
+    }
 }
 
 impl TryFrom<u32> for QueueSize {
@@ -123,6 +135,16 @@ mod tests {
         assert!(QueueSize::new(4).is_ok());
     }
 
+    #[test]
+    fn queue_size_halves_down_to_min() {
+        let min = QueueSize::new(4).unwrap();
+
+        assert_eq!(QueueSize::new(16).unwrap().halved(min).unwrap().get(), 8);
+        assert_eq!(QueueSize::new(8).unwrap().halved(min).unwrap().get(), 4);
+        assert!(QueueSize::new(4).unwrap().halved(min).is_none());
+        assert!(QueueSize::new(1).unwrap().halved(QueueSize(1)).is_none());
+    }
+
     #[test]
     fn frame_size_should_reject_values_below_2048() {
         assert!(FrameSize::new(0).is_err());
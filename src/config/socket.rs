@@ -5,8 +5,11 @@ use libxdp_sys::{
 };
 use std::{
     convert::{TryFrom, TryInto},
+    error,
     ffi::{CStr, CString, NulError},
+    fmt,
     str::FromStr,
+    time::Duration,
 };
 
 use super::QueueSize;
@@ -17,6 +20,15 @@ bitflags! {
     pub struct LibxdpFlags: u32 {
         /// Set to avoid loading of default XDP program on socket
         /// creation.
+        ///
+        /// Loading and attaching the default program is carried out
+        /// entirely within `libxdp`, which opens and closes its own
+        /// netlink socket to do so, and there is no way to hand it an
+        /// existing one. In environments where opening new netlink
+        /// sockets is disallowed, set this flag and load and attach
+        /// the XDP program yourself, using whichever netlink socket
+        /// is available, then add the socket's file descriptor to the
+        /// program's `XSKMAP`.
         const XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD = 1;
     }
 }
@@ -62,6 +74,62 @@ bitflags! {
     }
 }
 
+/// The action an XDP program takes on a packet.
+///
+/// Mirrors `enum xdp_action` as defined in the linux source at
+/// `include/uapi/linux/bpf.h`, and converts to and from the raw return
+/// codes used there.
+#[repr(u32)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum XdpAction {
+    /// Drop the packet and raise the `xdp_exception` tracepoint,
+    /// signalling an error in the program.
+    Aborted = 0,
+    /// Drop the packet.
+    Drop = 1,
+    /// Pass the packet on to the kernel's network stack.
+    #[default]
+    Pass = 2,
+    /// Transmit the packet back out of the interface it arrived on.
+    Tx = 3,
+    /// Redirect the packet elsewhere, e.g. to an AF_XDP socket via an
+    /// `XSKMAP`.
+    Redirect = 4,
+}
+
+impl From<XdpAction> for u32 {
+    fn from(action: XdpAction) -> Self {
+        action as u32
+    }
+}
+
+impl TryFrom<u32> for XdpAction {
+    type Error = XdpActionError;
+
+    fn try_from(code: u32) -> Result<Self, Self::Error> {
+        match code {
+            0 => Ok(Self::Aborted),
+            1 => Ok(Self::Drop),
+            2 => Ok(Self::Pass),
+            3 => Ok(Self::Tx),
+            4 => Ok(Self::Redirect),
+            _ => Err(XdpActionError(code)),
+        }
+    }
+}
+
+/// Error signifying an unknown XDP action code.
+#[derive(Debug)]
+pub struct XdpActionError(u32);
+
+impl fmt::Display for XdpActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown XDP action code {}", self.0)
+    }
+}
+
+impl error::Error for XdpActionError {}
+
 /// A device interface name.
 #[derive(Debug, Clone)]
 pub struct Interface(CString);
@@ -147,6 +215,126 @@ impl ConfigBuilder {
         self
     }
 
+    /// If set, and [`XDP_ZEROCOPY`](BindFlags::XDP_ZEROCOPY) is
+    /// requested but not supported by the driver, then rather than
+    /// failing socket creation will be retried in copy mode. Whether
+    /// this happened can be checked via
+    /// [`Socket::effective_bind_mode`](crate::Socket::effective_bind_mode).
+    /// Default is `false`.
+    pub fn zerocopy_fallback(&mut self, fallback: bool) -> &mut Self {
+        self.config.zerocopy_fallback = fallback;
+        self
+    }
+
+    /// If set, and socket creation fails with `ENOMEM` (for example
+    /// on a host where locked or huge page memory is tight), creation
+    /// will be retried with the [`RxQueue`](crate::RxQueue) and
+    /// [`TxQueue`](crate::TxQueue) sizes halved, repeatedly, until it
+    /// either succeeds or neither can be halved without dropping
+    /// below `min`. The sizes finally used can be checked via
+    /// [`Socket::rx_queue_size`](crate::Socket::rx_queue_size) and
+    /// [`Socket::tx_queue_size`](crate::Socket::tx_queue_size).
+    ///
+    /// Only sockets which share a [`Umem`](crate::Umem) created
+    /// earlier are retried. The first socket created with a `Umem`
+    /// uses the `Umem`'s own file descriptor, which outlives a failed
+    /// attempt along with any ring already set up on it, e.g. the rx
+    /// ring when it's the tx ring that fails, so its sizes can't be
+    /// changed on a retry. For that socket `ENOMEM` is returned
+    /// regardless.
+    ///
+    /// Bear in mind that smaller rings absorb less of a burst, so a
+    /// socket created this way may be more prone to dropping packets
+    /// under load than one with the sizes originally requested.
+    /// Default is `None`, i.e. fail on `ENOMEM`.
+    pub fn enomem_min_queue_size(&mut self, min: Option<QueueSize>) -> &mut Self {
+        self.config.enomem_min_queue_size = min;
+        self
+    }
+
+    /// If set, and the socket shares its [`Umem`](crate::Umem) with
+    /// another, [`TxQueue::wakeup`](crate::TxQueue::wakeup) will pass
+    /// the socket's bound `sockaddr_xdp` address to `sendto` rather
+    /// than a null destination. See
+    /// [`Socket::wakeup_address_required`](crate::Socket::wakeup_address_required)
+    /// for when this applies. Default is `false`.
+    pub fn wakeup_with_address(&mut self, with_address: bool) -> &mut Self {
+        self.config.wakeup_with_address = with_address;
+        self
+    }
+
+    /// If set, socket creation leaves the interface's XDP program and
+    /// `XSKMAP` alone entirely, on the assumption that they are
+    /// managed out of band, e.g. by a separate orchestrator, and that
+    /// packets arriving on the socket's queue are already redirected
+    /// to it. The socket is only created and bound.
+    ///
+    /// This is equivalent to setting
+    /// [`XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD`](LibxdpFlags::XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD),
+    /// regardless of the [`LibxdpFlags`] otherwise set, and means the
+    /// [`XdpFlags`] have no effect. The caller is fully responsible for
+    /// the redirect path: until the socket's file descriptor is added
+    /// to the program's `XSKMAP` (see
+    /// [`XsksMap`](crate::socket::XsksMap)), nothing will be received.
+    /// Default is `false`.
+    pub fn assume_program_loaded(&mut self, assume_loaded: bool) -> &mut Self {
+        self.config.assume_program_loaded = assume_loaded;
+        self
+    }
+
+    /// The action the XDP program should take on packets which
+    /// aren't redirected to an AF_XDP socket, e.g. those arriving on
+    /// queues no socket is bound to.
+    ///
+    /// The default program loaded by `libxdp` is fixed to
+    /// [`XdpAction::Pass`], which on a busy interface can mean the
+    /// host's network stack sees far more traffic than it can cope
+    /// with. Any other action requires loading your own program, so
+    /// socket creation fails unless
+    /// [`assume_program_loaded`](Self::assume_program_loaded) or
+    /// [`XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD`](LibxdpFlags::XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD)
+    /// is also set. The program is then responsible for honouring the
+    /// action, which can be read back via
+    /// [`SocketConfig::default_action`](Config::default_action).
+    /// Default is [`XdpAction::Pass`].
+    pub fn default_action(&mut self, action: XdpAction) -> &mut Self {
+        self.config.default_action = action;
+        self
+    }
+
+    /// If set, waking up the kernel on a socket whose interface is
+    /// down fails with an `ENETDOWN` error, which
+    /// [`is_link_down`](crate::socket::is_link_down) recognises, rather
+    /// than quietly succeeding without anything being sent or
+    /// received. See there for which calls this covers. Default is
+    /// `false`.
+    pub fn report_link_down(&mut self, report: bool) -> &mut Self {
+        self.config.report_link_down = report;
+        self
+    }
+
+    /// If set, [`Socket::new`](crate::Socket::new) gives up and returns
+    /// a [`TimedOut`](std::io::ErrorKind::TimedOut) error if creating
+    /// the socket takes longer than `timeout`, e.g. because a
+    /// misbehaving driver hangs while the program is loaded or the
+    /// socket bound. Default is `None`, i.e. wait indefinitely.
+    ///
+    /// Creation is run on a separate thread which the calling thread
+    /// waits on. A syscall stuck in the kernel can't be interrupted, so
+    /// on timeout that thread is left to run and is only cleaned up if
+    /// creation eventually finishes, in which case the socket is
+    /// deleted again straight away. In the meantime it holds the
+    /// [`Umem`](crate::Umem)'s lock, and should it complete it may
+    /// have taken the `Umem`'s fill and completion queues with it. So
+    /// on timeout the `Umem` is marked unusable, and any further
+    /// `Socket::new` with it or its clones fails straight away rather
+    /// than waiting on the lock. It should be dropped, and the
+    /// interface left alone, until whatever hung has been dealt with.
+    pub fn creation_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.config.creation_timeout = timeout;
+        self
+    }
+
     /// Build a [`SocketConfig`](Config) instance using the values set
     /// in this builder.
     pub fn build(&self) -> Config {
@@ -162,6 +350,13 @@ pub struct Config {
     libxdp_flags: LibxdpFlags,
     xdp_flags: XdpFlags,
     bind_flags: BindFlags,
+    zerocopy_fallback: bool,
+    enomem_min_queue_size: Option<QueueSize>,
+    wakeup_with_address: bool,
+    assume_program_loaded: bool,
+    default_action: XdpAction,
+    report_link_down: bool,
+    creation_timeout: Option<Duration>,
 }
 
 impl Config {
@@ -194,6 +389,57 @@ impl Config {
     pub fn bind_flags(&self) -> &BindFlags {
         &self.bind_flags
     }
+
+    /// Whether to fall back to copy mode if zero-copy mode is
+    /// requested but not supported.
+    pub fn zerocopy_fallback(&self) -> bool {
+        self.zerocopy_fallback
+    }
+
+    /// The smallest queue size to shrink to when retrying socket
+    /// creation after `ENOMEM`, if retrying is enabled.
+    pub fn enomem_min_queue_size(&self) -> Option<QueueSize> {
+        self.enomem_min_queue_size
+    }
+
+    /// Whether tx wakeups pass the socket's bound address to `sendto`
+    /// when sharing a [`Umem`](crate::Umem).
+    pub fn wakeup_with_address(&self) -> bool {
+        self.wakeup_with_address
+    }
+
+    /// Whether socket creation assumes an XDP program is already
+    /// loaded and redirecting to the socket, and so leaves it alone.
+    pub fn assume_program_loaded(&self) -> bool {
+        self.assume_program_loaded
+    }
+
+    /// The action for the XDP program to take on packets not
+    /// redirected to a socket.
+    pub fn default_action(&self) -> XdpAction {
+        self.default_action
+    }
+
+    /// Whether wakeups return an error while the socket's interface
+    /// is down.
+    pub fn report_link_down(&self) -> bool {
+        self.report_link_down
+    }
+
+    /// How long socket creation may take before it's given up on, if
+    /// at all.
+    pub fn creation_timeout(&self) -> Option<Duration> {
+        self.creation_timeout
+    }
+
+    /// Whether socket creation will have `libxdp` load its default
+    /// program.
+    pub(crate) fn loads_default_program(&self) -> bool {
+        !self.assume_program_loaded
+            && !self
+                .libxdp_flags
+                .contains(LibxdpFlags::XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD)
+    }
 }
 
 impl Default for Config {
@@ -204,14 +450,27 @@ impl Default for Config {
             libxdp_flags: LibxdpFlags::empty(),
             xdp_flags: XdpFlags::empty(),
             bind_flags: BindFlags::empty(),
+            zerocopy_fallback: false,
+            enomem_min_queue_size: None,
+            wakeup_with_address: false,
+            assume_program_loaded: false,
+            default_action: XdpAction::Pass,
+            report_link_down: false,
+            creation_timeout: None,
         }
     }
 }
 
 impl From<Config> for xsk_socket_config {
     fn from(c: Config) -> Self {
+        let mut libxdp_flags = c.libxdp_flags;
+
+        if c.assume_program_loaded {
+            libxdp_flags.insert(LibxdpFlags::XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD);
+        }
+
         let xsk_socket_config = xsk_socket_config__bindgen_ty_1 {
-            libxdp_flags: c.libxdp_flags.bits(),
+            libxdp_flags: libxdp_flags.bits(),
         };
 
         xsk_socket_config {
@@ -223,3 +482,56 @@ impl From<Config> for xsk_socket_config {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assume_program_loaded_inhibits_prog_load() {
+        let config: xsk_socket_config = ConfigBuilder::new()
+            .assume_program_loaded(true)
+            .build()
+            .into();
+
+        assert_eq!(
+            unsafe { config.__bindgen_anon_1.libxdp_flags },
+            LibxdpFlags::XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD.bits()
+        );
+
+        let config: xsk_socket_config = Config::default().into();
+
+        assert_eq!(unsafe { config.__bindgen_anon_1.libxdp_flags }, 0);
+    }
+
+    #[test]
+    fn xdp_action_round_trips_through_raw_codes() {
+        for action in [
+            XdpAction::Aborted,
+            XdpAction::Drop,
+            XdpAction::Pass,
+            XdpAction::Tx,
+            XdpAction::Redirect,
+        ] {
+            assert_eq!(XdpAction::try_from(u32::from(action)).unwrap(), action);
+        }
+
+        assert_eq!(u32::from(XdpAction::Pass), 2);
+        assert!(XdpAction::try_from(5).is_err());
+    }
+
+    #[test]
+    fn either_inhibit_option_skips_default_program() {
+        assert!(Config::default().loads_default_program());
+
+        assert!(!ConfigBuilder::new()
+            .assume_program_loaded(true)
+            .build()
+            .loads_default_program());
+
+        assert!(!ConfigBuilder::new()
+            .libxdp_flags(LibxdpFlags::XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD)
+            .build()
+            .loads_default_program());
+    }
+}
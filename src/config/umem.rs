@@ -1,11 +1,24 @@
+use bitflags::bitflags;
 use libxdp_sys::{
     xsk_umem_config, XDP_PACKET_HEADROOM, XSK_RING_CONS__DEFAULT_NUM_DESCS,
     XSK_RING_PROD__DEFAULT_NUM_DESCS, XSK_UMEM__DEFAULT_FRAME_HEADROOM,
     XSK_UMEM__DEFAULT_FRAME_SIZE,
 };
-use std::{error, fmt};
+use std::{error, fmt, num::NonZeroU32};
 
-use super::{FrameSize, QueueSize};
+use super::{FrameSize, QueueSize, XDP_UMEM_MIN_CHUNK_SIZE};
+
+bitflags! {
+    /// UMEM registration flags.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct UmemFlags: u32 {
+        /// Have the kernel calculate TX checksums in software when
+        /// requested via TX metadata, for drivers without checksum
+        /// offload. Intended for testing, requires kernel 6.11 or
+        /// later.
+        const XDP_UMEM_TX_SW_CSUM = 1 << 1;
+    }
+}
 
 /// Builder for a [`UmemConfig`](Config).
 #[derive(Debug, Default, Clone, Copy)]
@@ -50,6 +63,13 @@ impl ConfigBuilder {
         self
     }
 
+    /// Set the [`UmemFlags`] used when registering the UMEM with the
+    /// kernel. Default is no flags set.
+    pub fn flags(&mut self, flags: UmemFlags) -> &mut Self {
+        self.config.flags = flags;
+        self
+    }
+
     /// Build a [`UmemConfig`](Config) instance using the values set
     /// in this builder.
     ///
@@ -84,6 +104,7 @@ pub struct Config {
     fill_queue_size: QueueSize,
     comp_queue_size: QueueSize,
     frame_headroom: u32,
+    flags: UmemFlags,
 }
 
 impl Config {
@@ -93,6 +114,47 @@ impl Config {
         ConfigBuilder::new()
     }
 
+    /// A config with room for `count` packets of up to `mtu` bytes
+    /// each. Pass the same `count` as the frame count when creating
+    /// the [`Umem`](crate::Umem).
+    ///
+    /// The values are derived as follows:
+    ///
+    /// - frame size is `mtu` plus [`XDP_PACKET_HEADROOM`] and the
+    ///   default frame headroom ([`XSK_UMEM__DEFAULT_FRAME_HEADROOM`]),
+    ///   rounded up to the next power of two and to at least
+    ///   [`XDP_UMEM_MIN_CHUNK_SIZE`](super::XDP_UMEM_MIN_CHUNK_SIZE).
+    ///   A power of two is required for aligned mode chunks. Note the
+    ///   kernel also rejects frames larger than the page size (usually
+    ///   4096 bytes) in aligned mode, so an `mtu` of more than `4096 -
+    ///   XDP_PACKET_HEADROOM` will need huge pages or multi-buffer
+    ///   packets instead.
+    /// - the fill and completion queue sizes are `count` rounded up
+    ///   to the next power of two, so every frame can be handed to the
+    ///   kernel at once without either ring filling up.
+    ///
+    /// The resulting [`mtu`](Self::mtu) may be larger than requested
+    /// due to the rounding, and is only smaller if `mtu` exceeds the
+    /// largest possible frame size. Values can be adjusted afterwards
+    /// through a [`ConfigBuilder`] if needed.
+    pub fn for_packets(count: NonZeroU32, mtu: u32) -> Self {
+        let frame_size = mtu
+            .saturating_add(XDP_PACKET_HEADROOM + XSK_UMEM__DEFAULT_FRAME_HEADROOM)
+            .max(XDP_UMEM_MIN_CHUNK_SIZE)
+            .checked_next_power_of_two()
+            .unwrap_or(1 << 31);
+
+        let queue_size = count.get().checked_next_power_of_two().unwrap_or(1 << 31);
+
+        Self {
+            frame_size: FrameSize(frame_size),
+            fill_queue_size: QueueSize(queue_size),
+            comp_queue_size: QueueSize(queue_size),
+            frame_headroom: XSK_UMEM__DEFAULT_FRAME_HEADROOM,
+            flags: UmemFlags::empty(),
+        }
+    }
+
     /// The size of each frame in the [`Umem`](crate::Umem).
     pub fn frame_size(&self) -> FrameSize {
         self.frame_size
@@ -118,6 +180,15 @@ impl Config {
         self.frame_headroom
     }
 
+    /// The [`UmemFlags`] set.
+    pub fn flags(&self) -> &UmemFlags {
+        &self.flags
+    }
+
+    pub(crate) fn set_flags(&mut self, flags: UmemFlags) {
+        self.flags = flags;
+    }
+
     /// The maximum transmission unit, or the length of the packet
     /// data segment of the frame.
     ///
@@ -135,6 +206,7 @@ impl Default for Config {
             fill_queue_size: QueueSize(XSK_RING_PROD__DEFAULT_NUM_DESCS),
             comp_queue_size: QueueSize(XSK_RING_CONS__DEFAULT_NUM_DESCS),
             frame_headroom: XSK_UMEM__DEFAULT_FRAME_HEADROOM,
+            flags: UmemFlags::empty(),
         }
     }
 }
@@ -146,7 +218,7 @@ impl From<Config> for xsk_umem_config {
             comp_size: c.comp_queue_size.get(),
             frame_size: c.frame_size.get(),
             frame_headroom: c.frame_headroom,
-            flags: 0,
+            flags: c.flags.bits(),
         }
     }
 }
@@ -174,8 +246,6 @@ impl error::Error for ConfigBuildError {}
 mod tests {
     use std::convert::TryInto;
 
-    use crate::config::XDP_UMEM_MIN_CHUNK_SIZE;
-
     use super::*;
 
     #[test]
@@ -208,4 +278,20 @@ mod tests {
             XDP_UMEM_MIN_CHUNK_SIZE - (frame_headroom + XDP_PACKET_HEADROOM)
         );
     }
+
+    #[test]
+    fn for_packets_rounds_frame_and_queue_sizes_to_powers_of_two() {
+        let config = Config::for_packets(1000.try_into().unwrap(), 1500);
+
+        assert_eq!(config.frame_size().get(), XDP_UMEM_MIN_CHUNK_SIZE);
+        assert_eq!(config.fill_queue_size().get(), 1024);
+        assert_eq!(config.comp_queue_size().get(), 1024);
+        assert!(config.mtu() >= 1500);
+
+        let config = Config::for_packets(1.try_into().unwrap(), 3000);
+
+        assert_eq!(config.frame_size().get(), 4096);
+        assert_eq!(config.fill_queue_size().get(), 1);
+        assert!(config.mtu() >= 3000);
+    }
 }
@@ -0,0 +1,453 @@
+//! Helpers for inspecting the contents of frames.
+
+use std::net::Ipv4Addr;
+
+const ETH_HDR_LEN: usize = 14;
+const VLAN_HDR_LEN: usize = 4;
+
+pub(crate) const ETH_P_IPV4: u16 = 0x0800;
+const ETH_P_IPV6: u16 = 0x86dd;
+const ETH_P_8021Q: u16 = 0x8100;
+const ETH_P_8021AD: u16 = 0x88a8;
+
+const IPV4_MIN_HDR_LEN: usize = 20;
+const IPV6_HDR_LEN: usize = 40;
+
+const UDP_HDR_LEN: usize = 8;
+
+const IPPROTO_TCP: u8 = 6;
+pub(crate) const IPPROTO_UDP: u8 = 17;
+const IPPROTO_SCTP: u8 = 132;
+
+const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// Computes a symmetric hash of the 5-tuple (source and destination
+/// address, source and destination port, and protocol) of the
+/// Ethernet frame in `data`, suitable for spreading flows across
+/// worker threads.
+///
+/// The hash is symmetric, so both directions of a flow produce the
+/// same value and therefore land on the same worker. Both IPv4 and
+/// IPv6 are supported, optionally behind up to two VLAN tags. Ports
+/// are only included for TCP, UDP and SCTP, and are omitted for
+/// non-initial IPv4 fragments, since they aren't present. IPv6
+/// extension headers are not walked, so ports are omitted if any are
+/// present.
+///
+/// This is not a cryptographic hash and shouldn't be relied upon
+/// where an adversary may pick flows to collide.
+///
+/// Returns `None` if `data` isn't an IP packet or is too short to
+/// contain the relevant headers.
+pub fn flow_hash(data: &[u8]) -> Option<u32> {
+    let (ethertype, l3_offset) = ethertype_and_l3_offset(data)?;
+
+    let l3 = &data[l3_offset..];
+
+    let (src, dst, protocol, l4) = match ethertype {
+        ETH_P_IPV4 => {
+            if l3.len() < IPV4_MIN_HDR_LEN || l3[0] >> 4 != 4 {
+                return None;
+            }
+
+            let hdr_len = ((l3[0] & 0x0f) as usize) * 4;
+
+            if hdr_len < IPV4_MIN_HDR_LEN || l3.len() < hdr_len {
+                return None;
+            }
+
+            let frag_offset = u16::from_be_bytes([l3[6], l3[7]]) & 0x1fff;
+
+            let l4 = if frag_offset == 0 {
+                Some(&l3[hdr_len..])
+            } else {
+                None
+            };
+
+            (&l3[12..16], &l3[16..20], l3[9], l4)
+        }
+        ETH_P_IPV6 => {
+            if l3.len() < IPV6_HDR_LEN || l3[0] >> 4 != 6 {
+                return None;
+            }
+
+            (&l3[8..24], &l3[24..40], l3[6], Some(&l3[IPV6_HDR_LEN..]))
+        }
+        _ => return None,
+    };
+
+    let (src_port, dst_port) = match (protocol, l4) {
+        (IPPROTO_TCP | IPPROTO_UDP | IPPROTO_SCTP, Some(l4)) if l4.len() >= 4 => {
+            (&l4[0..2], &l4[2..4])
+        }
+        _ => (&[0u8; 2][..], &[0u8; 2][..]),
+    };
+
+    // Order the two endpoints so that swapping source and destination
+    // yields the same input to the hash.
+    let ((lo_addr, lo_port), (hi_addr, hi_port)) = if (src, src_port) <= (dst, dst_port) {
+        ((src, src_port), (dst, dst_port))
+    } else {
+        ((dst, dst_port), (src, src_port))
+    };
+
+    let hash = [lo_addr, lo_port, hi_addr, hi_port, &[protocol][..]]
+        .iter()
+        .flat_map(|bytes| bytes.iter())
+        .fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ (*byte as u32)).wrapping_mul(FNV_PRIME)
+        });
+
+    Some(hash)
+}
+
+/// A view over the Ethernet header at the start of a frame.
+#[derive(Debug, Clone, Copy)]
+pub struct EthHdr<'a> {
+    data: &'a [u8],
+    ethertype: u16,
+    payload_offset: usize,
+}
+
+impl<'a> EthHdr<'a> {
+    /// Parses the Ethernet header at the start of `data`, skipping
+    /// over up to two VLAN tags.
+    ///
+    /// Returns `None` if `data` is too short to hold the header and
+    /// any tags.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let (ethertype, payload_offset) = ethertype_and_l3_offset(data)?;
+
+        Some(Self {
+            data,
+            ethertype,
+            payload_offset,
+        })
+    }
+
+    /// The destination MAC address.
+    #[inline]
+    pub fn dst_mac(&self) -> [u8; 6] {
+        mac(&self.data[0..6])
+    }
+
+    /// The source MAC address.
+    #[inline]
+    pub fn src_mac(&self) -> [u8; 6] {
+        mac(&self.data[6..12])
+    }
+
+    /// The ethertype of the payload, i.e. following any VLAN tags.
+    #[inline]
+    pub fn ethertype(&self) -> u16 {
+        self.ethertype
+    }
+
+    /// The bytes following the header and any VLAN tags.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        &self.data[self.payload_offset..]
+    }
+}
+
+/// A view over an IPv4 header and the packet it heads.
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv4Hdr<'a> {
+    data: &'a [u8],
+    hdr_len: usize,
+}
+
+impl<'a> Ipv4Hdr<'a> {
+    /// Parses the IPv4 header at the start of `data`, e.g. the
+    /// [`payload`](EthHdr::payload) of an [`EthHdr`].
+    ///
+    /// Returns `None` if the version isn't 4, or if the header or
+    /// total lengths are inconsistent with each other or with the
+    /// length of `data`. Any bytes beyond the total length, such as
+    /// Ethernet padding, are excluded from the
+    /// [`payload`](Self::payload).
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.len() < IPV4_MIN_HDR_LEN || data[0] >> 4 != 4 {
+            return None;
+        }
+
+        let hdr_len = ((data[0] & 0x0f) as usize) * 4;
+        let total_len = u16::from_be_bytes([data[2], data[3]]) as usize;
+
+        if hdr_len < IPV4_MIN_HDR_LEN || total_len < hdr_len || data.len() < total_len {
+            return None;
+        }
+
+        Some(Self {
+            data: &data[..total_len],
+            hdr_len,
+        })
+    }
+
+    /// The source address.
+    #[inline]
+    pub fn src(&self) -> Ipv4Addr {
+        Ipv4Addr::new(self.data[12], self.data[13], self.data[14], self.data[15])
+    }
+
+    /// The destination address.
+    #[inline]
+    pub fn dst(&self) -> Ipv4Addr {
+        Ipv4Addr::new(self.data[16], self.data[17], self.data[18], self.data[19])
+    }
+
+    /// The IP protocol number of the payload, e.g. 17 for UDP.
+    #[inline]
+    pub fn protocol(&self) -> u8 {
+        self.data[9]
+    }
+
+    /// The time to live.
+    #[inline]
+    pub fn ttl(&self) -> u8 {
+        self.data[8]
+    }
+
+    /// The length of the header, including any options.
+    #[inline]
+    pub fn header_len(&self) -> usize {
+        self.hdr_len
+    }
+
+    /// The length of the packet, header included.
+    #[inline]
+    pub fn total_len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The fragment offset, in units of eight bytes. Zero for
+    /// unfragmented packets and initial fragments.
+    #[inline]
+    pub fn fragment_offset(&self) -> u16 {
+        u16::from_be_bytes([self.data[6], self.data[7]]) & 0x1fff
+    }
+
+    /// The bytes following the header, up to the total length.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        &self.data[self.hdr_len..]
+    }
+}
+
+/// A view over a UDP header and the datagram it heads.
+#[derive(Debug, Clone, Copy)]
+pub struct UdpHdr<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> UdpHdr<'a> {
+    /// Parses the UDP header at the start of `data`, e.g. the
+    /// [`payload`](Ipv4Hdr::payload) of an [`Ipv4Hdr`].
+    ///
+    /// Returns `None` if the length field is smaller than the header
+    /// or larger than `data`.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.len() < UDP_HDR_LEN {
+            return None;
+        }
+
+        let len = u16::from_be_bytes([data[4], data[5]]) as usize;
+
+        if len < UDP_HDR_LEN || data.len() < len {
+            return None;
+        }
+
+        Some(Self { data: &data[..len] })
+    }
+
+    /// The source port.
+    #[inline]
+    pub fn src_port(&self) -> u16 {
+        u16::from_be_bytes([self.data[0], self.data[1]])
+    }
+
+    /// The destination port.
+    #[inline]
+    pub fn dst_port(&self) -> u16 {
+        u16::from_be_bytes([self.data[2], self.data[3]])
+    }
+
+    /// The length of the datagram, header included.
+    #[inline]
+    pub fn length(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The checksum, as found in the header. Not verified.
+    #[inline]
+    pub fn checksum(&self) -> u16 {
+        u16::from_be_bytes([self.data[6], self.data[7]])
+    }
+
+    /// The datagram's payload.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        &self.data[UDP_HDR_LEN..]
+    }
+}
+
+#[inline]
+fn mac(bytes: &[u8]) -> [u8; 6] {
+    let mut mac = [0; 6];
+    mac.copy_from_slice(bytes);
+    mac
+}
+
+/// The ethertype of the L3 payload and the offset at which it starts,
+/// skipping over any VLAN tags.
+fn ethertype_and_l3_offset(data: &[u8]) -> Option<(u16, usize)> {
+    if data.len() < ETH_HDR_LEN {
+        return None;
+    }
+
+    let mut ethertype = u16::from_be_bytes([data[12], data[13]]);
+    let mut offset = ETH_HDR_LEN;
+
+    for _ in 0..2 {
+        if ethertype != ETH_P_8021Q && ethertype != ETH_P_8021AD {
+            break;
+        }
+
+        if data.len() < offset + VLAN_HDR_LEN {
+            return None;
+        }
+
+        ethertype = u16::from_be_bytes([data[offset + 2], data[offset + 3]]);
+        offset += VLAN_HDR_LEN;
+    }
+
+    Some((ethertype, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eth_hdr(ethertype: u16) -> Vec<u8> {
+        let mut hdr = vec![0xff; 12];
+        hdr.extend_from_slice(&ethertype.to_be_bytes());
+        hdr
+    }
+
+    fn ipv4_pkt(src: [u8; 4], dst: [u8; 4], protocol: u8, sport: u16, dport: u16) -> Vec<u8> {
+        let mut pkt = eth_hdr(ETH_P_IPV4);
+
+        pkt.extend_from_slice(&[0x45, 0, 0, 28, 0, 0, 0, 0, 64, protocol, 0, 0]);
+        pkt.extend_from_slice(&src);
+        pkt.extend_from_slice(&dst);
+        pkt.extend_from_slice(&sport.to_be_bytes());
+        pkt.extend_from_slice(&dport.to_be_bytes());
+        pkt.extend_from_slice(&[0; 4]);
+
+        pkt
+    }
+
+    fn ipv6_pkt(src: [u8; 16], dst: [u8; 16], sport: u16, dport: u16) -> Vec<u8> {
+        let mut pkt = eth_hdr(ETH_P_IPV6);
+
+        pkt.extend_from_slice(&[0x60, 0, 0, 0, 0, 8, IPPROTO_UDP, 64]);
+        pkt.extend_from_slice(&src);
+        pkt.extend_from_slice(&dst);
+        pkt.extend_from_slice(&sport.to_be_bytes());
+        pkt.extend_from_slice(&dport.to_be_bytes());
+        pkt.extend_from_slice(&[0; 4]);
+
+        pkt
+    }
+
+    #[test]
+    fn ipv4_hash_is_symmetric() {
+        let fwd = ipv4_pkt([10, 0, 0, 1], [10, 0, 0, 2], IPPROTO_TCP, 1234, 80);
+        let rev = ipv4_pkt([10, 0, 0, 2], [10, 0, 0, 1], IPPROTO_TCP, 80, 1234);
+
+        assert_eq!(flow_hash(&fwd).unwrap(), flow_hash(&rev).unwrap());
+    }
+
+    #[test]
+    fn ipv6_hash_is_symmetric() {
+        let a = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        let b = [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+        let fwd = ipv6_pkt(a, b, 5000, 53);
+        let rev = ipv6_pkt(b, a, 53, 5000);
+
+        assert_eq!(flow_hash(&fwd).unwrap(), flow_hash(&rev).unwrap());
+    }
+
+    #[test]
+    fn different_flows_hash_differently() {
+        let fst = ipv4_pkt([10, 0, 0, 1], [10, 0, 0, 2], IPPROTO_UDP, 1234, 80);
+        let snd = ipv4_pkt([10, 0, 0, 1], [10, 0, 0, 2], IPPROTO_UDP, 1235, 80);
+        let thd = ipv4_pkt([10, 0, 0, 1], [10, 0, 0, 2], IPPROTO_TCP, 1234, 80);
+
+        assert_ne!(flow_hash(&fst).unwrap(), flow_hash(&snd).unwrap());
+        assert_ne!(flow_hash(&fst).unwrap(), flow_hash(&thd).unwrap());
+    }
+
+    #[test]
+    fn vlan_tag_is_skipped() {
+        let untagged = ipv4_pkt([10, 0, 0, 1], [10, 0, 0, 2], IPPROTO_UDP, 1234, 80);
+
+        let mut tagged = eth_hdr(ETH_P_8021Q);
+        tagged.extend_from_slice(&[0, 100]);
+        tagged.extend_from_slice(&untagged[12..]);
+
+        assert_eq!(flow_hash(&untagged).unwrap(), flow_hash(&tagged).unwrap());
+    }
+
+    #[test]
+    fn header_views_expose_udp_over_ipv4() {
+        let mut pkt = eth_hdr(ETH_P_IPV4);
+
+        pkt.extend_from_slice(&[0x45, 0, 0, 31, 0, 0, 0, 0, 64, IPPROTO_UDP, 0, 0]);
+        pkt.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        pkt.extend_from_slice(&[0x04, 0xd2, 0, 80, 0, 11, 0xab, 0xcd]);
+        pkt.extend_from_slice(b"hey");
+
+        // Ethernet padding
+        pkt.extend_from_slice(&[0; 4]);
+
+        let eth = EthHdr::parse(&pkt).unwrap();
+
+        assert_eq!(eth.dst_mac(), [0xff; 6]);
+        assert_eq!(eth.ethertype(), ETH_P_IPV4);
+
+        let ip = Ipv4Hdr::parse(eth.payload()).unwrap();
+
+        assert_eq!(ip.src(), Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(ip.dst(), Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(ip.protocol(), IPPROTO_UDP);
+        assert_eq!(ip.total_len(), 31);
+
+        let udp = UdpHdr::parse(ip.payload()).unwrap();
+
+        assert_eq!(udp.src_port(), 1234);
+        assert_eq!(udp.dst_port(), 80);
+        assert_eq!(udp.checksum(), 0xabcd);
+        assert_eq!(udp.payload(), b"hey");
+
+        // Total length claims more than is present
+        pkt[ETH_HDR_LEN + 3] = 200;
+
+        assert!(Ipv4Hdr::parse(EthHdr::parse(&pkt).unwrap().payload()).is_none());
+    }
+
+    #[test]
+    fn non_ip_or_truncated_frames_have_no_hash() {
+        let mut arp = eth_hdr(0x0806);
+        arp.extend_from_slice(&[0; 28]);
+
+        assert_eq!(flow_hash(&arp), None);
+
+        let pkt = ipv4_pkt([10, 0, 0, 1], [10, 0, 0, 2], IPPROTO_UDP, 1234, 80);
+
+        assert_eq!(flow_hash(&pkt[..ETH_HDR_LEN + 10]), None);
+        assert_eq!(flow_hash(&pkt[..10]), None);
+    }
+}
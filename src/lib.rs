@@ -23,6 +23,33 @@
 //! - Do not use one [`Umem`]'s frame descriptors to access frames of
 //!   another, different [`Umem`]. For example, via [`Umem::frame`].
 //!
+//! ### Memory ordering
+//!
+//! Producing to and consuming from the rings goes through libxdp,
+//! whose ring accessors already carry the barriers needed to share
+//! frames with the kernel, on weakly ordered architectures such as
+//! aarch64 as well as x86:
+//! - A producer's position is published with a store-release, so
+//!   anything written to a frame (packet data, headroom or metadata)
+//!   and its descriptor before calling, e.g., [`TxQueue::produce`] or
+//!   [`FillQueue::produce`] is visible to the kernel by the time it
+//!   sees the new entries.
+//! - A producer's position is read with a load-acquire, so anything
+//!   the kernel wrote to a frame before placing it on the rx or
+//!   completion ring is visible once it's been returned by, e.g.,
+//!   [`RxQueue::consume`] or [`CompQueue::consume`].
+//! - A consumer's position is only advanced, with a store-release,
+//!   once the entries have been read, so the kernel can't reuse a
+//!   ring slot while it's still being read from.
+//!
+//! No extra fences are needed around these calls. The same pairing
+//! also applies to any of your own accesses to frame memory, atomic
+//! or otherwise, made on the thread which produces or consumes: write
+//! before producing, read after consuming. Handing a frame to another
+//! thread is another matter, and needs its own synchronisation, e.g.
+//! a channel or a release/acquire pair, just as any other shared
+//! memory would.
+//!
 //! ### Usage
 //!
 //! The below example sends a packet from one interface to another.
@@ -126,7 +153,11 @@ cfg_if! {
 
         pub mod config;
 
+        pub mod frame_utils;
+
         mod ring;
+        #[cfg(feature = "ring-stats")]
+        pub use ring::RingStats;
         mod util;
 
         #[cfg(test)]
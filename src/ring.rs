@@ -1,71 +1,384 @@
-use std::ptr;
+#[cfg(feature = "ring-stats")]
+use std::sync::atomic::AtomicU64;
+use std::{
+    ptr,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use libxdp_sys::{xsk_ring_cons, xsk_ring_prod};
 
+/// Userspace side counters for one of a socket's rings, recorded on
+/// each attempt to reserve space on (for producer rings) or peek
+/// entries from (for consumer rings) the ring.
+///
+/// A producer ring with many short or empty reservations is waiting
+/// on the kernel to consume entries, whereas a consumer ring with
+/// many short or empty peeks is waiting on the kernel to produce
+/// them. Comparing these across the rings shows where in the pipeline
+/// frames are stalling.
+///
+/// Only available with the `ring-stats` feature enabled.
+#[cfg(feature = "ring-stats")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RingStats {
+    requests: u64,
+    short: u64,
+    empty: u64,
+    wraps: u64,
+}
+
+#[cfg(feature = "ring-stats")]
+impl RingStats {
+    /// Total number of attempts to reserve or peek a non-zero number
+    /// of entries.
+    #[inline]
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    /// Number of attempts which got some, but fewer than requested,
+    /// entries.
+    #[inline]
+    pub fn short(&self) -> u64 {
+        self.short
+    }
+
+    /// Number of attempts which got no entries at all.
+    #[inline]
+    pub fn empty(&self) -> u64 {
+        self.empty
+    }
+
+    /// Number of attempts whose entries wrapped around the end of the
+    /// ring.
+    #[inline]
+    pub fn wraps(&self) -> u64 {
+        self.wraps
+    }
+
+    #[inline]
+    fn record(&mut self, size: u32, idx: u32, requested: u32, got: u32) {
+        self.requests += 1;
+
+        if got == 0 {
+            self.empty += 1;
+        } else if got < requested {
+            self.short += 1;
+        }
+
+        if got > 0 && (idx & (size - 1)) + got > size {
+            self.wraps += 1;
+        }
+    }
+}
+
+/// Weight given to the newest sample by [`FramesPerCall`].
+#[cfg(feature = "ring-stats")]
+const FRAMES_PER_CALL_WEIGHT: f64 = 0.125;
+
+/// An exponentially weighted moving average of the number of entries
+/// a ring handled between consecutive syscalls on it, e.g. wakeups or
+/// polls.
+///
+/// Atomic so that calls may be recorded through a shared reference,
+/// as some of the wakeups take one.
+#[cfg(feature = "ring-stats")]
+#[derive(Debug, Default)]
+struct FramesPerCall {
+    pending: AtomicU64,
+    calls: AtomicU64,
+    avg_bits: AtomicU64,
+}
+
+#[cfg(feature = "ring-stats")]
+impl FramesPerCall {
+    #[inline]
+    fn add_frames(&self, n: u32) {
+        self.pending.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Fold the entries handled since the last call into the average.
+    #[inline]
+    fn record_call(&self) {
+        let n = self.pending.swap(0, Ordering::Relaxed) as f64;
+
+        let avg = if self.calls.fetch_add(1, Ordering::Relaxed) == 0 {
+            n
+        } else {
+            let avg = f64::from_bits(self.avg_bits.load(Ordering::Relaxed));
+
+            avg + FRAMES_PER_CALL_WEIGHT * (n - avg)
+        };
+
+        self.avg_bits.store(avg.to_bits(), Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn get(&self) -> f64 {
+        f64::from_bits(self.avg_bits.load(Ordering::Relaxed))
+    }
+}
+
 #[derive(Debug)]
-pub struct XskRingCons(xsk_ring_cons);
+pub struct XskRingCons {
+    ring: xsk_ring_cons,
+    #[cfg(feature = "ring-stats")]
+    stats: RingStats,
+    #[cfg(feature = "ring-stats")]
+    per_call: FramesPerCall,
+}
 
 impl XskRingCons {
     pub fn as_mut(&mut self) -> &mut xsk_ring_cons {
-        &mut self.0
+        &mut self.ring
     }
 
     pub fn as_ref(&self) -> &xsk_ring_cons {
-        &self.0
+        &self.ring
     }
 
     pub fn is_ring_null(&self) -> bool {
-        self.0.ring.is_null()
+        self.ring.ring.is_null()
+    }
+
+    /// The ring's shared flags word. See the queues' `ring_flags`
+    /// methods for the bits currently defined.
+    #[inline]
+    pub fn flags(&self) -> u32 {
+        if self.ring.flags.is_null() {
+            0
+        } else {
+            // SAFETY: the pointer is non-null, so the ring has been
+            // initialised and `flags` points into its mapped memory.
+            unsafe { ptr::read_volatile(self.ring.flags) }
+        }
+    }
+
+    /// The number of entries produced by the kernel but not yet
+    /// peeked, from a fresh read of its producer position.
+    #[inline]
+    pub fn depth(&self) -> u32 {
+        let r = &self.ring;
+
+        if r.producer.is_null() {
+            return 0;
+        }
+
+        // SAFETY: the pointer is non-null, so the ring has been
+        // initialised and `producer` points into its mapped memory.
+        let producer = unsafe { (*(r.producer as *const AtomicU32)).load(Ordering::Acquire) };
+
+        producer.wrapping_sub(r.cached_cons)
+    }
+
+    /// Record the outcome of a peek which starting at `idx` returned
+    /// `got` out of the `requested` entries. A no-op unless the
+    /// `ring-stats` feature is enabled.
+    #[inline]
+    pub fn record_peek(&mut self, idx: u32, requested: u32, got: u32) {
+        #[cfg(feature = "ring-stats")]
+        {
+            self.stats.record(self.ring.size, idx, requested, got);
+            self.per_call.add_frames(got);
+        }
+
+        #[cfg(not(feature = "ring-stats"))]
+        let _ = (idx, requested, got);
+    }
+
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn stats(&self) -> RingStats {
+        self.stats
+    }
+
+    /// Record a syscall made on the ring's behalf, e.g. a wakeup.
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn record_call(&self) {
+        self.per_call.record_call()
+    }
+
+    /// The moving average of entries handled per call recorded.
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn frames_per_call(&self) -> f64 {
+        self.per_call.get()
     }
 }
 
 impl Default for XskRingCons {
     fn default() -> Self {
-        Self(xsk_ring_cons {
-            cached_prod: 0,
-            cached_cons: 0,
-            mask: 0,
-            size: 0,
-            producer: ptr::null_mut(),
-            consumer: ptr::null_mut(),
-            ring: ptr::null_mut(),
-            flags: ptr::null_mut(),
-        })
+        Self {
+            ring: xsk_ring_cons {
+                cached_prod: 0,
+                cached_cons: 0,
+                mask: 0,
+                size: 0,
+                producer: ptr::null_mut(),
+                consumer: ptr::null_mut(),
+                ring: ptr::null_mut(),
+                flags: ptr::null_mut(),
+            },
+            #[cfg(feature = "ring-stats")]
+            stats: RingStats::default(),
+            #[cfg(feature = "ring-stats")]
+            per_call: FramesPerCall::default(),
+        }
     }
 }
 
 unsafe impl Send for XskRingCons {}
 
 #[derive(Debug)]
-pub struct XskRingProd(xsk_ring_prod);
+pub struct XskRingProd {
+    ring: xsk_ring_prod,
+    #[cfg(feature = "ring-stats")]
+    stats: RingStats,
+    #[cfg(feature = "ring-stats")]
+    per_call: FramesPerCall,
+}
 
 impl XskRingProd {
     pub fn as_mut(&mut self) -> &mut xsk_ring_prod {
-        &mut self.0
+        &mut self.ring
     }
 
     pub fn as_ref(&self) -> &xsk_ring_prod {
-        &self.0
+        &self.ring
     }
 
     pub fn is_ring_null(&self) -> bool {
-        self.0.ring.is_null()
+        self.ring.ring.is_null()
+    }
+
+    /// The ring's shared flags word. See the queues' `ring_flags`
+    /// methods for the bits currently defined.
+    #[inline]
+    pub fn flags(&self) -> u32 {
+        if self.ring.flags.is_null() {
+            0
+        } else {
+            // SAFETY: the pointer is non-null, so the ring has been
+            // initialised and `flags` points into its mapped memory.
+            unsafe { ptr::read_volatile(self.ring.flags) }
+        }
+    }
+
+    /// The number of entries free to be reserved. As with libxdp's
+    /// `xsk_prod_nb_free`, the kernel's consumer position is only
+    /// re-read if fewer than `nb` are known to be free already.
+    #[inline]
+    pub fn nb_free(&mut self, nb: u32) -> u32 {
+        let r = &mut self.ring;
+
+        let free = r.cached_cons.wrapping_sub(r.cached_prod);
+
+        if free >= nb || r.consumer.is_null() {
+            return free;
+        }
+
+        // SAFETY: the pointer is non-null, so the ring has been
+        // initialised and `consumer` points into its mapped memory.
+        // The acquire pairs with the kernel's release once it's done
+        // reading the entries it has consumed.
+        let consumer = unsafe { (*(r.consumer as *const AtomicU32)).load(Ordering::Acquire) };
+
+        r.cached_cons = consumer.wrapping_add(r.size);
+
+        r.cached_cons.wrapping_sub(r.cached_prod)
+    }
+
+    /// Record the outcome of a reservation which starting at `idx`
+    /// returned `got` out of the `requested` entries. A no-op unless
+    /// the `ring-stats` feature is enabled.
+    #[inline]
+    pub fn record_reserve(&mut self, idx: u32, requested: u32, got: u32) {
+        #[cfg(feature = "ring-stats")]
+        {
+            self.stats.record(self.ring.size, idx, requested, got);
+            self.per_call.add_frames(got);
+        }
+
+        #[cfg(not(feature = "ring-stats"))]
+        let _ = (idx, requested, got);
+    }
+
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn stats(&self) -> RingStats {
+        self.stats
+    }
+
+    /// Record a syscall made on the ring's behalf, e.g. a wakeup.
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn record_call(&self) {
+        self.per_call.record_call()
+    }
+
+    /// The moving average of entries handled per call recorded.
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn frames_per_call(&self) -> f64 {
+        self.per_call.get()
     }
 }
 
 impl Default for XskRingProd {
     fn default() -> Self {
-        Self(xsk_ring_prod {
-            cached_prod: 0,
-            cached_cons: 0,
-            mask: 0,
-            size: 0,
-            producer: ptr::null_mut(),
-            consumer: ptr::null_mut(),
-            ring: ptr::null_mut(),
-            flags: ptr::null_mut(),
-        })
+        Self {
+            ring: xsk_ring_prod {
+                cached_prod: 0,
+                cached_cons: 0,
+                mask: 0,
+                size: 0,
+                producer: ptr::null_mut(),
+                consumer: ptr::null_mut(),
+                ring: ptr::null_mut(),
+                flags: ptr::null_mut(),
+            },
+            #[cfg(feature = "ring-stats")]
+            stats: RingStats::default(),
+            #[cfg(feature = "ring-stats")]
+            per_call: FramesPerCall::default(),
+        }
     }
 }
 
 unsafe impl Send for XskRingProd {}
+
+#[cfg(all(test, feature = "ring-stats"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_distinguish_short_empty_and_wrapping_requests() {
+        let mut stats = RingStats::default();
+
+        stats.record(4, 0, 2, 2);
+        stats.record(4, 2, 4, 2);
+        stats.record(4, 4, 1, 0);
+        stats.record(4, 7, 2, 2);
+
+        assert_eq!(stats.requests(), 4);
+        assert_eq!(stats.short(), 1);
+        assert_eq!(stats.empty(), 1);
+        assert_eq!(stats.wraps(), 1);
+    }
+
+    #[test]
+    fn frames_per_call_starts_from_first_sample_and_decays() {
+        let per_call = FramesPerCall::default();
+
+        per_call.add_frames(8);
+        per_call.add_frames(8);
+        per_call.record_call();
+
+        assert_eq!(per_call.get(), 16.0);
+
+        per_call.record_call();
+
+        assert_eq!(per_call.get(), 14.0);
+    }
+}
@@ -0,0 +1,54 @@
+//! Picking a starting point for batch sizes.
+
+/// The largest batch [`suggest_batch_size`] will recommend.
+///
+/// Processing a batch touches each frame's descriptor and at least the
+/// first couple of cache lines of its packet, so at this size a batch
+/// is a few tens of KiB and fits comfortably in a typical L2 cache
+/// alongside everything else the loop needs.
+const MAX_SUGGESTED_BATCH_SIZE: u32 = 256;
+
+/// A suggested number of frames to transmit or receive per call, for
+/// AF_XDP rings of `ring_size` entries in front of a NIC ring of
+/// `nic_ring_size` descriptors, e.g. as reported by
+/// [`nic_ring_params`](super::nic_ring_params).
+///
+/// This is a heuristic, giving a sane starting point to tune from
+/// rather than any guarantee of best performance. The batch is a
+/// quarter of the smaller of the two rings, so a ring can still be
+/// refilled while several batches are in flight, rounded down to a
+/// power of two and capped so that its working set stays cache
+/// resident. Pass zero for `nic_ring_size` if it isn't known, in which
+/// case only `ring_size` is considered. The result is always at least
+/// one.
+pub fn suggest_batch_size(ring_size: u32, nic_ring_size: u32) -> usize {
+    let smallest = if nic_ring_size == 0 {
+        ring_size
+    } else {
+        ring_size.min(nic_ring_size)
+    };
+
+    let quarter = (smallest / 4).clamp(1, MAX_SUGGESTED_BATCH_SIZE);
+
+    // Round down to a power of two
+    (1u32 << (31 - quarter.leading_zeros())) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_is_quarter_of_smaller_ring_rounded_down() {
+        assert_eq!(suggest_batch_size(2048, 512), 128);
+        assert_eq!(suggest_batch_size(256, 1000), 64);
+        assert_eq!(suggest_batch_size(1000, 0), 128);
+    }
+
+    #[test]
+    fn batch_is_clamped() {
+        assert_eq!(suggest_batch_size(8192, 8192), 256);
+        assert_eq!(suggest_batch_size(2, 4096), 1);
+        assert_eq!(suggest_batch_size(0, 0), 1);
+    }
+}
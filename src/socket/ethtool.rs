@@ -0,0 +1,524 @@
+//! Reading driver statistics and ring settings via the `SIOCETHTOOL`
+//! ioctl, as defined in the linux source at
+//! `include/uapi/linux/ethtool.h`.
+
+use libc::{AF_INET, EOPNOTSUPP, EPERM, IFNAMSIZ, SOCK_CLOEXEC, SOCK_DGRAM};
+use std::{borrow::Cow, collections::HashMap, ffi::CStr, io, mem, os::unix::prelude::RawFd};
+
+const SIOCETHTOOL: libc::c_ulong = 0x8946;
+
+const ETHTOOL_GRINGPARAM: u32 = 0x10;
+const ETHTOOL_SRINGPARAM: u32 = 0x11;
+const ETHTOOL_GSTRINGS: u32 = 0x1b;
+const ETHTOOL_GSTATS: u32 = 0x1d;
+const ETHTOOL_GSSET_INFO: u32 = 0x37;
+
+const ETH_SS_STATS: u32 = 1;
+const ETH_GSTRING_LEN: usize = 32;
+
+/// Driver level XDP counters for the interface with index `if_index`,
+/// as reported by `ethtool -S`, keyed by counter name.
+///
+/// Complements the socket level [`XdpStatistics`]: counters here
+/// include packets dropped, redirected or transmitted by the driver
+/// before they reach any socket's rings, which helps narrow down where
+/// packets are going missing. Names and availability vary by driver,
+/// so rather than interpreting them this returns every counter whose
+/// name contains `xdp` (e.g. `rx_xdp_drop`, `xdp_redirect` or
+/// `rx_queue_0_xdp_packets`), leaving it to the caller to pick out the
+/// ones their driver reports. The map is empty if the driver reports
+/// no such counters.
+///
+/// [`XdpStatistics`]: super::XdpStatistics
+pub fn driver_xdp_stats(if_index: u32) -> io::Result<HashMap<String, u64>> {
+    let (names, values) = driver_stats(if_index)?;
+
+    Ok(xdp_counters(&names, &values))
+}
+
+/// Packet and byte counters for a single queue of an interface, as
+/// reported by its driver.
+///
+/// Each counter is [`None`] if the driver doesn't report it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStats {
+    rx_packets: Option<u64>,
+    rx_bytes: Option<u64>,
+    tx_packets: Option<u64>,
+    tx_bytes: Option<u64>,
+}
+
+impl QueueStats {
+    /// Packets received on the queue.
+    #[inline]
+    pub fn rx_packets(&self) -> Option<u64> {
+        self.rx_packets
+    }
+
+    /// Bytes received on the queue.
+    #[inline]
+    pub fn rx_bytes(&self) -> Option<u64> {
+        self.rx_bytes
+    }
+
+    /// Packets transmitted on the queue.
+    #[inline]
+    pub fn tx_packets(&self) -> Option<u64> {
+        self.tx_packets
+    }
+
+    /// Bytes transmitted on the queue.
+    #[inline]
+    pub fn tx_bytes(&self) -> Option<u64> {
+        self.tx_bytes
+    }
+}
+
+/// Per queue packet and byte counters for queue `queue_id` of the
+/// interface with index `if_index`, as reported by `ethtool -S`.
+///
+/// Comparing these with what a socket bound to the queue has
+/// processed shows how much of the queue's traffic is actually
+/// reaching it. There's no standard naming for per queue counters, so
+/// this looks for the common conventions (`rx_queue_0_packets`,
+/// `rx-0.packets` and `rx0_packets`) and leaves any counter it can't
+/// find as [`None`]. Depending on the driver, counts may include
+/// packets handled by XDP or only those passed up to the stack.
+pub fn queue_stats(if_index: u32, queue_id: u32) -> io::Result<QueueStats> {
+    let (names, values) = driver_stats(if_index)?;
+
+    Ok(queue_counters(&names, &values, queue_id))
+}
+
+/// The sizes of an interface's hardware descriptor rings, as reported
+/// by `ethtool -g`.
+///
+/// These are the rings between the NIC and its driver, separate from
+/// (and sitting in front of) a socket's AF_XDP rings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NicRingParams {
+    rx_max_pending: u32,
+    tx_max_pending: u32,
+    rx_pending: u32,
+    tx_pending: u32,
+}
+
+impl NicRingParams {
+    /// The largest size the rx ring supports.
+    #[inline]
+    pub fn rx_max_pending(&self) -> u32 {
+        self.rx_max_pending
+    }
+
+    /// The largest size the tx ring supports.
+    #[inline]
+    pub fn tx_max_pending(&self) -> u32 {
+        self.tx_max_pending
+    }
+
+    /// The current size of the rx ring.
+    #[inline]
+    pub fn rx_pending(&self) -> u32 {
+        self.rx_pending
+    }
+
+    /// The current size of the tx ring.
+    #[inline]
+    pub fn tx_pending(&self) -> u32 {
+        self.tx_pending
+    }
+}
+
+/// The `ethtool_ringparam` struct.
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Default)]
+struct RingParam {
+    cmd: u32,
+    rx_max_pending: u32,
+    rx_mini_max_pending: u32,
+    rx_jumbo_max_pending: u32,
+    tx_max_pending: u32,
+    rx_pending: u32,
+    rx_mini_pending: u32,
+    rx_jumbo_pending: u32,
+    tx_pending: u32,
+}
+
+/// The hardware rx and tx ring sizes of the interface with index
+/// `if_index`, along with the largest sizes it supports.
+///
+/// If these rings are too small, packets can be dropped by the NIC
+/// before XDP ever sees them, no matter how large the socket's own
+/// rings are. Drivers which don't expose their rings, e.g. `veth` on
+/// older kernels, return an [`Unsupported`](io::ErrorKind::Unsupported)
+/// error.
+pub fn nic_ring_params(if_index: u32) -> io::Result<NicRingParams> {
+    let if_name = if_name(if_index)?;
+
+    let param = IoctlSocket::new()?.ring_param(&if_name)?;
+
+    Ok(NicRingParams {
+        rx_max_pending: param.rx_max_pending,
+        tx_max_pending: param.tx_max_pending,
+        rx_pending: param.rx_pending,
+        tx_pending: param.tx_pending,
+    })
+}
+
+/// Resize the hardware rx and tx rings of the interface with index
+/// `if_index`, as with `ethtool -G`.
+///
+/// The sizes must be no larger than those reported by
+/// [`nic_ring_params`], and the driver may round them. Drivers which
+/// can report their rings but not resize them return an
+/// [`Unsupported`](io::ErrorKind::Unsupported) error. Any other ring
+/// settings are left as they are. Requires `CAP_NET_ADMIN`, and since
+/// many drivers reset the device to apply the change, the link may go
+/// down briefly, so call this before creating any sockets on the
+/// interface.
+pub fn set_nic_ring_params(if_index: u32, rx_pending: u32, tx_pending: u32) -> io::Result<()> {
+    let if_name = if_name(if_index)?;
+
+    let sock = IoctlSocket::new()?;
+
+    let mut param = sock.ring_param(&if_name)?;
+
+    param.cmd = ETHTOOL_SRINGPARAM;
+    param.rx_pending = rx_pending;
+    param.tx_pending = tx_pending;
+
+    sock.ethtool(&if_name, &mut param as *mut _ as *mut libc::c_void)
+        .map_err(|err| {
+            if err.raw_os_error() == Some(EPERM) {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "setting NIC ring parameters requires CAP_NET_ADMIN",
+                )
+            } else if err.raw_os_error() == Some(EOPNOTSUPP) {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "driver doesn't support resizing its rings",
+                )
+            } else {
+                err
+            }
+        })
+}
+
+/// The name of the interface with index `if_index`.
+pub(super) fn if_name(if_index: u32) -> io::Result<[libc::c_char; IFNAMSIZ]> {
+    let mut if_name = [0 as libc::c_char; IFNAMSIZ];
+
+    if unsafe { libc::if_indextoname(if_index, if_name.as_mut_ptr()) }.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(if_name)
+}
+
+/// The names and values of all the driver's statistics for the
+/// interface with index `if_index`.
+fn driver_stats(if_index: u32) -> io::Result<(Vec<u8>, Vec<u64>)> {
+    let if_name = if_name(if_index)?;
+
+    let sock = IoctlSocket::new()?;
+
+    let count = sock.stats_count(&if_name)?;
+
+    if count == 0 {
+        return Ok((Vec::new(), Vec::new()));
+    }
+
+    let names = sock.stats_names(&if_name, count)?;
+    let values = sock.stats_values(&if_name, count)?;
+
+    Ok((names, values))
+}
+
+/// A socket to issue ioctls against, closed on drop.
+#[derive(Debug)]
+pub(super) struct IoctlSocket(RawFd);
+
+impl IoctlSocket {
+    pub(super) fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::socket(AF_INET, SOCK_DGRAM | SOCK_CLOEXEC, 0) };
+
+        if fd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Self(fd))
+        }
+    }
+
+    /// The number of statistics the driver reports.
+    fn stats_count(&self, if_name: &[libc::c_char; IFNAMSIZ]) -> io::Result<usize> {
+        #[repr(C)]
+        #[allow(dead_code)]
+        struct SsetInfo {
+            cmd: u32,
+            reserved: u32,
+            sset_mask: u64,
+            data: [u32; 1],
+        }
+
+        let mut info = SsetInfo {
+            cmd: ETHTOOL_GSSET_INFO,
+            reserved: 0,
+            sset_mask: 1 << ETH_SS_STATS,
+            data: [0],
+        };
+
+        self.ethtool(if_name, &mut info as *mut _ as *mut libc::c_void)?;
+
+        // The kernel clears the bit if the driver has no statistics
+        if info.sset_mask & (1 << ETH_SS_STATS) == 0 {
+            Ok(0)
+        } else {
+            Ok(info.data[0] as usize)
+        }
+    }
+
+    /// The interface's `IFF_*` flags, via `SIOCGIFFLAGS`.
+    pub(super) fn if_flags(&self, if_name: &[libc::c_char; IFNAMSIZ]) -> io::Result<libc::c_int> {
+        let mut ifr: libc::ifreq = unsafe { mem::zeroed() };
+
+        ifr.ifr_name = *if_name;
+
+        let err = unsafe { libc::ioctl(self.0, libc::SIOCGIFFLAGS as _, &mut ifr) };
+
+        if err < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            // The flags are a `short`, so widen without sign extending
+            // to compare against the `IFF_*` constants.
+            Ok(unsafe { ifr.ifr_ifru.ifru_flags } as u16 as libc::c_int)
+        }
+    }
+
+    /// Set the interface's `IFF_*` flags, via `SIOCSIFFLAGS`.
+    pub(super) fn set_if_flags(
+        &self,
+        if_name: &[libc::c_char; IFNAMSIZ],
+        flags: libc::c_int,
+    ) -> io::Result<()> {
+        let mut ifr: libc::ifreq = unsafe { mem::zeroed() };
+
+        ifr.ifr_name = *if_name;
+        ifr.ifr_ifru.ifru_flags = flags as libc::c_short;
+
+        let err = unsafe { libc::ioctl(self.0, libc::SIOCSIFFLAGS as _, &mut ifr) };
+
+        if err < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The driver's current ring settings.
+    fn ring_param(&self, if_name: &[libc::c_char; IFNAMSIZ]) -> io::Result<RingParam> {
+        let mut param = RingParam {
+            cmd: ETHTOOL_GRINGPARAM,
+            ..RingParam::default()
+        };
+
+        self.ethtool(if_name, &mut param as *mut _ as *mut libc::c_void)
+            .map_err(|err| {
+                if err.raw_os_error() == Some(EOPNOTSUPP) {
+                    io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "driver doesn't report its ring parameters",
+                    )
+                } else {
+                    err
+                }
+            })?;
+
+        Ok(param)
+    }
+
+    /// The names of the driver's statistics, each a nul padded string
+    /// of [`ETH_GSTRING_LEN`] bytes.
+    fn stats_names(&self, if_name: &[libc::c_char; IFNAMSIZ], count: usize) -> io::Result<Vec<u8>> {
+        const HDR_LEN: usize = 3;
+
+        // Layout is `cmd`, `string_set` and `len`, followed by the
+        // strings themselves. Use `u32`s to get the right alignment.
+        let mut buf = vec![0u32; HDR_LEN + count * ETH_GSTRING_LEN / mem::size_of::<u32>()];
+
+        buf[0] = ETHTOOL_GSTRINGS;
+        buf[1] = ETH_SS_STATS;
+        buf[2] = count as u32;
+
+        self.ethtool(if_name, buf.as_mut_ptr() as *mut libc::c_void)?;
+
+        let len = std::cmp::min(buf[2] as usize, count);
+
+        Ok(buf[HDR_LEN..]
+            .iter()
+            .flat_map(|word| word.to_ne_bytes())
+            .take(len * ETH_GSTRING_LEN)
+            .collect())
+    }
+
+    /// The values of the driver's statistics, in the same order as
+    /// their names.
+    fn stats_values(
+        &self,
+        if_name: &[libc::c_char; IFNAMSIZ],
+        count: usize,
+    ) -> io::Result<Vec<u64>> {
+        // Layout is `cmd` and `n_stats`, followed by the values.
+        let mut buf = vec![0u64; 1 + count];
+
+        {
+            let hdr = buf.as_mut_ptr() as *mut u32;
+
+            // SAFETY: the first `u64` is large enough to hold both
+            // header fields.
+            unsafe {
+                *hdr = ETHTOOL_GSTATS;
+                *hdr.add(1) = count as u32;
+            }
+        }
+
+        self.ethtool(if_name, buf.as_mut_ptr() as *mut libc::c_void)?;
+
+        let n_stats = unsafe { *(buf.as_ptr() as *const u32).add(1) } as usize;
+
+        buf.remove(0);
+        buf.truncate(std::cmp::min(n_stats, count));
+
+        Ok(buf)
+    }
+
+    fn ethtool(
+        &self,
+        if_name: &[libc::c_char; IFNAMSIZ],
+        data: *mut libc::c_void,
+    ) -> io::Result<()> {
+        let mut ifr: libc::ifreq = unsafe { mem::zeroed() };
+
+        ifr.ifr_name = *if_name;
+        ifr.ifr_ifru.ifru_data = data as *mut libc::c_char;
+
+        let err = unsafe { libc::ioctl(self.0, SIOCETHTOOL as _, &mut ifr) };
+
+        if err < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for IoctlSocket {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+/// Pair up the counter `names` with their `values`.
+fn counters<'a>(
+    names: &'a [u8],
+    values: &'a [u64],
+) -> impl Iterator<Item = (Cow<'a, str>, u64)> + 'a {
+    names
+        .chunks(ETH_GSTRING_LEN)
+        .zip(values.iter())
+        .map(|(name, value)| {
+            let name = match CStr::from_bytes_until_nul(name) {
+                Ok(name) => name.to_string_lossy(),
+                // Not nul terminated, so use the whole thing
+                Err(_) => String::from_utf8_lossy(name),
+            };
+
+            (name, *value)
+        })
+}
+
+/// Pair up the counter `names` with their `values`, keeping only
+/// those which look XDP related.
+fn xdp_counters(names: &[u8], values: &[u64]) -> HashMap<String, u64> {
+    counters(names, values)
+        .filter(|(name, _)| name.to_ascii_lowercase().contains("xdp"))
+        .map(|(name, value)| (name.into_owned(), value))
+        .collect()
+}
+
+/// Pick out the packet and byte counters for `queue_id` from the
+/// counter `names` and their `values`.
+fn queue_counters(names: &[u8], values: &[u64], queue_id: u32) -> QueueStats {
+    let matches = |name: &str, dir: &str, unit: &str| {
+        name == format!("{}_queue_{}_{}", dir, queue_id, unit)
+            || name == format!("{}-{}.{}", dir, queue_id, unit)
+            || name == format!("{}{}_{}", dir, queue_id, unit)
+    };
+
+    let mut stats = QueueStats::default();
+
+    for (name, value) in counters(names, values) {
+        let field = if matches(&name, "rx", "packets") {
+            &mut stats.rx_packets
+        } else if matches(&name, "rx", "bytes") {
+            &mut stats.rx_bytes
+        } else if matches(&name, "tx", "packets") {
+            &mut stats.tx_packets
+        } else if matches(&name, "tx", "bytes") {
+            &mut stats.tx_bytes
+        } else {
+            continue;
+        };
+
+        field.get_or_insert(value);
+    }
+
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gstring(name: &str) -> [u8; ETH_GSTRING_LEN] {
+        let mut buf = [0; ETH_GSTRING_LEN];
+        buf[..name.len()].copy_from_slice(name.as_bytes());
+        buf
+    }
+
+    #[test]
+    fn only_xdp_counters_are_kept() {
+        let names: Vec<u8> = ["rx_packets", "rx_xdp_drop", "XDP_redirect", "tx_bytes"]
+            .iter()
+            .flat_map(|name| gstring(name))
+            .collect();
+
+        let counters = xdp_counters(&names, &[1, 2, 3, 4]);
+
+        assert_eq!(counters.len(), 2);
+        assert_eq!(counters["rx_xdp_drop"], 2);
+        assert_eq!(counters["XDP_redirect"], 3);
+    }
+
+    #[test]
+    fn queue_counters_match_common_naming_conventions() {
+        let names: Vec<u8> = [
+            "rx_queue_1_packets",
+            "rx_queue_11_packets",
+            "rx-1.bytes",
+            "tx1_packets",
+            "tx_queue_0_bytes",
+        ]
+        .iter()
+        .flat_map(|name| gstring(name))
+        .collect();
+
+        let stats = queue_counters(&names, &[1, 2, 3, 4, 5], 1);
+
+        assert_eq!(stats.rx_packets(), Some(1));
+        assert_eq!(stats.rx_bytes(), Some(3));
+        assert_eq!(stats.tx_packets(), Some(4));
+        assert_eq!(stats.tx_bytes(), None);
+    }
+}
@@ -0,0 +1,116 @@
+//! Fairly servicing several [`RxQueue`]s from a single thread.
+
+use std::num::NonZeroUsize;
+
+use crate::{umem::frame::FrameDesc, util};
+
+use super::RxQueue;
+
+/// Services multiple [`RxQueue`]s in round-robin order, consuming at
+/// most a fixed budget of frames from each per round so that a busy
+/// queue can't starve the others.
+///
+/// Each round starts from the queue after the one which started the
+/// previous round, so no queue is consistently favoured by virtue of
+/// its position.
+#[derive(Debug)]
+pub struct FairScheduler {
+    queues: Vec<RxQueue>,
+    budget: usize,
+    next: usize,
+    consumed: Vec<usize>,
+}
+
+impl FairScheduler {
+    /// Creates a new `FairScheduler` over `queues`, consuming at most
+    /// `budget` frames from each per round.
+    pub fn new(queues: Vec<RxQueue>, budget: NonZeroUsize) -> Self {
+        let consumed = vec![0; queues.len()];
+
+        Self {
+            queues,
+            budget: budget.get(),
+            next: 0,
+            consumed,
+        }
+    }
+
+    /// The maximum number of frames consumed from each queue per
+    /// round.
+    #[inline]
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Set the maximum number of frames consumed from each queue per
+    /// round.
+    #[inline]
+    pub fn set_budget(&mut self, budget: NonZeroUsize) {
+        self.budget = budget.get();
+    }
+
+    /// The queues being serviced, in the order they were provided.
+    #[inline]
+    pub fn queues(&self) -> &[RxQueue] {
+        &self.queues
+    }
+
+    /// Mutable access to the queues being serviced, e.g. to poll
+    /// their file descriptors.
+    #[inline]
+    pub fn queues_mut(&mut self) -> &mut [RxQueue] {
+        &mut self.queues
+    }
+
+    /// Consumes the scheduler, returning the queues.
+    pub fn into_queues(self) -> Vec<RxQueue> {
+        self.queues
+    }
+
+    /// Run a single round, consuming up to the budget (or the length
+    /// of `descs`, if smaller) from each queue in turn and passing
+    /// any consumed frames to `f` along with the index of the queue
+    /// they came from.
+    ///
+    /// Returns the number of frames consumed from each queue this
+    /// round, indexed in the order the queues were provided.
+    ///
+    /// # Safety
+    ///
+    /// See [`RxQueue::consume`]. Additionally, if the queues don't
+    /// share a [`Umem`] then `f` must take care to use each batch
+    /// with the correct one.
+    ///
+    /// [`Umem`]: crate::Umem
+    pub unsafe fn run_round<F>(&mut self, descs: &mut [FrameDesc], mut f: F) -> &[usize]
+    where
+        F: FnMut(usize, &mut [FrameDesc]),
+    {
+        let nb_queues = self.queues.len();
+
+        self.consumed.iter_mut().for_each(|cnt| *cnt = 0);
+
+        if nb_queues == 0 {
+            return &self.consumed;
+        }
+
+        let batch_size = util::min_usize(self.budget, descs.len());
+        let start = self.next;
+
+        for i in 0..nb_queues {
+            let queue_idx = (start + i) % nb_queues;
+
+            let cnt = unsafe { self.queues[queue_idx].consume(&mut descs[..batch_size]) };
+
+            if cnt > 0 {
+                f(queue_idx, &mut descs[..cnt]);
+            }
+
+            self.consumed[queue_idx] = cnt;
+        }
+
+        self.next = (start + 1) % nb_queues;
+
+        &self.consumed
+    }
+}
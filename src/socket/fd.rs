@@ -1,18 +1,49 @@
 //! File descriptor utilities.
 
-use libc::{EINTR, POLLIN, POLLOUT, SOL_XDP};
-use libxdp_sys::{xdp_statistics, XDP_STATISTICS};
+use libc::{
+    EAGAIN, EBUSY, EINTR, ENETDOWN, ENOBUFS, MSG_DONTWAIT, POLLIN, POLLOUT, SOL_SOCKET, SOL_XDP,
+    SO_MEMINFO,
+};
+use libxdp_sys::{xdp_options, xdp_statistics, XDP_MMAP_OFFSETS, XDP_OPTIONS, XDP_STATISTICS};
 use std::{
     fmt,
     io::{self, ErrorKind},
     mem,
     os::unix::prelude::{AsRawFd, RawFd},
+    ptr,
 };
 
 use crate::util;
 
 const XDP_STATISTICS_SIZEOF: u32 = mem::size_of::<xdp_statistics>() as u32;
 
+const XDP_OPTIONS_SIZEOF: u32 = mem::size_of::<xdp_options>() as u32;
+
+/// Number of `u64` fields in `struct xdp_mmap_offsets`, four rings of
+/// four offsets each.
+const XDP_MMAP_OFFSETS_FIELDS: usize = 16;
+
+const XDP_MMAP_OFFSETS_SIZEOF: u32 = (XDP_MMAP_OFFSETS_FIELDS * mem::size_of::<u64>()) as u32;
+
+/// Size of the pre 5.4 `struct xdp_mmap_offsets`, which lacks the
+/// flags offsets.
+const XDP_MMAP_OFFSETS_V1_SIZEOF: u32 = (12 * mem::size_of::<u64>()) as u32;
+
+/// Number of entries in the array returned by `SO_MEMINFO`.
+///
+/// Matches `SK_MEMINFO_VARS` as defined in the linux source at
+/// `include/uapi/linux/sock_diag.h`.
+const SK_MEMINFO_VARS: usize = 9;
+
+const SK_MEMINFO_SIZEOF: u32 = (SK_MEMINFO_VARS * mem::size_of::<u32>()) as u32;
+
+/// Socket option setting the maximum number of packets processed per
+/// busy poll.
+///
+/// Matches the constant of the same name defined in the linux source
+/// at `include/uapi/asm-generic/socket.h`.
+const SO_BUSY_POLL_BUDGET: i32 = 70;
+
 #[derive(Clone, Copy)]
 struct PollFd(libc::pollfd);
 
@@ -42,10 +73,11 @@ pub struct Fd {
     id: i32,
     pollfd_read: PollFd,
     pollfd_write: PollFd,
+    report_link_down: bool,
 }
 
 impl Fd {
-    pub(super) fn new(id: i32) -> Self {
+    pub(super) fn new(id: i32, report_link_down: bool) -> Self {
         let pollfd_read = PollFd(libc::pollfd {
             fd: id,
             events: POLLIN,
@@ -62,6 +94,7 @@ impl Fd {
             id,
             pollfd_read,
             pollfd_write,
+            report_link_down,
         }
     }
 
@@ -70,6 +103,7 @@ impl Fd {
             id: self.id,
             pollfd_read: self.pollfd_read,
             pollfd_write: self.pollfd_write,
+            report_link_down: self.report_link_down,
         }
     }
 
@@ -83,6 +117,35 @@ impl Fd {
         self.pollfd_write.poll(timeout_ms)
     }
 
+    /// Wait for the socket to become readable, first spinning on a
+    /// non-blocking `poll` up to `spin_iters` times before falling
+    /// back to a blocking `poll` with a timeout of `timeout_ms`.
+    ///
+    /// Spinning avoids the wakeup latency of a blocking `poll` when
+    /// packets arrive in quick succession, while the fallback stops an
+    /// idle socket from burning a core. Raising `spin_iters` trades
+    /// more CPU for lower latency. The returned [`PollStatus`] says
+    /// which phase the socket became ready in, which helps with
+    /// tuning: if it's nearly always [`ReadyAfterBlocking`], spinning
+    /// isn't paying off.
+    ///
+    /// [`ReadyAfterBlocking`]: PollStatus::ReadyAfterBlocking
+    pub fn poll_adaptive(&mut self, spin_iters: usize, timeout_ms: i32) -> io::Result<PollStatus> {
+        for _ in 0..spin_iters {
+            if self.pollfd_read.poll(0)? {
+                return Ok(PollStatus::ReadyWhileSpinning);
+            }
+
+            std::hint::spin_loop();
+        }
+
+        if self.pollfd_read.poll(timeout_ms)? {
+            Ok(PollStatus::ReadyAfterBlocking)
+        } else {
+            Ok(PollStatus::TimedOut)
+        }
+    }
+
     /// Returns [`Socket`](crate::Socket) statistics.
     #[inline]
     pub fn xdp_statistics(&self) -> io::Result<XdpStatistics> {
@@ -113,6 +176,199 @@ impl Fd {
             ))
         }
     }
+
+    /// Returns the socket's `XDP_OPTIONS` flags, e.g.
+    /// `XDP_OPTIONS_ZEROCOPY` if bound in zero-copy mode.
+    pub(crate) fn xdp_options(&self) -> io::Result<u32> {
+        let mut opts = xdp_options { flags: 0 };
+
+        let mut optlen = XDP_OPTIONS_SIZEOF;
+
+        let err = unsafe {
+            libc::getsockopt(
+                self.as_raw_fd(),
+                SOL_XDP,
+                XDP_OPTIONS as i32,
+                &mut opts as *mut _ as *mut libc::c_void,
+                &mut optlen,
+            )
+        };
+
+        if err != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if optlen == XDP_OPTIONS_SIZEOF {
+            Ok(opts.flags)
+        } else {
+            Err(io::Error::new(
+                ErrorKind::Other,
+                "`optlen` returned from `getsockopt` does not match `xdp_options` struct size",
+            ))
+        }
+    }
+
+    /// Returns [`Socket`](crate::Socket) memory usage.
+    ///
+    /// Mostly of interest in copy mode, where packets pass through
+    /// the socket's buffers. An [`rmem_alloc`] approaching
+    /// [`rcvbuf`] is a sign of incoming pressure and that drops are
+    /// likely to follow.
+    ///
+    /// [`rmem_alloc`]: SockMemInfo::rmem_alloc
+    /// [`rcvbuf`]: SockMemInfo::rcvbuf
+    #[inline]
+    pub fn meminfo(&self) -> io::Result<SockMemInfo> {
+        let mut meminfo = SockMemInfo::default();
+
+        let mut optlen = SK_MEMINFO_SIZEOF;
+
+        let err = unsafe {
+            libc::getsockopt(
+                self.as_raw_fd(),
+                SOL_SOCKET,
+                SO_MEMINFO,
+                meminfo.0.as_mut_ptr() as *mut libc::c_void,
+                &mut optlen,
+            )
+        };
+
+        if err != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Older kernels may report fewer fields, in which case the
+        // remainder are left as zero.
+        Ok(meminfo)
+    }
+
+    /// Returns the offsets of the producer, consumer, descriptor and
+    /// flags fields within each of the socket's memory mapped rings,
+    /// as reported by the kernel.
+    ///
+    /// With these, and the page offsets the rings are mapped at
+    /// (`XDP_PGOFF_RX_RING`, `XDP_PGOFF_TX_RING`,
+    /// `XDP_UMEM_PGOFF_FILL_RING` and `XDP_UMEM_PGOFF_COMPLETION_RING`),
+    /// the rings can be mapped and accessed directly. Kernels before
+    /// 5.4 don't report a flags offset, in which case
+    /// [`XdpRingOffsets::flags`] will be [`None`].
+    pub fn mmap_offsets(&self) -> io::Result<XdpMmapOffsets> {
+        let mut offsets = [0u64; XDP_MMAP_OFFSETS_FIELDS];
+
+        let mut optlen = XDP_MMAP_OFFSETS_SIZEOF;
+
+        let err = unsafe {
+            libc::getsockopt(
+                self.as_raw_fd(),
+                SOL_XDP,
+                XDP_MMAP_OFFSETS as i32,
+                offsets.as_mut_ptr() as *mut libc::c_void,
+                &mut optlen,
+            )
+        };
+
+        if err != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Pre 5.4 kernels use a layout without the flags field.
+        let (fields_per_ring, has_flags) = if optlen == XDP_MMAP_OFFSETS_SIZEOF {
+            (4, true)
+        } else if optlen == XDP_MMAP_OFFSETS_V1_SIZEOF {
+            (3, false)
+        } else {
+            return Err(io::Error::new(
+                ErrorKind::Other,
+                "`optlen` returned from `getsockopt` does not match `xdp_mmap_offsets` struct size",
+            ));
+        };
+
+        let ring = |i: usize| {
+            let fields = &offsets[i * fields_per_ring..(i + 1) * fields_per_ring];
+
+            XdpRingOffsets {
+                producer: fields[0],
+                consumer: fields[1],
+                desc: fields[2],
+                flags: if has_flags { Some(fields[3]) } else { None },
+            }
+        };
+
+        Ok(XdpMmapOffsets {
+            rx: ring(0),
+            tx: ring(1),
+            fill: ring(2),
+            comp: ring(3),
+        })
+    }
+
+    /// Sets the maximum number of packets the kernel processes each
+    /// time it busy polls on behalf of this socket
+    /// (`SO_BUSY_POLL_BUDGET`).
+    ///
+    /// Requires linux 5.11 or later, and only has an effect when busy
+    /// polling is enabled (e.g. via `SO_BUSY_POLL` and
+    /// `SO_PREFER_BUSY_POLL`). Raising the budget above its current
+    /// value requires `CAP_NET_ADMIN`, and since it starts at zero
+    /// (meaning use the kernel's default of 8) this includes setting
+    /// it for the first time. Values larger than [`u16::MAX`] are
+    /// rejected with `EINVAL`.
+    pub fn set_busy_poll_budget(&self, budget: u32) -> io::Result<()> {
+        let budget = budget as libc::c_int;
+
+        let err = unsafe {
+            libc::setsockopt(
+                self.as_raw_fd(),
+                SOL_SOCKET,
+                SO_BUSY_POLL_BUDGET,
+                &budget as *const _ as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+
+        if err != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Whether wakeups on this socket return `ENETDOWN` rather than
+    /// ignoring it. See
+    /// [`SocketConfigBuilder::report_link_down`](crate::config::SocketConfigBuilder::report_link_down).
+    #[inline]
+    pub(crate) fn report_link_down(&self) -> bool {
+        self.report_link_down
+    }
+
+    /// Issue a zero length, non-blocking `recvfrom` on the socket,
+    /// which drives the kernel to process the rx and fill rings.
+    ///
+    /// Errors indicating there was simply nothing to do, or that the
+    /// kernel is already busy processing, are ignored. So is the link
+    /// being down, unless the socket reports it.
+    pub(crate) fn recvfrom_wakeup(&self) -> io::Result<()> {
+        let ret = unsafe {
+            libc::recvfrom(
+                self.as_raw_fd(),
+                ptr::null_mut(),
+                0,
+                MSG_DONTWAIT,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+
+        if ret < 0 {
+            match util::get_errno() {
+                EAGAIN | EBUSY | EINTR | ENOBUFS => (),
+                ENETDOWN if !self.report_link_down => (),
+                _ => return Err(io::Error::last_os_error()),
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl fmt::Debug for Fd {
@@ -137,6 +393,19 @@ impl AsRawFd for Fd {
     }
 }
 
+/// The outcome of [`Fd::poll_adaptive`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollStatus {
+    /// The socket became readable during the spinning phase.
+    ReadyWhileSpinning,
+    /// The socket became readable during the blocking `poll`.
+    ReadyAfterBlocking,
+    /// The socket didn't become readable before the timeout. May
+    /// also be returned if the blocking `poll` was interrupted by a
+    /// signal.
+    TimedOut,
+}
+
 /// AF_XDP [`Socket`](crate::Socket) statistics.
 ///
 /// Can be retrieved by calling [`xdp_statistics`](Fd::xdp_statistics).
@@ -192,4 +461,163 @@ impl XdpStatistics {
     pub fn tx_ring_empty_descs(&self) -> u64 {
         self.0.tx_ring_empty_descs
     }
+
+    /// The change in each counter since `baseline` was read.
+    pub(crate) fn since(&self, baseline: &XdpStatistics) -> XdpStatistics {
+        let (now, then) = (&self.0, &baseline.0);
+
+        Self(xdp_statistics {
+            rx_dropped: now.rx_dropped.wrapping_sub(then.rx_dropped),
+            rx_invalid_descs: now.rx_invalid_descs.wrapping_sub(then.rx_invalid_descs),
+            tx_invalid_descs: now.tx_invalid_descs.wrapping_sub(then.tx_invalid_descs),
+            rx_ring_full: now.rx_ring_full.wrapping_sub(then.rx_ring_full),
+            rx_fill_ring_empty_descs: now
+                .rx_fill_ring_empty_descs
+                .wrapping_sub(then.rx_fill_ring_empty_descs),
+            tx_ring_empty_descs: now
+                .tx_ring_empty_descs
+                .wrapping_sub(then.tx_ring_empty_descs),
+        })
+    }
+}
+
+/// AF_XDP [`Socket`](crate::Socket) memory usage in bytes, with the
+/// exception of [`drops`](SockMemInfo::drops).
+///
+/// Can be retrieved by calling [`meminfo`](Fd::meminfo).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SockMemInfo([u32; SK_MEMINFO_VARS]);
+
+impl SockMemInfo {
+    /// Memory currently allocated for received data.
+    #[inline]
+    pub fn rmem_alloc(&self) -> u32 {
+        self.0[0]
+    }
+
+    /// Receive buffer size limit.
+    #[inline]
+    pub fn rcvbuf(&self) -> u32 {
+        self.0[1]
+    }
+
+    /// Memory currently allocated for data to be sent.
+    #[inline]
+    pub fn wmem_alloc(&self) -> u32 {
+        self.0[2]
+    }
+
+    /// Send buffer size limit.
+    #[inline]
+    pub fn sndbuf(&self) -> u32 {
+        self.0[3]
+    }
+
+    /// Memory reserved for future use but not yet allocated.
+    #[inline]
+    pub fn fwd_alloc(&self) -> u32 {
+        self.0[4]
+    }
+
+    /// Memory used by data queued for sending.
+    #[inline]
+    pub fn wmem_queued(&self) -> u32 {
+        self.0[5]
+    }
+
+    /// Memory used for socket options and other ancillary data.
+    #[inline]
+    pub fn optmem(&self) -> u32 {
+        self.0[6]
+    }
+
+    /// Memory used by the socket's backlog queue.
+    #[inline]
+    pub fn backlog(&self) -> u32 {
+        self.0[7]
+    }
+
+    /// Number of packets dropped by the socket.
+    #[inline]
+    pub fn drops(&self) -> u32 {
+        self.0[8]
+    }
+}
+
+/// Offsets of the fields within one of an AF_XDP
+/// [`Socket`](crate::Socket)'s memory mapped rings, relative to the
+/// start of the mapping. Mirrors `struct xdp_ring_offset` as defined
+/// in the linux source at `include/uapi/linux/if_xdp.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XdpRingOffsets {
+    producer: u64,
+    consumer: u64,
+    desc: u64,
+    flags: Option<u64>,
+}
+
+impl XdpRingOffsets {
+    /// Offset of the producer index, a `u32`.
+    #[inline]
+    pub fn producer(&self) -> u64 {
+        self.producer
+    }
+
+    /// Offset of the consumer index, a `u32`.
+    #[inline]
+    pub fn consumer(&self) -> u64 {
+        self.consumer
+    }
+
+    /// Offset of the start of the descriptor array.
+    #[inline]
+    pub fn desc(&self) -> u64 {
+        self.desc
+    }
+
+    /// Offset of the ring flags, a `u32`, or [`None`] on kernels
+    /// before 5.4 which don't have them.
+    #[inline]
+    pub fn flags(&self) -> Option<u64> {
+        self.flags
+    }
+}
+
+/// Ring field offsets for each of the rings associated with an AF_XDP
+/// [`Socket`](crate::Socket). Mirrors `struct xdp_mmap_offsets` as
+/// defined in the linux source at `include/uapi/linux/if_xdp.h`.
+///
+/// Can be retrieved by calling [`mmap_offsets`](Fd::mmap_offsets).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XdpMmapOffsets {
+    rx: XdpRingOffsets,
+    tx: XdpRingOffsets,
+    fill: XdpRingOffsets,
+    comp: XdpRingOffsets,
+}
+
+impl XdpMmapOffsets {
+    /// Offsets within the [`RxQueue`](crate::RxQueue) ring.
+    #[inline]
+    pub fn rx(&self) -> &XdpRingOffsets {
+        &self.rx
+    }
+
+    /// Offsets within the [`TxQueue`](crate::TxQueue) ring.
+    #[inline]
+    pub fn tx(&self) -> &XdpRingOffsets {
+        &self.tx
+    }
+
+    /// Offsets within the [`FillQueue`](crate::FillQueue) ring.
+    #[inline]
+    pub fn fill(&self) -> &XdpRingOffsets {
+        &self.fill
+    }
+
+    /// Offsets within the [`CompQueue`](crate::CompQueue) ring.
+    #[inline]
+    pub fn comp(&self) -> &XdpRingOffsets {
+        &self.comp
+    }
 }
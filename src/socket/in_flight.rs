@@ -0,0 +1,120 @@
+//! Bounding the number of frames in flight on the transmit path.
+
+use std::num::NonZeroUsize;
+
+use crate::util;
+
+/// Caps the number of frames that may be in flight at once, where a
+/// frame is considered in flight from the moment it's submitted to
+/// the [`TxQueue`](crate::TxQueue) until it's consumed from the
+/// [`CompQueue`](crate::CompQueue).
+///
+/// Before transmitting, ask how many frames may be submitted via
+/// [`try_acquire`](Self::try_acquire) (or use
+/// [`TxQueue::produce_limited`](crate::TxQueue::produce_limited)
+/// which does this for you) and, after consuming from the
+/// [`CompQueue`](crate::CompQueue), hand back the number of completed
+/// frames via [`release`](Self::release). This keeps the pipeline
+/// from overcommitting and so exhausting the pool of free frames.
+#[derive(Debug, Clone, Copy)]
+pub struct InFlightLimiter {
+    limit: usize,
+    in_flight: usize,
+}
+
+impl InFlightLimiter {
+    /// Creates a new `InFlightLimiter` allowing at most `limit` frames
+    /// in flight.
+    pub fn new(limit: NonZeroUsize) -> Self {
+        Self {
+            limit: limit.get(),
+            in_flight: 0,
+        }
+    }
+
+    /// Returns how many of `n` frames may be submitted given the
+    /// number currently in flight, and marks that many as in flight.
+    #[inline]
+    pub fn try_acquire(&mut self, n: usize) -> usize {
+        let cnt = util::min_usize(n, self.available());
+
+        self.in_flight += cnt;
+
+        cnt
+    }
+
+    /// Marks `n` frames as no longer in flight, e.g. after consuming
+    /// them from the [`CompQueue`](crate::CompQueue).
+    ///
+    /// Releasing more frames than are in flight is a bookkeeping
+    /// error; in debug builds this will panic, otherwise the
+    /// in-flight count saturates at zero.
+    #[inline]
+    pub fn release(&mut self, n: usize) {
+        debug_assert!(
+            n <= self.in_flight,
+            "released {} frames but only {} in flight",
+            n,
+            self.in_flight
+        );
+
+        self.in_flight = self.in_flight.saturating_sub(n);
+    }
+
+    /// The number of frames currently in flight.
+    #[inline]
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// The number of frames that may still be submitted.
+    #[inline]
+    pub fn available(&self) -> usize {
+        self.limit - self.in_flight
+    }
+
+    /// The maximum number of frames permitted in flight.
+    #[inline]
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+
+    #[test]
+    fn acquire_is_capped_at_limit() {
+        let mut limiter = InFlightLimiter::new(4.try_into().unwrap());
+
+        assert_eq!(limiter.try_acquire(3), 3);
+        assert_eq!(limiter.try_acquire(3), 1);
+        assert_eq!(limiter.try_acquire(1), 0);
+        assert_eq!(limiter.in_flight(), 4);
+    }
+
+    #[test]
+    fn release_frees_up_capacity() {
+        let mut limiter = InFlightLimiter::new(4.try_into().unwrap());
+
+        assert_eq!(limiter.try_acquire(4), 4);
+
+        limiter.release(2);
+
+        assert_eq!(limiter.available(), 2);
+        assert_eq!(limiter.try_acquire(4), 2);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn releasing_more_than_in_flight_panics_in_debug() {
+        let mut limiter = InFlightLimiter::new(4.try_into().unwrap());
+
+        limiter.try_acquire(1);
+        limiter.release(2);
+    }
+}
@@ -0,0 +1,273 @@
+//! A compact histogram for tracking packet processing latency.
+
+use std::time::Duration;
+
+/// Number of bits of precision kept within each power of two range.
+const SUB_BUCKET_BITS: u32 = 5;
+const SUB_BUCKET_COUNT: usize = 1 << SUB_BUCKET_BITS;
+
+/// Enough buckets to cover the entire range of a `u64`.
+const BUCKET_COUNT: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKET_COUNT;
+
+/// A histogram of latencies with log-linear, HDR-style, buckets.
+///
+/// Values are recorded at nanosecond resolution. Each power of two
+/// range is split into 32 equal width buckets, so values below 32ns
+/// are recorded exactly and larger values to within roughly 3%, while
+/// the whole histogram takes up a fixed 15KiB regardless of the range
+/// of values recorded.
+///
+/// A typical use is measuring the time between a packet arriving,
+/// according to some timestamp taken on receipt, and it being
+/// processed, via [`record_between`](Self::record_between).
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    buckets: Box<[u64]>,
+    count: u64,
+    min: u64,
+    max: u64,
+}
+
+impl LatencyHistogram {
+    /// Creates a new, empty `LatencyHistogram`.
+    pub fn new() -> Self {
+        Self {
+            buckets: vec![0; BUCKET_COUNT].into_boxed_slice(),
+            count: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// Record a single latency sample.
+    ///
+    /// Latencies of more than `u64::MAX` nanoseconds (roughly 584
+    /// years) are recorded as `u64::MAX`.
+    #[inline]
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = if latency.as_nanos() > u64::MAX as u128 {
+            u64::MAX
+        } else {
+            latency.as_nanos() as u64
+        };
+
+        self.buckets[bucket_index(nanos)] += 1;
+        self.count += 1;
+
+        if nanos < self.min {
+            self.min = nanos;
+        }
+
+        if nanos > self.max {
+            self.max = nanos;
+        }
+    }
+
+    /// Record the latency between `timestamp`, e.g. the time a packet
+    /// was received, and `now`, both measured against the same clock.
+    ///
+    /// If `now` is earlier than `timestamp`, which may happen if the
+    /// two clocks are not perfectly synchronised, a latency of zero is
+    /// recorded.
+    #[inline]
+    pub fn record_between(&mut self, timestamp: Duration, now: Duration) {
+        self.record(now.checked_sub(timestamp).unwrap_or_default())
+    }
+
+    /// The number of samples recorded.
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The smallest latency recorded, or `None` if empty.
+    #[inline]
+    pub fn min(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(Duration::from_nanos(self.min))
+        }
+    }
+
+    /// The largest latency recorded, or `None` if empty.
+    #[inline]
+    pub fn max(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(Duration::from_nanos(self.max))
+        }
+    }
+
+    /// The latency below or at which `percentile` percent of samples
+    /// fall, e.g. `99.0` for the p99 latency. `percentile` is clamped
+    /// to the range `0.0..=100.0`.
+    ///
+    /// The value returned is the upper bound of the bucket the sample
+    /// falls in (capped to the largest value recorded), so may
+    /// overstate the true value by the bucket's width. Returns `None`
+    /// if empty.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let percentile = percentile.clamp(0.0, 100.0);
+
+        let rank = ((percentile / 100.0) * self.count as f64).ceil() as u64;
+        let rank = rank.clamp(1, self.count);
+
+        let mut seen = 0;
+
+        for (idx, cnt) in self.buckets.iter().enumerate() {
+            seen += cnt;
+
+            if seen >= rank {
+                let nanos = bucket_upper_bound(idx).clamp(self.min, self.max);
+
+                return Some(Duration::from_nanos(nanos));
+            }
+        }
+
+        Some(Duration::from_nanos(self.max))
+    }
+
+    /// Add all samples from `other` to this histogram.
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (cnt, other_cnt) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *cnt += other_cnt;
+        }
+
+        self.count += other.count;
+
+        if other.min < self.min {
+            self.min = other.min;
+        }
+
+        if other.max > self.max {
+            self.max = other.max;
+        }
+    }
+
+    /// Clear all recorded samples.
+    pub fn reset(&mut self) {
+        self.buckets.iter_mut().for_each(|cnt| *cnt = 0);
+        self.count = 0;
+        self.min = u64::MAX;
+        self.max = 0;
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[inline]
+fn bucket_index(nanos: u64) -> usize {
+    if nanos < SUB_BUCKET_COUNT as u64 {
+        return nanos as usize;
+    }
+
+    let magnitude = 63 - nanos.leading_zeros();
+    let shift = magnitude - SUB_BUCKET_BITS;
+    let sub_bucket = (nanos >> shift) as usize;
+
+    (shift as usize + 1) * SUB_BUCKET_COUNT + (sub_bucket - SUB_BUCKET_COUNT)
+}
+
+#[inline]
+fn bucket_upper_bound(idx: usize) -> u64 {
+    if idx < SUB_BUCKET_COUNT {
+        return idx as u64;
+    }
+
+    let shift = (idx / SUB_BUCKET_COUNT - 1) as u32;
+    let sub_bucket = (idx % SUB_BUCKET_COUNT + SUB_BUCKET_COUNT) as u64;
+
+    (sub_bucket << shift) + ((1 << shift) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_bounds_contain_their_values() {
+        for nanos in (0..10_000).chain([u64::MAX / 2, u64::MAX - 1, u64::MAX]) {
+            let idx = bucket_index(nanos);
+
+            assert!(idx < BUCKET_COUNT);
+            assert!(nanos <= bucket_upper_bound(idx));
+
+            if idx > 0 {
+                assert!(nanos > bucket_upper_bound(idx - 1));
+            }
+        }
+    }
+
+    #[test]
+    fn percentiles_are_within_bucket_precision() {
+        let mut hist = LatencyHistogram::new();
+
+        for micros in 1..=1000 {
+            hist.record(Duration::from_micros(micros));
+        }
+
+        assert_eq!(hist.count(), 1000);
+        assert_eq!(hist.min(), Some(Duration::from_micros(1)));
+        assert_eq!(hist.max(), Some(Duration::from_micros(1000)));
+
+        for (percentile, expected) in [(50.0, 500), (99.0, 990), (99.9, 999)] {
+            let actual = hist.percentile(percentile).unwrap().as_nanos() as f64;
+            let expected = Duration::from_micros(expected).as_nanos() as f64;
+
+            assert!(actual >= expected);
+            assert!((actual - expected) / expected < 0.04);
+        }
+
+        assert_eq!(hist.percentile(100.0), hist.max());
+    }
+
+    #[test]
+    fn empty_histogram_has_no_percentiles() {
+        let mut hist = LatencyHistogram::new();
+
+        assert_eq!(hist.percentile(50.0), None);
+        assert_eq!(hist.min(), None);
+
+        hist.record(Duration::from_nanos(5));
+        hist.reset();
+
+        assert_eq!(hist.count(), 0);
+        assert_eq!(hist.max(), None);
+    }
+
+    #[test]
+    fn record_between_saturates_at_zero() {
+        let mut hist = LatencyHistogram::new();
+
+        hist.record_between(Duration::from_secs(2), Duration::from_secs(1));
+        hist.record_between(Duration::from_secs(1), Duration::from_secs(2));
+
+        assert_eq!(hist.min(), Some(Duration::ZERO));
+        assert_eq!(hist.max(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn merging_combines_samples() {
+        let mut fst = LatencyHistogram::new();
+        let mut snd = LatencyHistogram::new();
+
+        fst.record(Duration::from_nanos(10));
+        snd.record(Duration::from_nanos(20));
+
+        fst.merge(&snd);
+
+        assert_eq!(fst.count(), 2);
+        assert_eq!(fst.min(), Some(Duration::from_nanos(10)));
+        assert_eq!(fst.max(), Some(Duration::from_nanos(20)));
+    }
+}
@@ -0,0 +1,112 @@
+//! Querying the state of a network interface's link.
+
+use libc::{ENETDOWN, ENODEV, ENXIO, EPERM, IFF_PROMISC, IFF_RUNNING, IFF_UP};
+use std::{
+    io, thread,
+    time::{Duration, Instant},
+};
+
+use super::ethtool::{if_name, IoctlSocket};
+
+/// How long [`wait_for_link`] sleeps between checks.
+const LINK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Wait for the link of the interface with index `if_index` to come
+/// up, returning `true` once it has or `false` if `timeout` elapses
+/// first.
+///
+/// The link is taken to be up once the interface is both
+/// administratively up and running, i.e. has a carrier, which is when
+/// it's safe to bind a socket to it. The interface's flags are polled
+/// via `SIOCGIFFLAGS` every few milliseconds, and an interface which
+/// doesn't exist yet is treated as being down, so this may also be
+/// used to wait for one to appear. The flags are always checked at
+/// least once, so a zero `timeout` just reports whether the link is
+/// currently up.
+pub fn wait_for_link(if_index: u32, timeout: Duration) -> io::Result<bool> {
+    let deadline = Instant::now() + timeout;
+
+    let sock = IoctlSocket::new()?;
+
+    loop {
+        if is_link_up(&sock, if_index)? {
+            return Ok(true);
+        }
+
+        let now = Instant::now();
+
+        if now >= deadline {
+            return Ok(false);
+        }
+
+        thread::sleep(LINK_POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+/// Whether `err` is the kernel reporting that the link of a socket's
+/// interface is down.
+///
+/// Wakeups only return such errors on sockets created with
+/// [`report_link_down`] set, and then consistently: both
+/// [`TxQueue::wakeup`](super::TxQueue::wakeup) and
+/// [`FillQueue::wakeup_with_budget`](crate::FillQueue::wakeup_with_budget),
+/// and everything built on them, e.g. `produce_and_wakeup`, fail with
+/// an error for which this returns `true`. A supervisor can then pause
+/// the data plane and [`wait_for_link`] before carrying on with the
+/// same socket.
+///
+/// Note that the kernel never reports the link state through `poll`,
+/// so neither do the poll based wakeups and consumes, which just time
+/// out while the link is down. A thread which only polls can check
+/// with [`wait_for_link`] and a zero timeout whenever they do.
+///
+/// [`report_link_down`]: crate::config::SocketConfigBuilder::report_link_down
+#[inline]
+pub fn is_link_down(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(ENETDOWN)
+}
+
+/// Turn promiscuous mode on or off for the interface with index
+/// `if_index`, returning whether it was on beforehand.
+pub(super) fn set_promiscuous(if_index: u32, enabled: bool) -> io::Result<bool> {
+    let if_name = if_name(if_index)?;
+
+    let sock = IoctlSocket::new()?;
+
+    let flags = sock.if_flags(&if_name)?;
+
+    let was_enabled = flags & IFF_PROMISC != 0;
+
+    if was_enabled != enabled {
+        let flags = if enabled {
+            flags | IFF_PROMISC
+        } else {
+            flags & !IFF_PROMISC
+        };
+
+        sock.set_if_flags(&if_name, flags).map_err(|err| {
+            if err.raw_os_error() == Some(EPERM) {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "changing promiscuous mode requires CAP_NET_ADMIN",
+                )
+            } else {
+                err
+            }
+        })?;
+    }
+
+    Ok(was_enabled)
+}
+
+/// Whether the interface with index `if_index` exists and has its
+/// link up.
+fn is_link_up(sock: &IoctlSocket, if_index: u32) -> io::Result<bool> {
+    let flags = if_name(if_index).and_then(|name| sock.if_flags(&name));
+
+    match flags {
+        Ok(flags) => Ok(flags & (IFF_UP | IFF_RUNNING) == IFF_UP | IFF_RUNNING),
+        Err(err) if matches!(err.raw_os_error(), Some(ENXIO) | Some(ENODEV)) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
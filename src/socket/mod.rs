@@ -1,25 +1,89 @@
 //! Types for creating and using an AF_XDP [`Socket`].
 
 mod fd;
-pub use fd::{Fd, XdpStatistics};
+pub use fd::{Fd, PollStatus, SockMemInfo, XdpMmapOffsets, XdpRingOffsets, XdpStatistics};
+
+mod batch;
+pub use batch::suggest_batch_size;
+
+mod ethtool;
+pub use ethtool::{
+    driver_xdp_stats, nic_ring_params, queue_stats, set_nic_ring_params, NicRingParams, QueueStats,
+};
+
+mod fair_scheduler;
+pub use fair_scheduler::FairScheduler;
+
+mod in_flight;
+pub use in_flight::InFlightLimiter;
+
+mod latency;
+pub use latency::LatencyHistogram;
+
+mod link;
+pub use link::{is_link_down, wait_for_link};
+
+mod netdev;
+pub use netdev::{xdp_act_flags, XdpActFlags};
+
+mod pacer;
+pub use pacer::Pacer;
+
+mod parsed_iter;
+pub use parsed_iter::{ParsedIter, ParsedPacket};
+
+mod reflector;
+pub use reflector::Reflector;
+
+mod ring_snapshot;
+use ring_snapshot::RingPtrs;
+pub use ring_snapshot::{RingPosition, RingSnapshot};
 
 mod rx_queue;
 pub use rx_queue::RxQueue;
 
+mod sender;
+pub use sender::Sender;
+
+mod splice;
+pub use splice::splice;
+
+mod stream;
+pub use stream::XskStream;
+
+mod sync_tx_queue;
+pub use sync_tx_queue::SyncTxQueue;
+
 mod tx_queue;
-pub use tx_queue::TxQueue;
+pub use tx_queue::{TxQueue, DEFAULT_STALL_WARNING_THRESHOLD};
+
+mod tx_transaction;
+pub use tx_transaction::TxTransaction;
 
-use libxdp_sys::xsk_socket;
+mod xsks_map;
+pub use xsks_map::XsksMap;
+
+use libc::{AF_XDP, ENOMEM, EOPNOTSUPP, EPERM, IFNAMSIZ, SOL_SOCKET, SO_BINDTODEVICE};
+use libxdp_sys::{
+    sockaddr_xdp, xsk_socket, xsk_socket_config, XDP_OPTIONS_ZEROCOPY, XDP_SHARED_UMEM,
+};
+use log::warn;
 use std::{
     borrow::Borrow,
     error::Error,
-    fmt, io,
+    fmt, io, mem,
+    os::unix::prelude::{AsRawFd, RawFd},
     ptr::{self, NonNull},
-    sync::{Arc, Mutex},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
 };
 
 use crate::{
-    config::{Interface, SocketConfig},
+    config::{BindFlags, Interface, QueueSize, SocketConfig, XdpAction},
     ring::{XskRingCons, XskRingProd},
     umem::{CompQueue, FillQueue, Umem},
 };
@@ -43,6 +107,13 @@ impl XskSocket {
 
 impl Drop for XskSocket {
     fn drop(&mut self) {
+        // A mock socket was never created, so there's nothing to
+        // delete.
+        #[cfg(test)]
+        if self.0 == NonNull::dangling() {
+            return;
+        }
+
         // SAFETY: unsafe constructor contract guarantees that the
         // socket has not been deleted already.
         unsafe {
@@ -58,6 +129,9 @@ struct SocketInner {
     // `ptr` must appear before `umem` to ensure correct drop order.
     _ptr: XskSocket,
     _umem: Umem,
+    /// The interface to turn promiscuous mode back off for on drop,
+    /// if this socket turned it on.
+    promisc_restore: Option<u32>,
 }
 
 impl SocketInner {
@@ -65,6 +139,20 @@ impl SocketInner {
         Self {
             _ptr: ptr,
             _umem: umem,
+            promisc_restore: None,
+        }
+    }
+}
+
+impl Drop for SocketInner {
+    fn drop(&mut self) {
+        if let Some(if_index) = self.promisc_restore {
+            if let Err(e) = link::set_promiscuous(if_index, false) {
+                warn!(
+                    "failed to turn off promiscuous mode for interface {}: {}",
+                    if_index, e
+                );
+            }
         }
     }
 }
@@ -76,9 +164,38 @@ impl SocketInner {
 #[derive(Debug)]
 pub struct Socket {
     fd: Fd,
+    bind_mode: BindMode,
+    rx_queue_size: QueueSize,
+    tx_queue_size: QueueSize,
+    bound_addr: BoundAddr,
+    wakeup_with_address: bool,
+    rings: RingPtrs,
+    stats_baseline: Mutex<XdpStatistics>,
     _inner: Arc<Mutex<SocketInner>>,
 }
 
+/// The address an AF_XDP [`Socket`] was bound with, as passed to
+/// `bind` in a `sockaddr_xdp`.
+#[derive(Debug, Clone, Copy)]
+struct BoundAddr {
+    ifindex: u32,
+    queue_id: u32,
+    flags: u16,
+    shared_umem_fd: u32,
+}
+
+impl BoundAddr {
+    fn to_sockaddr_xdp(self) -> sockaddr_xdp {
+        sockaddr_xdp {
+            sxdp_family: AF_XDP as u16,
+            sxdp_flags: self.flags,
+            sxdp_ifindex: self.ifindex,
+            sxdp_queue_id: self.queue_id,
+            sxdp_shared_umem_fd: self.shared_umem_fd,
+        }
+    }
+}
+
 impl Socket {
     /// Create and bind a new AF_XDP socket to a given interface and
     /// queue id using the underlying UMEM.
@@ -101,6 +218,16 @@ impl Socket {
     ///
     ///  2. If the [`Umem`] is not currently shared, expect [`Some`].
     ///
+    /// Any number of sockets may share a [`Umem`], across any mix of
+    /// interfaces and queues. Every socket after the first is bound
+    /// with `XDP_SHARED_UMEM` and the [`Umem`]'s file descriptor as its
+    /// `sxdp_shared_umem_fd`, which is the descriptor of the first
+    /// socket created with it (see [`shared_umem_fd`]). So the only
+    /// ordering requirement is that this first socket must be created
+    /// before the rest. Its descriptor stays open for as long as the
+    /// [`Umem`] or anything created from it exists, so the first
+    /// socket may still be dropped before the others.
+    ///
     /// For further details on using a shared [`Umem`] please see the
     /// [docs](https://www.kernel.org/doc/html/latest/networking/af_xdp.html#xdp-shared-umem-bind-flag).
     ///
@@ -113,6 +240,7 @@ impl Socket {
     /// if the program has already been detached.
     ///
     /// [`XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD`]: crate::config::LibxdpFlags::XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD
+    /// [`shared_umem_fd`]: Self::shared_umem_fd
     #[allow(clippy::new_ret_no_self)]
     #[allow(clippy::type_complexity)]
     pub unsafe fn new(
@@ -121,30 +249,199 @@ impl Socket {
         if_name: &Interface,
         queue_id: u32,
     ) -> Result<(TxQueue, RxQueue, Option<(FillQueue, CompQueue)>), SocketCreateError> {
+        if umem.is_poisoned() {
+            return Err(SocketCreateError {
+                reason: "an earlier socket creation with this UMEM timed out, so it can't be used to create any more",
+                err: io::Error::from(io::ErrorKind::Other),
+            });
+        }
+
+        // SAFETY: the unsafe contract of this function is passed on.
+        match config.creation_timeout() {
+            Some(timeout) => unsafe {
+                Self::create_with_timeout(config, umem, if_name, queue_id, timeout)
+            },
+            None => unsafe { Self::create(config, umem, if_name, queue_id) },
+        }
+    }
+
+    /// Same as [`create`](Self::create), but run on a separate thread
+    /// which is given up on after `timeout`. See
+    /// [`SocketConfigBuilder::creation_timeout`](crate::config::SocketConfigBuilder::creation_timeout).
+    ///
+    /// # Safety
+    ///
+    /// See [`new`](Self::new).
+    #[allow(clippy::type_complexity)]
+    unsafe fn create_with_timeout(
+        config: SocketConfig,
+        umem: &Umem,
+        if_name: &Interface,
+        queue_id: u32,
+        timeout: Duration,
+    ) -> Result<(TxQueue, RxQueue, Option<(FillQueue, CompQueue)>), SocketCreateError> {
+        let (sender, receiver) = mpsc::channel();
+
+        let thread_umem = umem.clone();
+        let if_name = if_name.clone();
+
+        let spawned = thread::Builder::new()
+            .name("xsk-socket-create".into())
+            .spawn(move || {
+                // SAFETY: the unsafe contract of `new` is passed on.
+                let res = unsafe { Self::create(config, &thread_umem, &if_name, queue_id) };
+
+                // If we've been given up on then the receiver is gone,
+                // and dropping the result deletes the socket again.
+                let _ = sender.send(res);
+            });
+
+        if let Err(err) = spawned {
+            return Err(SocketCreateError {
+                reason: "failed to spawn socket creation thread",
+                err,
+            });
+        }
+
+        match receiver.recv_timeout(timeout) {
+            Ok(res) => res,
+            Err(RecvTimeoutError::Timeout) => {
+                // The creation thread may still take the lock and the
+                // saved queues with it, so stop anything else waiting
+                // on or relying on either.
+                umem.poison();
+
+                Err(SocketCreateError {
+                    reason: "timed out creating AF_XDP socket",
+                    err: io::Error::from(io::ErrorKind::TimedOut),
+                })
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(SocketCreateError {
+                reason: "socket creation thread panicked",
+                err: io::Error::from(io::ErrorKind::Other),
+            }),
+        }
+    }
+
+    /// Creates the socket on the calling thread, ignoring any
+    /// creation timeout.
+    ///
+    /// # Safety
+    ///
+    /// See [`new`](Self::new).
+    #[allow(clippy::type_complexity)]
+    unsafe fn create(
+        config: SocketConfig,
+        umem: &Umem,
+        if_name: &Interface,
+        queue_id: u32,
+    ) -> Result<(TxQueue, RxQueue, Option<(FillQueue, CompQueue)>), SocketCreateError> {
+        if config.default_action() != XdpAction::Pass && config.loads_default_program() {
+            return Err(SocketCreateError {
+                reason: "the default XDP program only supports `XdpAction::Pass`, load your own program to use another default action",
+                err: io::Error::from(io::ErrorKind::InvalidInput),
+            });
+        }
+
         let mut socket_ptr = ptr::null_mut();
         let mut tx_q = XskRingProd::default();
         let mut rx_q = XskRingCons::default();
 
-        let (err, fq, cq) = unsafe {
-            umem.with_ptr_and_saved_queues(|xsk_umem, saved_fq_and_cq| {
-                let (mut fq, mut cq) = saved_fq_and_cq
-                    .take()
-                    .unwrap_or_else(|| (Box::default(), Box::default()));
-
-                let err = libxdp_sys::xsk_socket__create_shared(
-                    &mut socket_ptr,
-                    if_name.as_cstr().as_ptr(),
-                    queue_id,
-                    xsk_umem,
-                    rx_q.as_mut(),
-                    tx_q.as_mut(),
-                    fq.as_mut().as_mut(), // double deref due to Box
-                    cq.as_mut().as_mut(),
-                    &config.into(),
+        let mut xsk_config: xsk_socket_config = config.into();
+        let mut fell_back = false;
+        let mut rx_queue_size = config.rx_queue_size();
+        let mut tx_queue_size = config.tx_queue_size();
+
+        let (err, fq, cq, shares_umem, umem_fd) = loop {
+            let (err, fq, cq, shares_umem, umem_fd) = unsafe {
+                umem.with_ptr_and_saved_queues(|xsk_umem, saved_fq_and_cq| {
+                    // The saved queues are only present until the first
+                    // socket is created with this UMEM, any after that
+                    // share it.
+                    let shares_umem = saved_fq_and_cq.is_none();
+
+                    let (mut fq, mut cq) = saved_fq_and_cq
+                        .take()
+                        .unwrap_or_else(|| (Box::default(), Box::default()));
+
+                    let err = libxdp_sys::xsk_socket__create_shared(
+                        &mut socket_ptr,
+                        if_name.as_cstr().as_ptr(),
+                        queue_id,
+                        xsk_umem,
+                        rx_q.as_mut(),
+                        tx_q.as_mut(),
+                        fq.as_mut().as_mut(), // double deref due to Box
+                        cq.as_mut().as_mut(),
+                        &xsk_config,
+                    );
+
+                    let umem_fd = libxdp_sys::xsk_umem__fd(xsk_umem);
+
+                    (err, fq, cq, shares_umem, umem_fd)
+                })
+            };
+
+            let zerocopy_unsupported =
+                err == -EOPNOTSUPP && config.bind_flags().contains(BindFlags::XDP_ZEROCOPY);
+
+            if zerocopy_unsupported && config.zerocopy_fallback() && !fell_back {
+                warn!(
+                    "zero-copy mode not supported on interface {:?} queue {}, falling back to copy mode",
+                    if_name, queue_id
                 );
 
-                (err, fq, cq)
-            })
+                Self::restore_saved_queues(umem, shares_umem, fq, cq);
+
+                let bind_flags =
+                    (*config.bind_flags() - BindFlags::XDP_ZEROCOPY) | BindFlags::XDP_COPY;
+
+                xsk_config.bind_flags = bind_flags.bits();
+                fell_back = true;
+
+                continue;
+            }
+
+            let enomem_min_queue_size = config.enomem_min_queue_size().filter(|_| err == -ENOMEM);
+
+            // The first socket created with a UMEM is the UMEM's own
+            // fd, which stays open after a failed attempt, so any ring
+            // set up before the failure keeps its size in the kernel
+            // and libxdp won't set it up again. Retrying with smaller
+            // sizes would then map a ring smaller than the kernel's.
+            if enomem_min_queue_size.is_some() && !shares_umem {
+                warn!(
+                    "out of memory creating the first socket of a UMEM on interface {:?} queue {}, not retrying as its rings may already be set up",
+                    if_name, queue_id
+                );
+            }
+
+            if let Some(min) = enomem_min_queue_size.filter(|_| shares_umem) {
+                let rx_halved = rx_queue_size.halved(min);
+                let tx_halved = tx_queue_size.halved(min);
+
+                if rx_halved.is_some() || tx_halved.is_some() {
+                    rx_queue_size = rx_halved.unwrap_or(rx_queue_size);
+                    tx_queue_size = tx_halved.unwrap_or(tx_queue_size);
+
+                    warn!(
+                        "out of memory creating socket on interface {:?} queue {}, retrying with rx queue size {} and tx queue size {}",
+                        if_name,
+                        queue_id,
+                        rx_queue_size.get(),
+                        tx_queue_size.get()
+                    );
+
+                    Self::restore_saved_queues(umem, shares_umem, fq, cq);
+
+                    xsk_config.rx_size = rx_queue_size.get();
+                    xsk_config.tx_size = tx_queue_size.get();
+
+                    continue;
+                }
+            }
+
+            break (err, fq, cq, shares_umem, umem_fd);
         };
 
         if err != 0 {
@@ -178,8 +475,48 @@ impl Socket {
             });
         }
 
+        let fd = Fd::new(fd, config.report_link_down());
+
+        // Can't fail at this point since socket creation would have
+        // already failed were the interface not found.
+        let ifindex = unsafe { libc::if_nametoindex(if_name.as_cstr().as_ptr()) };
+
+        let bind_mode = match fd.xdp_options() {
+            Ok(flags) if flags & XDP_OPTIONS_ZEROCOPY != 0 => BindMode::ZeroCopy,
+            Ok(_) => BindMode::Copy,
+            // Kernel predates `XDP_OPTIONS`, so go by what was asked
+            // for instead.
+            Err(_) if !fell_back && config.bind_flags().contains(BindFlags::XDP_ZEROCOPY) => {
+                BindMode::ZeroCopy
+            }
+            Err(_) => BindMode::Copy,
+        };
+
+        let bound_addr = if shares_umem {
+            BoundAddr {
+                ifindex,
+                queue_id,
+                flags: XDP_SHARED_UMEM as u16,
+                shared_umem_fd: umem_fd as u32,
+            }
+        } else {
+            BoundAddr {
+                ifindex,
+                queue_id,
+                flags: xsk_config.bind_flags,
+                shared_umem_fd: 0,
+            }
+        };
+
         let socket = Socket {
-            fd: Fd::new(fd),
+            fd,
+            bind_mode,
+            rx_queue_size,
+            tx_queue_size,
+            bound_addr,
+            wakeup_with_address: config.wakeup_with_address(),
+            rings: RingPtrs::new(&rx_q, &tx_q, &fq, &cq),
+            stats_baseline: Mutex::new(XdpStatistics::default()),
             _inner: Arc::new(Mutex::new(SocketInner::new(socket_ptr, umem.clone()))),
         };
 
@@ -198,7 +535,11 @@ impl Socket {
                 err: io::Error::from_raw_os_error(-err),
             });
         } else {
-            RxQueue::new(rx_q, socket)
+            let umem_config = umem.config();
+            let max_desc_len =
+                (umem_config.frame_size().get() - umem_config.xdp_headroom()) as usize;
+
+            RxQueue::new(rx_q, socket, max_desc_len)
         };
 
         let fq_and_cq = match (fq.is_ring_null(), cq.is_ring_null()) {
@@ -219,17 +560,366 @@ impl Socket {
 
         Ok((tx_q, rx_q, fq_and_cq))
     }
+
+    /// Hand the fill and comp queues taken for a failed creation
+    /// attempt back to `umem`, so they're available for the next one.
+    ///
+    /// Only the first socket of a UMEM takes the UMEM's own queues, a
+    /// sharing socket is given fresh, unmapped ones. Those must not be
+    /// saved, else the retry would take itself for the first socket,
+    /// recording the wrong bound address and refusing any further
+    /// `ENOMEM` retries.
+    fn restore_saved_queues(
+        umem: &Umem,
+        shares_umem: bool,
+        fq: Box<XskRingProd>,
+        cq: Box<XskRingCons>,
+    ) {
+        if shares_umem {
+            return;
+        }
+
+        let mut fq_and_cq = Some((fq, cq));
+
+        umem.with_ptr_and_saved_queues(|_, saved_fq_and_cq| {
+            *saved_fq_and_cq = fq_and_cq.take();
+        });
+    }
+
+    /// The id of the XDP program currently attached to the interface
+    /// with index `if_index`, if there is one.
+    ///
+    /// Intended as a check before creating any sockets, since unless
+    /// [`XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD`] is set [`new`] will
+    /// attach libxdp's default program, which can fail or interfere
+    /// with a program some other tool has attached already. The id can
+    /// be logged, or inspected with e.g. `bpftool prog show id <id>`,
+    /// to decide whether to go ahead, redirect to the interface's
+    /// sockets from the existing program, or give up.
+    ///
+    /// Programs can be attached in native (driver), generic (skb) and
+    /// hardware offload mode at the same time. If there's more than
+    /// one, the id of only one of them is returned, checking in that
+    /// order, but any would be in the way.
+    ///
+    /// Note that libxdp attaches its programs via a dispatcher, which
+    /// other libxdp programs can be added to alongside it, so an id
+    /// here isn't necessarily a conflict if the existing program was
+    /// loaded via libxdp too. In that case the id is the dispatcher's.
+    ///
+    /// [`XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD`]: crate::config::LibxdpFlags::XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD
+    /// [`new`]: Self::new
+    pub fn detect_xdp_conflict(if_index: u32) -> io::Result<Option<u32>> {
+        // SAFETY: all-zero is a valid `bpf_xdp_query_opts`, its size is
+        // then set below.
+        let mut opts: libxdp_sys::bpf_xdp_query_opts = unsafe { mem::zeroed() };
+
+        opts.sz = mem::size_of::<libxdp_sys::bpf_xdp_query_opts>() as _;
+
+        let err = unsafe { libxdp_sys::bpf_xdp_query(if_index as i32, 0, &mut opts) };
+
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(-err));
+        }
+
+        // `prog_id` is only set if there's a single program attached,
+        // with several the kernel only reports them per mode
+        Ok([
+            opts.prog_id,
+            opts.drv_prog_id,
+            opts.skb_prog_id,
+            opts.hw_prog_id,
+        ]
+        .iter()
+        .copied()
+        .find(|&id| id != 0))
+    }
+
+    /// The mode the socket ended up bound in.
+    ///
+    /// This may differ from what was requested, either because no
+    /// mode was specified and the kernel chose, or because
+    /// [`zerocopy_fallback`] was set and zero-copy mode was
+    /// unavailable. On kernels which don't support querying the mode
+    /// (pre 5.3) and where zero-copy mode was not explicitly
+    /// requested, this will return [`BindMode::Copy`].
+    ///
+    /// [`zerocopy_fallback`]: crate::config::SocketConfigBuilder::zerocopy_fallback
+    #[inline]
+    pub fn effective_bind_mode(&self) -> BindMode {
+        self.bind_mode
+    }
+
+    /// The size of the socket's [`RxQueue`].
+    ///
+    /// This will be smaller than requested if
+    /// [`enomem_min_queue_size`] was set and creation had to be
+    /// retried with a smaller size.
+    ///
+    /// [`enomem_min_queue_size`]: crate::config::SocketConfigBuilder::enomem_min_queue_size
+    #[inline]
+    pub fn rx_queue_size(&self) -> QueueSize {
+        self.rx_queue_size
+    }
+
+    /// The size of the socket's [`TxQueue`].
+    ///
+    /// See [`rx_queue_size`](Self::rx_queue_size) for why this may
+    /// differ from the size requested.
+    #[inline]
+    pub fn tx_queue_size(&self) -> QueueSize {
+        self.tx_queue_size
+    }
+
+    /// The file descriptor passed as `sxdp_shared_umem_fd` when this
+    /// socket was bound, i.e. that of the first socket created with
+    /// its [`Umem`], or [`None`] if this is that first socket and so
+    /// didn't bind with `XDP_SHARED_UMEM`.
+    #[inline]
+    pub fn shared_umem_fd(&self) -> Option<RawFd> {
+        if self.bound_addr.flags & XDP_SHARED_UMEM as u16 != 0 {
+            Some(self.bound_addr.shared_umem_fd as RawFd)
+        } else {
+            None
+        }
+    }
+
+    /// Whether this socket's binding is one where the destination
+    /// address passed to `sendto` when waking up the [`TxQueue`] may
+    /// matter, namely when it shares its [`Umem`] with another socket.
+    ///
+    /// For a socket with its own [`Umem`] a null destination is always
+    /// sufficient, as the socket can only be bound to one interface
+    /// and queue. With a shared [`Umem`], some kernels and drivers
+    /// use the destination to determine which queue to kick, in which
+    /// case [`wakeup_with_address`] should be set. Mainline kernels at
+    /// the time of writing ignore the destination entirely.
+    ///
+    /// [`wakeup_with_address`]: crate::config::SocketConfigBuilder::wakeup_with_address
+    #[inline]
+    pub fn wakeup_address_required(&self) -> bool {
+        self.bound_addr.flags & XDP_SHARED_UMEM as u16 != 0
+    }
+
+    /// Whether the interface this socket is bound to supports TX
+    /// checksum offload, as reported in its AF_XDP features by the
+    /// kernel's `netdev` generic netlink family.
+    ///
+    /// Kernels older than 6.8 don't report these features at all, in
+    /// which case this returns `Ok(false)`, so it is safe to fall back
+    /// to calculating checksums in software whenever this is `false`.
+    /// An error is only returned if the query itself fails, e.g. if a
+    /// netlink socket couldn't be opened.
+    pub fn tx_checksum_supported(&self) -> io::Result<bool> {
+        let features = netdev::xsk_features(self.bound_addr.ifindex)?;
+
+        Ok(
+            matches!(features, Some(features) if features & netdev::NETDEV_XSK_FLAGS_TX_CHECKSUM != 0),
+        )
+    }
+
+    /// The socket's [`XdpStatistics`] accumulated since the previous
+    /// call, or since the socket was created for the first call.
+    ///
+    /// The kernel's counters only ever increase and can't be reset, so
+    /// this keeps the last reading as a baseline and returns the
+    /// difference from it, giving per-interval counts when called
+    /// periodically. The baseline is held by this handle, so clones of
+    /// the socket, e.g. those held by its [`TxQueue`] and [`RxQueue`],
+    /// each track their own intervals, starting from wherever the
+    /// handle they were cloned from had got to.
+    pub fn stats_delta(&self) -> io::Result<XdpStatistics> {
+        let mut baseline = self.stats_baseline.lock().unwrap();
+
+        let stats = self.fd.xdp_statistics()?;
+
+        let delta = stats.since(&baseline);
+
+        *baseline = stats;
+
+        Ok(delta)
+    }
+
+    /// Turn promiscuous mode on or off for the interface this socket
+    /// is bound to, via `SIOCSIFFLAGS`, so that it receives all
+    /// traffic rather than just that addressed to it.
+    ///
+    /// If this turns promiscuous mode on, it's turned back off once
+    /// the last clone of the socket is dropped. If it was already on,
+    /// e.g. set by an administrator, it's left alone. Requires
+    /// `CAP_NET_ADMIN`, without which a
+    /// [`PermissionDenied`](io::ErrorKind::PermissionDenied) error is
+    /// returned.
+    ///
+    /// The interface flag is a single bit rather than a reference
+    /// count, so sockets on the same interface don't coordinate. Only
+    /// the socket which actually turned it on restores it, and does so
+    /// regardless of whether other sockets still want it, while
+    /// turning it off here affects every socket on the interface. If
+    /// several sockets need it, turn it on once for the lifetime of
+    /// the longest lived one.
+    pub fn set_promiscuous(&self, enabled: bool) -> io::Result<()> {
+        let if_index = self.bound_addr.ifindex;
+
+        let was_enabled = link::set_promiscuous(if_index, enabled)?;
+
+        let mut inner = self._inner.lock().unwrap();
+
+        if enabled && !was_enabled {
+            inner.promisc_restore = Some(if_index);
+        } else if !enabled {
+            inner.promisc_restore = None;
+        }
+
+        Ok(())
+    }
+
+    /// Bind the socket to the network device `if_name` via
+    /// `SO_BINDTODEVICE`.
+    ///
+    /// AF_XDP sockets are already tied to an interface and queue by
+    /// `bind`, so this doesn't change which packets reach the rings.
+    /// It's for copy mode setups which otherwise interact with the
+    /// network stack, and expect the socket's device binding to match.
+    ///
+    /// Requires `CAP_NET_RAW`, without which a
+    /// [`PermissionDenied`](io::ErrorKind::PermissionDenied) error is
+    /// returned. An empty `if_name` removes any existing binding.
+    pub fn bind_to_device(&self, if_name: &str) -> io::Result<()> {
+        if if_name.len() >= IFNAMSIZ || if_name.contains('\0') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid interface name {:?}", if_name),
+            ));
+        }
+
+        let err = unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                SOL_SOCKET,
+                SO_BINDTODEVICE,
+                if_name.as_ptr() as *const libc::c_void,
+                if_name.len() as libc::socklen_t,
+            )
+        };
+
+        if err != 0 {
+            let err = io::Error::last_os_error();
+
+            if err.raw_os_error() == Some(EPERM) {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "binding a socket to a device requires CAP_NET_RAW",
+                ));
+            }
+
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Read the producer and consumer positions of each of the
+    /// socket's rings, as last published to the kernel or by it.
+    ///
+    /// Intended for diagnostics, e.g. logging the state of the whole
+    /// pipeline when a stall is detected. The positions live in
+    /// separate cache lines so can't be read in a single atomic
+    /// operation, but they're read back to back with acquire ordering
+    /// to keep skew between them small. Consumers are read before
+    /// producers, so no ring will appear to have had more entries
+    /// consumed than produced.
+    ///
+    /// Positions cached locally by the queues but not yet submitted or
+    /// released aren't reflected.
+    pub fn ring_snapshot(&self) -> RingSnapshot {
+        // SAFETY: the rings are unmapped only once the last clone of
+        // this socket, which `self._inner` is shared with, is dropped.
+        unsafe { self.rings.snapshot() }
+    }
+
+    /// The `sockaddr_xdp` to pass to `sendto` when waking up the
+    /// kernel, if any.
+    #[inline]
+    pub(crate) fn wakeup_addr(&self) -> Option<sockaddr_xdp> {
+        if self.wakeup_with_address && self.wakeup_address_required() {
+            Some(self.bound_addr.to_sockaddr_xdp())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+impl Socket {
+    /// A `Socket` which was never created or bound, with its tx and rx
+    /// queues over `tx_q` and `rx_q`, for driving the queues against
+    /// the mock kernel in `umem::loopback`. Anything needing the file
+    /// descriptor, e.g. a wakeup, fails.
+    pub(crate) fn mock(
+        config: SocketConfig,
+        umem: &Umem,
+        tx_q: XskRingProd,
+        rx_q: XskRingCons,
+        fq: &XskRingProd,
+        cq: &XskRingCons,
+    ) -> (TxQueue, RxQueue) {
+        // SAFETY: the pointer is never dereferenced, nor deleted on
+        // drop.
+        let ptr = unsafe { XskSocket::new(NonNull::dangling()) };
+
+        let socket = Socket {
+            fd: Fd::new(-1, config.report_link_down()),
+            bind_mode: BindMode::Copy,
+            rx_queue_size: config.rx_queue_size(),
+            tx_queue_size: config.tx_queue_size(),
+            bound_addr: BoundAddr {
+                ifindex: 0,
+                queue_id: 0,
+                flags: 0,
+                shared_umem_fd: 0,
+            },
+            wakeup_with_address: config.wakeup_with_address(),
+            rings: RingPtrs::new(&rx_q, &tx_q, fq, cq),
+            stats_baseline: Mutex::new(XdpStatistics::default()),
+            _inner: Arc::new(Mutex::new(SocketInner::new(ptr, umem.clone()))),
+        };
+
+        let umem_config = umem.config();
+        let max_desc_len = (umem_config.frame_size().get() - umem_config.xdp_headroom()) as usize;
+
+        (
+            TxQueue::new(tx_q, socket.clone()),
+            RxQueue::new(rx_q, socket, max_desc_len),
+        )
+    }
 }
 
 impl Clone for Socket {
     fn clone(&self) -> Self {
         Self {
             fd: self.fd.clone(),
+            bind_mode: self.bind_mode,
+            rx_queue_size: self.rx_queue_size,
+            tx_queue_size: self.tx_queue_size,
+            bound_addr: self.bound_addr,
+            wakeup_with_address: self.wakeup_with_address,
+            rings: self.rings,
+            stats_baseline: Mutex::new(*self.stats_baseline.lock().unwrap()),
             _inner: self._inner.clone(),
         }
     }
 }
 
+/// The mode an AF_XDP [`Socket`] is bound in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindMode {
+    /// Packets are copied between the driver and the [`Umem`].
+    Copy,
+    /// The driver reads from and writes to the [`Umem`] directly.
+    ZeroCopy,
+}
+
 /// Error detailing why [`Socket`] creation failed.
 #[derive(Debug)]
 pub struct SocketCreateError {
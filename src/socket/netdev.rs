@@ -0,0 +1,353 @@
+//! A minimal generic netlink client for querying the kernel's
+//! `netdev` family, as defined in the linux source at
+//! `include/uapi/linux/netdev.h`.
+
+use bitflags::bitflags;
+use libc::{
+    AF_NETLINK, ENOENT, NETLINK_GENERIC, NLMSG_DONE, NLMSG_ERROR, NLM_F_REQUEST, SOCK_CLOEXEC,
+    SOCK_RAW,
+};
+use std::{
+    convert::TryInto,
+    io::{self, ErrorKind},
+    mem,
+    os::unix::prelude::RawFd,
+};
+
+use crate::util;
+
+const NLMSG_HDR_LEN: usize = 16;
+const GENL_HDR_LEN: usize = 4;
+const NLA_HDR_LEN: usize = 4;
+
+const GENL_ID_CTRL: u16 = 0x10;
+const CTRL_CMD_GETFAMILY: u8 = 3;
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+
+const NETDEV_FAMILY_NAME: &[u8] = b"netdev\0";
+const NETDEV_FAMILY_VERSION: u8 = 1;
+const NETDEV_CMD_DEV_GET: u8 = 1;
+const NETDEV_A_DEV_IFINDEX: u16 = 1;
+const NETDEV_A_DEV_XDP_FEATURES: u16 = 3;
+const NETDEV_A_DEV_XSK_FEATURES: u16 = 6;
+
+/// Set in a device's AF_XDP features if it supports TX checksum
+/// offload via TX metadata.
+pub(crate) const NETDEV_XSK_FLAGS_TX_CHECKSUM: u64 = 1 << 1;
+
+const RECV_BUF_LEN: usize = 8192;
+
+bitflags! {
+    /// The XDP features a device's driver reports supporting, i.e.
+    /// the `NETDEV_XDP_ACT_*` flags defined in the linux source at
+    /// `include/uapi/linux/netdev.h`. See [`xdp_act_flags`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct XdpActFlags: u64 {
+        /// Supports the basic XDP actions, i.e. `XDP_PASS`,
+        /// `XDP_DROP`, `XDP_ABORTED` and `XDP_TX`.
+        const NETDEV_XDP_ACT_BASIC = 1 << 0;
+        /// Supports the `XDP_REDIRECT` action, which is how packets
+        /// reach an AF_XDP socket.
+        const NETDEV_XDP_ACT_REDIRECT = 1 << 1;
+        /// Can be the target of an `XDP_REDIRECT` from another device.
+        const NETDEV_XDP_ACT_NDO_XMIT = 1 << 2;
+        /// Supports AF_XDP zero-copy mode.
+        const NETDEV_XDP_ACT_XSK_ZEROCOPY = 1 << 3;
+        /// Supports offloading XDP programs to the hardware.
+        const NETDEV_XDP_ACT_HW_OFFLOAD = 1 << 4;
+        /// Supports non-linear, i.e. multi-buffer, packets on receive.
+        const NETDEV_XDP_ACT_RX_SG = 1 << 5;
+        /// Supports non-linear packets when the target of an
+        /// `XDP_REDIRECT`.
+        const NETDEV_XDP_ACT_NDO_XMIT_SG = 1 << 6;
+    }
+}
+
+/// The XDP features reported by the driver of the interface with
+/// index `if_index`, as given by the kernel's `netdev` generic netlink
+/// family.
+///
+/// This is the authoritative source for what a device supports, e.g.
+/// checking for [`NETDEV_XDP_ACT_XSK_ZEROCOPY`] before binding with
+/// [`XDP_ZEROCOPY`] rather than trying it and seeing if it fails. Bits
+/// not yet known to this crate are kept as is.
+///
+/// Returns `None` if the features are unknown because the kernel
+/// predates them (added in 6.3), in which case there's nothing for it
+/// but to fall back to trial and error.
+///
+/// [`NETDEV_XDP_ACT_XSK_ZEROCOPY`]: XdpActFlags::NETDEV_XDP_ACT_XSK_ZEROCOPY
+/// [`XDP_ZEROCOPY`]: crate::config::BindFlags::XDP_ZEROCOPY
+pub fn xdp_act_flags(if_index: u32) -> io::Result<Option<XdpActFlags>> {
+    let features = dev_attr(if_index, NETDEV_A_DEV_XDP_FEATURES)?;
+
+    Ok(features.map(XdpActFlags::from_bits_retain))
+}
+
+/// The AF_XDP features (`NETDEV_A_DEV_XSK_FEATURES`) reported for the
+/// interface with index `ifindex`.
+///
+/// Returns `None` if the kernel predates the `netdev` family or this
+/// attribute (added in 6.8).
+pub(crate) fn xsk_features(ifindex: u32) -> io::Result<Option<u64>> {
+    dev_attr(ifindex, NETDEV_A_DEV_XSK_FEATURES)
+}
+
+/// The unsigned integer attribute `attr` of the `netdev` family's
+/// reply for the interface with index `ifindex`, or `None` if either
+/// the family or the attribute is missing.
+fn dev_attr(ifindex: u32, attr: u16) -> io::Result<Option<u64>> {
+    let sock = GenlSocket::new()?;
+
+    let family_id = match sock.resolve_family(NETDEV_FAMILY_NAME)? {
+        Some(family_id) => family_id,
+        None => return Ok(None),
+    };
+
+    let payload = sock.request(
+        family_id,
+        NETDEV_CMD_DEV_GET,
+        NETDEV_FAMILY_VERSION,
+        &[(NETDEV_A_DEV_IFINDEX, &ifindex.to_ne_bytes())],
+    )?;
+
+    Ok(find_attr(&payload, attr).and_then(read_uint))
+}
+
+/// A generic netlink socket, closed on drop.
+#[derive(Debug)]
+struct GenlSocket(RawFd);
+
+impl GenlSocket {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::socket(AF_NETLINK, SOCK_RAW | SOCK_CLOEXEC, NETLINK_GENERIC) };
+
+        if fd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Self(fd))
+        }
+    }
+
+    /// The id of the generic netlink family named `name`, or `None` if
+    /// the kernel doesn't know of it.
+    fn resolve_family(&self, name: &[u8]) -> io::Result<Option<u16>> {
+        let payload = match self.request(
+            GENL_ID_CTRL,
+            CTRL_CMD_GETFAMILY,
+            1,
+            &[(CTRL_ATTR_FAMILY_NAME, name)],
+        ) {
+            Ok(payload) => payload,
+            Err(e) if e.raw_os_error() == Some(ENOENT) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        match find_attr(&payload, CTRL_ATTR_FAMILY_ID) {
+            Some(id) if id.len() >= 2 => Ok(Some(u16::from_ne_bytes([id[0], id[1]]))),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "family id missing from generic netlink control response",
+            )),
+        }
+    }
+
+    /// Send a single request and return the attributes of the reply,
+    /// i.e. its payload following the generic netlink header.
+    fn request(
+        &self,
+        family: u16,
+        cmd: u8,
+        version: u8,
+        attrs: &[(u16, &[u8])],
+    ) -> io::Result<Vec<u8>> {
+        let msg = encode_request(family, cmd, version, attrs);
+
+        let ret = unsafe { libc::send(self.0, msg.as_ptr() as *const libc::c_void, msg.len(), 0) };
+
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buf = vec![0u8; RECV_BUF_LEN];
+
+        let len = loop {
+            let ret =
+                unsafe { libc::recv(self.0, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+
+            if ret >= 0 {
+                break ret as usize;
+            }
+
+            let err = io::Error::last_os_error();
+
+            if err.kind() != ErrorKind::Interrupted {
+                return Err(err);
+            }
+        };
+
+        decode_reply(&buf[..len])
+    }
+}
+
+impl Drop for GenlSocket {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+#[inline]
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn encode_request(family: u16, cmd: u8, version: u8, attrs: &[(u16, &[u8])]) -> Vec<u8> {
+    let mut msg = vec![0u8; NLMSG_HDR_LEN + GENL_HDR_LEN];
+
+    for (kind, value) in attrs {
+        let attr_len = NLA_HDR_LEN + value.len();
+
+        msg.extend_from_slice(&(attr_len as u16).to_ne_bytes());
+        msg.extend_from_slice(&kind.to_ne_bytes());
+        msg.extend_from_slice(value);
+        msg.resize(align4(msg.len()), 0);
+    }
+
+    let len = msg.len() as u32;
+
+    msg[0..4].copy_from_slice(&len.to_ne_bytes());
+    msg[4..6].copy_from_slice(&family.to_ne_bytes());
+    msg[6..8].copy_from_slice(&(NLM_F_REQUEST as u16).to_ne_bytes());
+    // Sequence number and port id are left as zero, the kernel fills
+    // in the latter.
+    msg[NLMSG_HDR_LEN] = cmd;
+    msg[NLMSG_HDR_LEN + 1] = version;
+
+    msg
+}
+
+fn decode_reply(buf: &[u8]) -> io::Result<Vec<u8>> {
+    let truncated = || io::Error::new(ErrorKind::InvalidData, "truncated netlink reply");
+
+    if buf.len() < NLMSG_HDR_LEN {
+        return Err(truncated());
+    }
+
+    let len = u32::from_ne_bytes(buf[0..4].try_into().unwrap()) as usize;
+    let kind = u16::from_ne_bytes([buf[4], buf[5]]) as i32;
+
+    if len < NLMSG_HDR_LEN || len > buf.len() {
+        return Err(truncated());
+    }
+
+    let body = &buf[NLMSG_HDR_LEN..len];
+
+    match kind {
+        NLMSG_ERROR => {
+            if body.len() < mem::size_of::<i32>() {
+                return Err(truncated());
+            }
+
+            let errno = -i32::from_ne_bytes(body[0..4].try_into().unwrap());
+
+            Err(io::Error::from_raw_os_error(errno))
+        }
+        NLMSG_DONE => Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "empty netlink reply",
+        )),
+        _ => {
+            if body.len() < GENL_HDR_LEN {
+                return Err(truncated());
+            }
+
+            Ok(body[GENL_HDR_LEN..].to_vec())
+        }
+    }
+}
+
+/// The value of the first attribute of type `kind` in `attrs`, if
+/// present.
+fn find_attr(mut attrs: &[u8], kind: u16) -> Option<&[u8]> {
+    while attrs.len() >= NLA_HDR_LEN {
+        let len = u16::from_ne_bytes([attrs[0], attrs[1]]) as usize;
+        // Mask off the nested and byte order flags
+        let attr_kind = u16::from_ne_bytes([attrs[2], attrs[3]]) & 0x3fff;
+
+        if len < NLA_HDR_LEN || len > attrs.len() {
+            return None;
+        }
+
+        if attr_kind == kind {
+            return Some(&attrs[NLA_HDR_LEN..len]);
+        }
+
+        attrs = &attrs[util::min_usize(align4(len), attrs.len())..];
+    }
+
+    None
+}
+
+/// Read a netlink `uint` attribute, which may be either 4 or 8 bytes.
+fn read_uint(value: &[u8]) -> Option<u64> {
+    match value.len() {
+        4 => Some(u32::from_ne_bytes(value.try_into().unwrap()) as u64),
+        8 => Some(u64::from_ne_bytes(value.try_into().unwrap())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_is_encoded_with_aligned_attributes() {
+        let msg = encode_request(GENL_ID_CTRL, CTRL_CMD_GETFAMILY, 1, &[(2, b"netdev\0")]);
+
+        // 16 header + 4 genl header + 4 attr header + 7 value + 1 pad
+        assert_eq!(msg.len(), 32);
+        assert_eq!(u32::from_ne_bytes(msg[0..4].try_into().unwrap()), 32);
+        assert_eq!(u16::from_ne_bytes([msg[4], msg[5]]), GENL_ID_CTRL);
+        assert_eq!(msg[16], CTRL_CMD_GETFAMILY);
+        assert_eq!(u16::from_ne_bytes([msg[20], msg[21]]), 11);
+        assert_eq!(&msg[24..31], b"netdev\0");
+    }
+
+    #[test]
+    fn attributes_are_found_and_decoded() {
+        let mut attrs = vec![];
+
+        // A 2 byte attribute, padded to 4
+        attrs.extend_from_slice(&6u16.to_ne_bytes());
+        attrs.extend_from_slice(&1u16.to_ne_bytes());
+        attrs.extend_from_slice(&[0xab, 0xcd, 0, 0]);
+
+        // An 8 byte uint
+        attrs.extend_from_slice(&12u16.to_ne_bytes());
+        attrs.extend_from_slice(&NETDEV_A_DEV_XSK_FEATURES.to_ne_bytes());
+        attrs.extend_from_slice(&NETDEV_XSK_FLAGS_TX_CHECKSUM.to_ne_bytes());
+
+        assert_eq!(find_attr(&attrs, 1), Some(&[0xab, 0xcd][..]));
+        assert_eq!(
+            find_attr(&attrs, NETDEV_A_DEV_XSK_FEATURES).and_then(read_uint),
+            Some(NETDEV_XSK_FLAGS_TX_CHECKSUM)
+        );
+        assert_eq!(find_attr(&attrs, 3), None);
+    }
+
+    #[test]
+    fn error_reply_is_decoded_as_os_error() {
+        let mut reply = vec![];
+
+        reply.extend_from_slice(&20u32.to_ne_bytes());
+        reply.extend_from_slice(&(NLMSG_ERROR as u16).to_ne_bytes());
+        reply.extend_from_slice(&[0; 10]);
+        reply.extend_from_slice(&(-ENOENT).to_ne_bytes());
+
+        let err = decode_reply(&reply).unwrap_err();
+
+        assert_eq!(err.raw_os_error(), Some(ENOENT));
+    }
+}
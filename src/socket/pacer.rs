@@ -0,0 +1,244 @@
+//! Rate limiting the transmit path.
+
+use std::{convert::TryInto, num::NonZeroU64, time::Instant};
+
+use crate::umem::frame::FrameDesc;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// A token bucket refilled at `rate` tokens per second, holding at
+/// most `burst` tokens.
+///
+/// Tokens are tracked in billionths so that refills over intervals
+/// shorter than a token's worth of time aren't lost to rounding.
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    rate: u128,
+    capacity: u128,
+    tokens: u128,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: NonZeroU64, burst: NonZeroU64, now: Instant) -> Self {
+        let capacity = burst.get() as u128 * NANOS_PER_SEC;
+
+        Self {
+            rate: rate.get() as u128,
+            capacity,
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    #[inline]
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_nanos();
+
+        self.tokens = self
+            .tokens
+            .saturating_add(elapsed.saturating_mul(self.rate))
+            .min(self.capacity);
+
+        self.last_refill = now;
+    }
+
+    /// Whole tokens currently available.
+    #[inline]
+    fn available(&self) -> u64 {
+        (self.tokens / NANOS_PER_SEC) as u64
+    }
+
+    #[inline]
+    fn take(&mut self, n: u64) {
+        self.tokens -= n as u128 * NANOS_PER_SEC;
+    }
+}
+
+/// Paces transmission to a configured packet rate, byte rate, or both,
+/// using token buckets refilled according to a monotonic clock.
+///
+/// Before submitting frames to the [`TxQueue`](crate::TxQueue), ask
+/// the pacer how many may go now via [`allow`](Self::allow) (packet
+/// rate only) or [`allow_descs`](Self::allow_descs) (packet and byte
+/// rate), and submit only that many. Anything held back can be retried
+/// later, once the buckets have had time to refill.
+///
+/// Each bucket starts full, so up to its burst size may be sent
+/// straight away. A `Pacer` with no rates set allows everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pacer {
+    packets: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Pacer {
+    /// Creates a new `Pacer` with no rate limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit transmission to `rate` packets per second, allowing
+    /// bursts of up to `burst` packets.
+    pub fn with_packet_rate(mut self, rate: NonZeroU64, burst: NonZeroU64) -> Self {
+        self.packets = Some(TokenBucket::new(rate, burst, Instant::now()));
+        self
+    }
+
+    /// Limit transmission to `rate` bytes per second, allowing bursts
+    /// of up to `burst` bytes.
+    ///
+    /// Only applied by [`allow_descs`](Self::allow_descs), since it
+    /// needs to know each frame's length. A frame longer than `burst`
+    /// will never be allowed.
+    pub fn with_byte_rate(mut self, rate: NonZeroU64, burst: NonZeroU64) -> Self {
+        self.bytes = Some(TokenBucket::new(rate, burst, Instant::now()));
+        self
+    }
+
+    /// Returns how many of `n` frames may be sent now under the packet
+    /// rate limit, and consumes that many tokens. Any byte rate limit
+    /// is ignored.
+    #[inline]
+    pub fn allow(&mut self, n: usize) -> usize {
+        self.allow_at(n, Instant::now())
+    }
+
+    /// Returns how many frames from the start of `descs` may be sent
+    /// now under both the packet and byte rate limits, and consumes
+    /// the corresponding tokens. A frame's size is taken to be the
+    /// length of its packet data.
+    ///
+    /// Frames are allowed strictly in order, so a large frame which
+    /// doesn't fit holds back any smaller ones behind it.
+    #[inline]
+    pub fn allow_descs(&mut self, descs: &[FrameDesc]) -> usize {
+        self.allow_descs_at(descs, Instant::now())
+    }
+
+    fn allow_at(&mut self, n: usize, now: Instant) -> usize {
+        match self.packets.as_mut() {
+            Some(packets) => {
+                packets.refill(now);
+
+                let cnt = std::cmp::min(n as u64, packets.available());
+
+                packets.take(cnt);
+
+                cnt as usize
+            }
+            None => n,
+        }
+    }
+
+    fn allow_descs_at(&mut self, descs: &[FrameDesc], now: Instant) -> usize {
+        let max_packets = self.packets.as_mut().map_or(u64::MAX, |packets| {
+            packets.refill(now);
+            packets.available()
+        });
+
+        let mut max_bytes = self.bytes.as_mut().map_or(u64::MAX, |bytes| {
+            bytes.refill(now);
+            bytes.available()
+        });
+
+        let mut cnt = 0;
+        let mut total_bytes = 0;
+
+        for desc in descs
+            .iter()
+            .take(max_packets.try_into().unwrap_or(usize::MAX))
+        {
+            let len = desc.lengths().data() as u64;
+
+            if len > max_bytes {
+                break;
+            }
+
+            max_bytes -= len;
+            total_bytes += len;
+            cnt += 1;
+        }
+
+        if let Some(packets) = self.packets.as_mut() {
+            packets.take(cnt as u64);
+        }
+
+        if let Some(bytes) = self.bytes.as_mut() {
+            bytes.take(total_bytes);
+        }
+
+        cnt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn desc_with_len(len: usize) -> FrameDesc {
+        let mut desc = FrameDesc::default();
+        desc.lengths.data = len;
+        desc
+    }
+
+    #[test]
+    fn unlimited_pacer_allows_everything() {
+        let mut pacer = Pacer::new();
+
+        assert_eq!(pacer.allow(100), 100);
+        assert_eq!(pacer.allow_descs(&[desc_with_len(9000); 8]), 8);
+    }
+
+    #[test]
+    fn packet_rate_refills_over_time() {
+        let mut pacer =
+            Pacer::new().with_packet_rate(1000.try_into().unwrap(), 10.try_into().unwrap());
+
+        let start = pacer.packets.unwrap().last_refill;
+
+        // Starts with a full burst
+        assert_eq!(pacer.allow_at(15, start), 10);
+        assert_eq!(pacer.allow_at(15, start), 0);
+
+        // 1000pps is one packet per millisecond
+        assert_eq!(pacer.allow_at(15, start + Duration::from_micros(2500)), 2);
+
+        // Half a token was carried over
+        assert_eq!(pacer.allow_at(15, start + Duration::from_micros(3000)), 1);
+
+        // Refills cap out at the burst size
+        assert_eq!(pacer.allow_at(15, start + Duration::from_secs(5)), 10);
+    }
+
+    #[test]
+    fn byte_rate_holds_back_frames_which_do_not_fit() {
+        let mut pacer =
+            Pacer::new().with_byte_rate(1000.try_into().unwrap(), 1500.try_into().unwrap());
+
+        let start = pacer.bytes.unwrap().last_refill;
+
+        let descs = [desc_with_len(1000), desc_with_len(1000), desc_with_len(10)];
+
+        assert_eq!(pacer.allow_descs_at(&descs, start), 1);
+
+        // 500 bytes left, so the next 1000 byte frame blocks the rest
+        assert_eq!(pacer.allow_descs_at(&descs[1..], start), 0);
+
+        assert_eq!(
+            pacer.allow_descs_at(&descs[1..], start + Duration::from_millis(510)),
+            2
+        );
+    }
+
+    #[test]
+    fn both_limits_apply_to_descs() {
+        let mut pacer = Pacer::new()
+            .with_packet_rate(1.try_into().unwrap(), 2.try_into().unwrap())
+            .with_byte_rate(1.try_into().unwrap(), 10_000.try_into().unwrap());
+
+        assert_eq!(pacer.allow_descs(&[desc_with_len(100); 4]), 2);
+    }
+}
@@ -0,0 +1,176 @@
+//! Iterating over received packets as typed header views.
+
+use crate::{
+    frame_utils::{EthHdr, Ipv4Hdr, UdpHdr, ETH_P_IPV4, IPPROTO_UDP},
+    ring::XskRingCons,
+    umem::{
+        frame::{Data, FrameDesc, SegmentLengths},
+        Umem,
+    },
+};
+
+use super::rx_queue::DescCheck;
+
+/// A received packet, exposing its headers as lazily parsed views.
+///
+/// Nothing is parsed until one of [`eth`](Self::eth),
+/// [`ipv4`](Self::ipv4) or [`udp`](Self::udp) is called, and each
+/// parses from scratch, so skipping uninteresting packets costs
+/// nothing beyond reading the descriptor.
+#[derive(Debug)]
+pub struct ParsedPacket<'umem> {
+    desc: FrameDesc,
+    data: Data<'umem>,
+}
+
+impl ParsedPacket<'_> {
+    /// The descriptor of the frame holding this packet. Once done
+    /// with the packet, the frame should be handed back to the
+    /// [`FillQueue`](crate::FillQueue) or
+    /// [`TxQueue`](crate::TxQueue) with it.
+    #[inline]
+    pub fn desc(&self) -> &FrameDesc {
+        &self.desc
+    }
+
+    /// The raw packet bytes.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        self.data.contents()
+    }
+
+    /// The packet's Ethernet header, if it's long enough to hold one.
+    #[inline]
+    pub fn eth(&self) -> Option<EthHdr<'_>> {
+        EthHdr::parse(self.data())
+    }
+
+    /// The packet's IPv4 header, if it's an IPv4 packet.
+    #[inline]
+    pub fn ipv4(&self) -> Option<Ipv4Hdr<'_>> {
+        self.eth()
+            .filter(|eth| eth.ethertype() == ETH_P_IPV4)
+            .and_then(|eth| Ipv4Hdr::parse(eth.payload()))
+    }
+
+    /// The packet's UDP header, if it's an unfragmented UDP over IPv4
+    /// packet or the first fragment of one.
+    #[inline]
+    pub fn udp(&self) -> Option<UdpHdr<'_>> {
+        self.ipv4()
+            .filter(|ip| ip.protocol() == IPPROTO_UDP && ip.fragment_offset() == 0)
+            .and_then(|ip| UdpHdr::parse(ip.payload()))
+    }
+}
+
+/// Iterator over packets read from the [`RxQueue`](super::RxQueue)'s
+/// ring. Returned by
+/// [`RxQueue::parsed_iter`](super::RxQueue::parsed_iter).
+///
+/// Entries yielded are released back to the kernel on drop. Any which
+/// were available but not reached are left on the ring, to be read
+/// by the next consume.
+#[derive(Debug)]
+pub struct ParsedIter<'a> {
+    ring: &'a mut XskRingCons,
+    desc_check: &'a mut DescCheck,
+    umem: &'a Umem,
+    idx: u32,
+    peeked: u32,
+    yielded: u32,
+    done: bool,
+}
+
+impl<'a> ParsedIter<'a> {
+    pub(super) fn new(
+        ring: &'a mut XskRingCons,
+        desc_check: &'a mut DescCheck,
+        umem: &'a Umem,
+        max: usize,
+    ) -> Self {
+        let nb = max as u32;
+
+        let mut idx = 0;
+
+        let peeked = if nb > 0 {
+            let cnt = unsafe { libxdp_sys::xsk_ring_cons__peek(ring.as_mut(), nb, &mut idx) };
+
+            ring.record_peek(idx, nb, cnt);
+
+            cnt
+        } else {
+            0
+        };
+
+        Self {
+            ring,
+            desc_check,
+            umem,
+            idx,
+            peeked,
+            yielded: 0,
+            done: false,
+        }
+    }
+}
+
+impl<'a> Iterator for ParsedIter<'a> {
+    type Item = ParsedPacket<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.yielded == self.peeked {
+            return None;
+        }
+
+        let idx = self.idx.wrapping_add(self.yielded);
+
+        let recv_pkt_desc = unsafe { *libxdp_sys::xsk_ring_cons__rx_desc(self.ring.as_ref(), idx) };
+
+        // Stop short of a descriptor which looks incomplete, as
+        // `RxQueue::consume` does.
+        if !self.desc_check.accept(idx, recv_pkt_desc.len) {
+            self.done = true;
+            return None;
+        }
+
+        self.yielded += 1;
+
+        let desc = FrameDesc {
+            addr: recv_pkt_desc.addr as usize,
+            options: recv_pkt_desc.options,
+            lengths: SegmentLengths {
+                headroom: 0,
+                data: recv_pkt_desc.len as usize,
+            },
+        };
+
+        // SAFETY: the unsafe contract of `RxQueue::parsed_iter`
+        // guarantees the frame belongs to `umem` and isn't otherwise
+        // being written to.
+        let data = unsafe { self.umem.data(&desc) };
+
+        Some(ParsedPacket { desc, data })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            (0, Some(0))
+        } else {
+            (0, Some((self.peeked - self.yielded) as usize))
+        }
+    }
+}
+
+impl Drop for ParsedIter<'_> {
+    fn drop(&mut self) {
+        let r = self.ring.as_mut();
+
+        if self.peeked > self.yielded {
+            unsafe { libxdp_sys::xsk_ring_cons__cancel(r, self.peeked - self.yielded) };
+        }
+
+        if self.yielded > 0 {
+            unsafe { libxdp_sys::xsk_ring_cons__release(r, self.yielded) };
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Sending received frames straight back out again.
+
+use std::{io, num::NonZeroUsize};
+
+use crate::umem::{frame::FrameDesc, CompQueue, FillQueue, Umem};
+
+use super::{RxQueue, TxQueue};
+
+/// Drives the receive, rewrite, transmit and recycle lifecycle of a
+/// single socket which sends received frames back out in place, e.g.
+/// an L2 reflector swapping MAC addresses.
+///
+/// Each call to [`process`](Self::process) moves frames along one
+/// step:
+///
+///  1. Frames whose transmission has completed are taken off the
+///  [`CompQueue`] and handed back to the [`FillQueue`].
+///
+///  2. Received frames are taken off the [`RxQueue`] and passed to a
+///  closure to rewrite in place.
+///
+///  3. The rewritten frames are submitted to the [`TxQueue`] using the
+///  same descriptors, so no data is copied.
+///
+/// The important part is that a frame only ever returns to the fill
+/// ring once its completion has arrived. Refilling it any earlier
+/// would let the kernel receive a new packet into it while it's still
+/// waiting to be sent, corrupting whatever goes out on the wire and
+/// eventually the frame pool itself.
+#[derive(Debug)]
+pub struct Reflector {
+    umem: Umem,
+    rx_q: RxQueue,
+    tx_q: TxQueue,
+    fq: FillQueue,
+    cq: CompQueue,
+    batch: Vec<FrameDesc>,
+    to_send: Vec<FrameDesc>,
+    to_fill: Vec<FrameDesc>,
+    in_flight: usize,
+}
+
+impl Reflector {
+    /// Creates a new `Reflector` over a socket's queues, consuming at
+    /// most `batch_size` frames from each ring at a time.
+    ///
+    /// `descs` are the frames to circulate. They're handed to the fill
+    /// ring by the first call to [`process`](Self::process).
+    ///
+    /// # Safety
+    ///
+    /// The queues must all belong to the same socket, and `descs` must
+    /// describe frames of its [`Umem`] which aren't in use anywhere
+    /// else, including on any of the queues.
+    pub unsafe fn new(
+        rx_q: RxQueue,
+        tx_q: TxQueue,
+        fq: FillQueue,
+        cq: CompQueue,
+        descs: Vec<FrameDesc>,
+        batch_size: NonZeroUsize,
+    ) -> Self {
+        let umem = fq.umem().clone();
+
+        Self {
+            umem,
+            rx_q,
+            tx_q,
+            fq,
+            cq,
+            batch: vec![FrameDesc::default(); batch_size.get()],
+            to_send: Vec::with_capacity(batch_size.get()),
+            to_fill: descs,
+            in_flight: 0,
+        }
+    }
+
+    /// Carry out a single, non-blocking pass of the lifecycle,
+    /// returning the number of frames submitted for transmission.
+    ///
+    /// `f` is called with the packet data of each received frame and
+    /// may rewrite it in place. If it returns `false` the frame is
+    /// dropped rather than sent, and goes straight back to the fill
+    /// ring.
+    ///
+    /// To wait for packets between passes, use [`poll`](Self::poll).
+    pub fn process<F>(&mut self, mut f: F) -> io::Result<usize>
+    where
+        F: FnMut(&mut [u8]) -> bool,
+    {
+        // SAFETY: the unsafe contract of `new` guarantees that the
+        // queues share a `Umem` and that we have sole ownership of the
+        // frames passing between them.
+        unsafe {
+            let completed = self.cq.consume(&mut self.batch);
+
+            // Saturate in case of completions for frames sent before
+            // this reflector took over the queues.
+            self.in_flight = self.in_flight.saturating_sub(completed);
+            self.to_fill.extend_from_slice(&self.batch[..completed]);
+
+            self.refill()?;
+
+            let received = self.rx_q.consume(&mut self.batch);
+
+            for desc in self.batch[..received].iter_mut() {
+                if f(self.umem.data_mut(desc).contents_mut()) {
+                    self.to_send.push(*desc);
+                } else {
+                    self.to_fill.push(*desc);
+                }
+            }
+
+            self.transmit()
+        }
+    }
+
+    /// Polls the socket, returning `true` if there are frames to
+    /// receive.
+    #[inline]
+    pub fn poll(&mut self, poll_timeout: i32) -> io::Result<bool> {
+        self.rx_q.poll(poll_timeout)
+    }
+
+    /// The number of frames submitted for transmission whose
+    /// completions haven't yet been seen.
+    #[inline]
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// The number of rewritten frames still waiting for room on the
+    /// tx ring.
+    #[inline]
+    pub fn pending_tx(&self) -> usize {
+        self.to_send.len()
+    }
+
+    /// The number of free frames still waiting for room on the fill
+    /// ring.
+    #[inline]
+    pub fn pending_fill(&self) -> usize {
+        self.to_fill.len()
+    }
+
+    /// Hand as many free frames to the fill ring as there's room for.
+    ///
+    /// # Safety
+    ///
+    /// See [`FillQueue::produce_partial`].
+    unsafe fn refill(&mut self) -> io::Result<()> {
+        let submitted = unsafe { self.fq.produce_partial(&self.to_fill) };
+
+        self.to_fill.drain(..submitted);
+
+        if submitted > 0 && self.fq.needs_wakeup() {
+            self.fq.wakeup(self.rx_q.fd_mut(), 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Submit as many rewritten frames to the tx ring as there's room
+    /// for.
+    ///
+    /// # Safety
+    ///
+    /// See [`TxQueue::produce`].
+    unsafe fn transmit(&mut self) -> io::Result<usize> {
+        let tx_q = &mut self.tx_q;
+
+        let submitted = produce_what_fits(&self.to_send, |descs| unsafe { tx_q.produce(descs) });
+
+        self.to_send.drain(..submitted);
+        self.in_flight += submitted;
+
+        if submitted > 0 && self.tx_q.needs_wakeup() {
+            self.tx_q.wakeup()?;
+        }
+
+        Ok(submitted)
+    }
+}
+
+/// Produce `descs` via `produce`, which submits either all of the
+/// descriptors it's given or none of them, falling back to one at a
+/// time if they don't all fit. Returns the number submitted.
+///
+/// Only needed for the tx ring, the fill ring has
+/// [`FillQueue::produce_partial`].
+fn produce_what_fits<F>(descs: &[FrameDesc], mut produce: F) -> usize
+where
+    F: FnMut(&[FrameDesc]) -> usize,
+{
+    if descs.is_empty() {
+        return 0;
+    }
+
+    if produce(descs) == descs.len() {
+        descs.len()
+    } else {
+        descs
+            .iter()
+            .take_while(|desc| produce(std::slice::from_ref(*desc)) == 1)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn produce_falls_back_to_one_at_a_time() {
+        let descs = [FrameDesc::default(); 5];
+
+        let mut room = 3;
+
+        let submitted = produce_what_fits(&descs, |descs| {
+            if descs.len() <= room {
+                room -= descs.len();
+                descs.len()
+            } else {
+                0
+            }
+        });
+
+        assert_eq!(submitted, 3);
+        assert_eq!(room, 0);
+
+        assert_eq!(produce_what_fits(&descs[..2], |descs| descs.len()), 2);
+        assert_eq!(produce_what_fits(&[], |_| unreachable!()), 0);
+    }
+}
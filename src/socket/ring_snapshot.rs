@@ -0,0 +1,186 @@
+//! Point in time views of a socket's ring positions.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::ring::{XskRingCons, XskRingProd};
+
+/// Pointers to a ring's shared producer and consumer positions.
+#[derive(Debug, Clone, Copy)]
+struct PositionPtrs {
+    producer: *const u32,
+    consumer: *const u32,
+}
+
+impl PositionPtrs {
+    fn new(producer: *const u32, consumer: *const u32, is_null: bool) -> Option<Self> {
+        if is_null {
+            None
+        } else {
+            Some(Self { producer, consumer })
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The ring's memory must still be mapped.
+    #[inline]
+    unsafe fn load_consumer(&self) -> u32 {
+        unsafe { (*(self.consumer as *const AtomicU32)).load(Ordering::Acquire) }
+    }
+
+    /// # Safety
+    ///
+    /// The ring's memory must still be mapped.
+    #[inline]
+    unsafe fn load_producer(&self) -> u32 {
+        unsafe { (*(self.producer as *const AtomicU32)).load(Ordering::Acquire) }
+    }
+}
+
+/// The shared positions of each of a [`Socket`](super::Socket)'s
+/// rings, used to build a [`RingSnapshot`].
+#[derive(Debug, Clone, Copy)]
+pub(super) struct RingPtrs {
+    rx: Option<PositionPtrs>,
+    tx: Option<PositionPtrs>,
+    fill: Option<PositionPtrs>,
+    comp: Option<PositionPtrs>,
+}
+
+// SAFETY: the pointers refer to the rings' memory mapped regions,
+// which are kept alive by the socket holding this struct. They're
+// only ever read from, atomically.
+unsafe impl Send for RingPtrs {}
+unsafe impl Sync for RingPtrs {}
+
+impl RingPtrs {
+    pub(super) fn new(
+        rx: &XskRingCons,
+        tx: &XskRingProd,
+        fill: &XskRingProd,
+        comp: &XskRingCons,
+    ) -> Self {
+        let cons = |r: &XskRingCons| {
+            PositionPtrs::new(r.as_ref().producer, r.as_ref().consumer, r.is_ring_null())
+        };
+
+        let prod = |r: &XskRingProd| {
+            PositionPtrs::new(r.as_ref().producer, r.as_ref().consumer, r.is_ring_null())
+        };
+
+        Self {
+            rx: cons(rx),
+            tx: prod(tx),
+            fill: prod(fill),
+            comp: cons(comp),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The rings' memory must still be mapped.
+    pub(super) unsafe fn snapshot(&self) -> RingSnapshot {
+        let rings = [self.rx, self.tx, self.fill, self.comp];
+
+        // Read every consumer before any producer. A consumer never
+        // overtakes its producer, so each ring's producer is then at
+        // least its consumer even if both moved between reads.
+        let mut consumers = [0; 4];
+
+        for (pos, ring) in consumers.iter_mut().zip(rings.iter()) {
+            if let Some(ring) = ring {
+                *pos = unsafe { ring.load_consumer() };
+            }
+        }
+
+        let mut positions = [None; 4];
+
+        for ((pos, ring), consumer) in positions.iter_mut().zip(rings.iter()).zip(consumers) {
+            if let Some(ring) = ring {
+                *pos = Some(RingPosition {
+                    producer: unsafe { ring.load_producer() },
+                    consumer,
+                });
+            }
+        }
+
+        RingSnapshot {
+            rx: positions[0].unwrap_or_default(),
+            tx: positions[1].unwrap_or_default(),
+            fill: positions[2],
+            comp: positions[3],
+        }
+    }
+}
+
+/// The producer and consumer positions of a single ring.
+///
+/// Positions are free running counters which wrap on overflow, rather
+/// than indices into the ring.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RingPosition {
+    producer: u32,
+    consumer: u32,
+}
+
+impl RingPosition {
+    /// The number of entries produced to the ring.
+    #[inline]
+    pub fn producer(&self) -> u32 {
+        self.producer
+    }
+
+    /// The number of entries consumed from the ring.
+    #[inline]
+    pub fn consumer(&self) -> u32 {
+        self.consumer
+    }
+
+    /// The number of entries produced but not yet consumed.
+    #[inline]
+    pub fn pending(&self) -> u32 {
+        self.producer.wrapping_sub(self.consumer)
+    }
+}
+
+/// The positions of all of a [`Socket`](super::Socket)'s rings, read
+/// together by [`Socket::ring_snapshot`](super::Socket::ring_snapshot).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RingSnapshot {
+    rx: RingPosition,
+    tx: RingPosition,
+    fill: Option<RingPosition>,
+    comp: Option<RingPosition>,
+}
+
+impl RingSnapshot {
+    /// The rx ring's positions.
+    #[inline]
+    pub fn rx(&self) -> RingPosition {
+        self.rx
+    }
+
+    /// The tx ring's positions.
+    #[inline]
+    pub fn tx(&self) -> RingPosition {
+        self.tx
+    }
+
+    /// The fill ring's positions.
+    ///
+    /// [`None`] if the socket was bound to an interface and queue
+    /// already using a shared [`Umem`](crate::Umem), in which case it
+    /// uses the fill ring returned for that earlier socket.
+    #[inline]
+    pub fn fill(&self) -> Option<RingPosition> {
+        self.fill
+    }
+
+    /// The completion ring's positions.
+    ///
+    /// [`None`] under the same conditions as [`fill`](Self::fill).
+    #[inline]
+    pub fn comp(&self) -> Option<RingPosition> {
+        self.comp
+    }
+}
@@ -1,8 +1,66 @@
-use std::io;
+use std::{io, mem, ops::ControlFlow, time::Instant};
 
-use crate::{ring::XskRingCons, umem::frame::FrameDesc};
+#[cfg(feature = "ring-stats")]
+use crate::ring::RingStats;
+use crate::{
+    ring::XskRingCons,
+    umem::{
+        frame::{FrameDesc, SegmentLengths},
+        FillQueue, Umem,
+    },
+    util,
+};
 
-use super::{fd::Fd, Socket};
+use super::{fd::Fd, ParsedIter, Socket};
+
+/// Number of frames consumed per iteration of [`RxQueue::run`].
+const RUN_BATCH_SIZE: usize = 64;
+
+/// How long [`RxQueue::run`] waits in `poll` before checking the rings
+/// again.
+const RUN_POLL_TIMEOUT_MS: i32 = 100;
+
+/// Sanity checks descriptors read off the rx ring, tracking any
+/// which looked incomplete.
+#[derive(Debug)]
+pub(super) struct DescCheck {
+    max_len: usize,
+    suspect_idx: Option<u32>,
+    truncated: u64,
+}
+
+impl DescCheck {
+    fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            suspect_idx: None,
+            truncated: 0,
+        }
+    }
+
+    /// Whether a descriptor of length `len` is well formed, i.e. is
+    /// non-empty and fits within a frame.
+    #[inline]
+    fn is_valid(&self, len: u32) -> bool {
+        len != 0 && len as usize <= self.max_len
+    }
+
+    /// Whether the descriptor at ring index `idx` with length `len`
+    /// should be consumed. A descriptor that fails the check is
+    /// rejected once and accepted if seen at the same index again,
+    /// however bad its length, so this delays rather than filters.
+    #[inline]
+    pub(super) fn accept(&mut self, idx: u32, len: u32) -> bool {
+        if self.is_valid(len) || self.suspect_idx == Some(idx) {
+            self.suspect_idx = None;
+            true
+        } else {
+            self.suspect_idx = Some(idx);
+            self.truncated += 1;
+            false
+        }
+    }
+}
 
 /// The receiving side of an AF_XDP [`Socket`].
 ///
@@ -12,11 +70,56 @@ use super::{fd::Fd, Socket};
 pub struct RxQueue {
     ring: XskRingCons,
     socket: Socket,
+    desc_check: DescCheck,
+    consume_buf: Vec<FrameDesc>,
 }
 
 impl RxQueue {
-    pub(super) fn new(ring: XskRingCons, socket: Socket) -> Self {
-        Self { ring, socket }
+    pub(super) fn new(ring: XskRingCons, socket: Socket, max_desc_len: usize) -> Self {
+        Self {
+            ring,
+            socket,
+            desc_check: DescCheck::new(max_desc_len),
+            consume_buf: Vec::new(),
+        }
+    }
+
+    /// Give this queue its own buffer of `capacity` descriptors for
+    /// [`consume_owned`](Self::consume_owned) to consume into.
+    ///
+    /// The buffer is allocated once here and reused by every call, so
+    /// a receive loop built on `consume_owned` doesn't need to manage
+    /// a descriptor buffer of its own. Replaces any buffer set
+    /// previously.
+    pub fn with_consume_buffer(mut self, capacity: usize) -> Self {
+        self.consume_buf = vec![FrameDesc::default(); capacity];
+        self
+    }
+
+    /// Same as [`consume`](Self::consume), but consumes up to `max`
+    /// frames into the queue's own buffer and returns a view of those
+    /// consumed, valid until the next call.
+    ///
+    /// At most as many frames as the capacity given to
+    /// [`with_consume_buffer`](Self::with_consume_buffer) are consumed
+    /// per call, and none at all if it hasn't been called. The
+    /// descriptors are plain values, so copy out any which need to
+    /// outlive the view, e.g. to hand back to the [`FillQueue`].
+    ///
+    /// # Safety
+    ///
+    /// See [`consume`](Self::consume).
+    #[inline]
+    pub unsafe fn consume_owned(&mut self, max: usize) -> &[FrameDesc] {
+        let len = util::min_usize(max, self.consume_buf.len());
+
+        let mut buf = mem::take(&mut self.consume_buf);
+
+        let cnt = unsafe { self.consume(&mut buf[..len]) };
+
+        self.consume_buf = buf;
+
+        &self.consume_buf[..cnt]
     }
 
     /// Update `descs` with information on which [`Umem`] frames have
@@ -27,6 +130,21 @@ impl RxQueue {
     /// the length of `descs`. Entries will be updated sequentially
     /// from the start of `descs` until the end.
     ///
+    /// As a defensive measure, each descriptor's length is checked to
+    /// be non-zero and to fit within a frame. If one doesn't, the batch
+    /// is cut short just before it, leaving it on the ring to be read
+    /// again by the next call, and the truncation is counted in
+    /// [`truncated_batches`](Self::truncated_batches). This only gives
+    /// a descriptor which was still being written a second chance, it
+    /// doesn't filter anything out: the next call hands the same
+    /// descriptor out as is, whatever its length, so a genuinely bad
+    /// length such as zero or more than a frame is merely delayed by a
+    /// call. Anything relying on lengths being in range must still
+    /// check them, or use
+    /// [`consume_classified`](Self::consume_classified), which does set
+    /// bad descriptors aside. See [`truncated_batches`] for why this
+    /// shouldn't normally happen.
+    ///
     /// Once the contents of the consumed frames have been dealt with
     /// and are no longer required, the frames should eventually be
     /// added back on to either the [`FillQueue`] or the [`TxQueue`].
@@ -39,6 +157,7 @@ impl RxQueue {
     /// [`Umem`]: crate::Umem
     /// [`FillQueue`]: crate::FillQueue
     /// [`TxQueue`]: crate::TxQueue
+    /// [`truncated_batches`]: Self::truncated_batches
     #[inline]
     pub unsafe fn consume(&mut self, descs: &mut [FrameDesc]) -> usize {
         let nb = descs.len() as u32;
@@ -51,25 +170,51 @@ impl RxQueue {
 
         let cnt = unsafe { libxdp_sys::xsk_ring_cons__peek(self.ring.as_mut(), nb, &mut idx) };
 
-        if cnt > 0 {
-            for desc in descs.iter_mut().take(cnt as usize) {
-                let recv_pkt_desc =
-                    unsafe { libxdp_sys::xsk_ring_cons__rx_desc(self.ring.as_ref(), idx) };
-
-                unsafe {
-                    desc.addr = (*recv_pkt_desc).addr as usize;
-                    desc.lengths.data = (*recv_pkt_desc).len as usize;
-                    desc.lengths.headroom = 0;
-                    desc.options = (*recv_pkt_desc).options;
-                }
+        self.ring.record_peek(idx, nb, cnt);
+
+        let mut good = 0;
+
+        for desc in descs.iter_mut().take(cnt as usize) {
+            let recv_pkt_desc =
+                unsafe { *libxdp_sys::xsk_ring_cons__rx_desc(self.ring.as_ref(), idx) };
 
-                idx += 1;
+            if !self.desc_check.accept(idx, recv_pkt_desc.len) {
+                break;
             }
 
-            unsafe { libxdp_sys::xsk_ring_cons__release(self.ring.as_mut(), cnt) };
+            desc.addr = recv_pkt_desc.addr as usize;
+            desc.lengths.data = recv_pkt_desc.len as usize;
+            desc.lengths.headroom = 0;
+            desc.options = recv_pkt_desc.options;
+
+            idx += 1;
+            good += 1;
         }
 
-        cnt as usize
+        unsafe { self.release_checked(cnt, good) };
+
+        good as usize
+    }
+
+    /// Same as [`consume`], but also returns whether more frames were
+    /// left waiting on the ring afterwards.
+    ///
+    /// If so, `descs` was the limit rather than the traffic, and
+    /// calling again straight away will find more without needing to
+    /// poll first. This checks the ring's [`depth`] once consumed, so
+    /// also picks up anything which arrived in the meantime.
+    ///
+    /// # Safety
+    ///
+    /// See [`consume`].
+    ///
+    /// [`consume`]: Self::consume
+    /// [`depth`]: Self::depth
+    #[inline]
+    pub unsafe fn consume_with_more(&mut self, descs: &mut [FrameDesc]) -> (usize, bool) {
+        let cnt = unsafe { self.consume(descs) };
+
+        (cnt, self.ring.depth() > 0)
     }
 
     /// Same as [`consume`] but for a single frame descriptor.
@@ -85,21 +230,139 @@ impl RxQueue {
 
         let cnt = unsafe { libxdp_sys::xsk_ring_cons__peek(self.ring.as_mut(), 1, &mut idx) };
 
+        self.ring.record_peek(idx, 1, cnt);
+
+        let mut good = 0;
+
         if cnt > 0 {
             let recv_pkt_desc =
-                unsafe { libxdp_sys::xsk_ring_cons__rx_desc(self.ring.as_ref(), idx) };
+                unsafe { *libxdp_sys::xsk_ring_cons__rx_desc(self.ring.as_ref(), idx) };
 
-            unsafe {
-                desc.addr = (*recv_pkt_desc).addr as usize;
-                desc.lengths.data = (*recv_pkt_desc).len as usize;
+            if self.desc_check.accept(idx, recv_pkt_desc.len) {
+                desc.addr = recv_pkt_desc.addr as usize;
+                desc.lengths.data = recv_pkt_desc.len as usize;
                 desc.lengths.headroom = 0;
-                desc.options = (*recv_pkt_desc).options;
+                desc.options = recv_pkt_desc.options;
+
+                good = 1;
+            }
+        }
+
+        unsafe { self.release_checked(cnt, good) };
+
+        good as usize
+    }
+
+    /// Same as [`consume`], but instead of holding back descriptors
+    /// which look malformed, partitions everything read into `good`
+    /// and `bad`. Reads at most `max` entries off the ring and returns
+    /// the number of elements of `good` updated.
+    ///
+    /// A descriptor is taken to be well formed if its length is
+    /// non-zero and no more than [`max_desc_len`], which defaults to
+    /// the frame size less the XDP headroom. Well formed descriptors
+    /// are written to `good` in order, and this stops early if it
+    /// fills up, leaving the remaining entries on the ring. Malformed
+    /// ones, e.g. from a buggy XDP program, are appended to `bad`
+    /// as is, to be inspected and then recycled however suits.
+    ///
+    /// Unlike `consume`, malformed descriptors aren't given a second
+    /// chance and aren't counted in [`truncated_batches`].
+    ///
+    /// # Safety
+    ///
+    /// See [`consume`]. This applies to the frames of both `good` and
+    /// `bad`, so a malformed descriptor's address should be checked
+    /// before its frame is accessed.
+    ///
+    /// [`consume`]: Self::consume
+    /// [`max_desc_len`]: Self::max_desc_len
+    /// [`truncated_batches`]: Self::truncated_batches
+    pub unsafe fn consume_classified(
+        &mut self,
+        good: &mut [FrameDesc],
+        bad: &mut Vec<FrameDesc>,
+        max: usize,
+    ) -> usize {
+        let nb = max as u32;
+
+        if nb == 0 {
+            return 0;
+        }
+
+        let mut idx = 0;
+
+        let cnt = unsafe { libxdp_sys::xsk_ring_cons__peek(self.ring.as_mut(), nb, &mut idx) };
+
+        self.ring.record_peek(idx, nb, cnt);
+
+        let mut n_good = 0;
+        let mut taken = 0;
+
+        while taken < cnt {
+            let recv_pkt_desc = unsafe {
+                *libxdp_sys::xsk_ring_cons__rx_desc(self.ring.as_ref(), idx.wrapping_add(taken))
+            };
+
+            let desc = FrameDesc {
+                addr: recv_pkt_desc.addr as usize,
+                options: recv_pkt_desc.options,
+                lengths: SegmentLengths {
+                    headroom: 0,
+                    data: recv_pkt_desc.len as usize,
+                },
+            };
+
+            if self.desc_check.is_valid(recv_pkt_desc.len) {
+                match good.get_mut(n_good) {
+                    Some(slot) => *slot = desc,
+                    None => break,
+                }
+
+                n_good += 1;
+            } else {
+                bad.push(desc);
             }
 
-            unsafe { libxdp_sys::xsk_ring_cons__release(self.ring.as_mut(), cnt) };
+            taken += 1;
+        }
+
+        unsafe { self.release_checked(cnt, taken) };
+
+        n_good
+    }
+
+    /// The largest descriptor length considered well formed by
+    /// [`consume`](Self::consume) and
+    /// [`consume_classified`](Self::consume_classified).
+    #[inline]
+    pub fn max_desc_len(&self) -> usize {
+        self.desc_check.max_len
+    }
+
+    /// Set the largest descriptor length considered well formed, e.g.
+    /// to flag packets larger than the MTU. Defaults to the frame size
+    /// less the XDP headroom.
+    #[inline]
+    pub fn set_max_desc_len(&mut self, max_len: usize) {
+        self.desc_check.max_len = max_len;
+    }
+
+    /// Release the first `good` of `cnt` peeked entries, and un-peek
+    /// the rest so they're read again by the next consume.
+    ///
+    /// # Safety
+    ///
+    /// `cnt` entries must have just been peeked, with `good <= cnt`.
+    #[inline]
+    unsafe fn release_checked(&mut self, cnt: u32, good: u32) {
+        if good < cnt {
+            unsafe { libxdp_sys::xsk_ring_cons__cancel(self.ring.as_mut(), cnt - good) };
         }
 
-        cnt as usize
+        if good > 0 {
+            unsafe { libxdp_sys::xsk_ring_cons__release(self.ring.as_mut(), good) };
+        }
     }
 
     /// Same as [`consume`] but poll first to check if there is
@@ -142,12 +405,247 @@ impl RxQueue {
         }
     }
 
+    /// Repeatedly poll and [`consume`] until either some frames are
+    /// received or `deadline` passes. Returns the number of elements
+    /// of `descs` updated, which will be zero if the deadline passed
+    /// without anything arriving.
+    ///
+    /// The poll timeout is recomputed from the time remaining on each
+    /// iteration, so this may span any number of poll cycles, e.g. if
+    /// a poll is interrupted by a signal or reports the socket as
+    /// readable but there turns out to be nothing to consume. An empty
+    /// `descs` returns zero immediately.
+    ///
+    /// # Safety
+    ///
+    /// See [`consume`].
+    ///
+    /// [`consume`]: Self::consume
+    pub unsafe fn recv_deadline(
+        &mut self,
+        descs: &mut [FrameDesc],
+        deadline: Instant,
+    ) -> io::Result<usize> {
+        if descs.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let cnt = unsafe { self.consume(descs) };
+
+            if cnt > 0 {
+                return Ok(cnt);
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                return Ok(0);
+            }
+
+            // Round up so we don't spin on a sub-millisecond remainder
+            let remaining_ms = (deadline - now).as_nanos().div_ceil(1_000_000);
+            let poll_timeout = if remaining_ms > i32::MAX as u128 {
+                i32::MAX
+            } else {
+                remaining_ms as i32
+            };
+
+            // An interrupted poll returns `false`, in which case we just
+            // go round again with the timeout updated.
+            self.poll(poll_timeout)?;
+        }
+    }
+
+    /// Receive packets indefinitely, calling `f` with the contents of
+    /// each received frame, until `f` returns [`ControlFlow::Break`].
+    ///
+    /// Takes care of the plumbing needed to keep packets flowing:
+    /// frames are consumed from this queue in batches, polling while
+    /// there's nothing to read (which also wakes the kernel if
+    /// required), and each frame is returned to `fill` once `f` has
+    /// been called on it. If `fill` doesn't have room for all of a
+    /// batch the remainder are held on to and resubmitted on the next
+    /// iteration.
+    ///
+    /// `fill` must already hold some frames for anything to be
+    /// received. Each frame is passed to `f` separately, so packets
+    /// spanning multiple frames are seen as several calls. On break,
+    /// any frames still waiting for space in `fill` will no longer be
+    /// circulated, which can only happen if the fill ring is smaller
+    /// than the number of frames in use.
+    ///
+    /// # Safety
+    ///
+    /// `fill` must be tied to the same [`Umem`] as this `RxQueue`, and
+    /// the frames it holds must not be accessed elsewhere while this
+    /// runs. See also [`consume`].
+    ///
+    /// [`Umem`]: crate::Umem
+    /// [`consume`]: Self::consume
+    pub unsafe fn run<F>(&mut self, fill: &mut FillQueue, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> ControlFlow<()>,
+    {
+        let umem = fill.umem().clone();
+
+        let mut descs = vec![FrameDesc::default(); RUN_BATCH_SIZE];
+        let mut pending: Vec<FrameDesc> = Vec::with_capacity(RUN_BATCH_SIZE);
+
+        loop {
+            let cnt = unsafe { self.poll_and_consume(&mut descs, RUN_POLL_TIMEOUT_MS)? };
+
+            let mut stop = false;
+
+            for desc in descs[..cnt].iter() {
+                // SAFETY: the frame was just consumed, so belongs to
+                // us until handed back to the fill queue.
+                let data = unsafe { umem.data(desc) };
+
+                if f(data.contents()).is_break() {
+                    stop = true;
+                    break;
+                }
+            }
+
+            pending.extend_from_slice(&descs[..cnt]);
+
+            if !pending.is_empty() {
+                unsafe { self.refill(fill, &mut pending)? };
+            }
+
+            if stop {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Submit as many of `pending` to `fill` as there's room for,
+    /// removing them from `pending`.
+    ///
+    /// # Safety
+    ///
+    /// See [`FillQueue::produce_partial`].
+    unsafe fn refill(
+        &mut self,
+        fill: &mut FillQueue,
+        pending: &mut Vec<FrameDesc>,
+    ) -> io::Result<()> {
+        let submitted = unsafe { fill.produce_partial(pending) };
+
+        pending.drain(..submitted);
+
+        if submitted > 0 && fill.needs_wakeup() {
+            fill.wakeup(&mut self.socket.fd, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Consume up to `max` received packets, yielding each as a
+    /// [`ParsedPacket`](super::ParsedPacket) whose headers are parsed
+    /// on demand.
+    ///
+    /// Descriptors are checked in the same way as by
+    /// [`consume`](Self::consume), with iteration ending early at one
+    /// which looks incomplete. Those yielded are released back to the
+    /// kernel when the returned iterator is dropped, while any not
+    /// reached stay on the ring for the next consume. As with
+    /// `consume`, each packet's frame should eventually be handed back
+    /// to the [`FillQueue`] or [`TxQueue`](crate::TxQueue) via its
+    /// [`desc`](super::ParsedPacket::desc).
+    ///
+    /// # Safety
+    ///
+    /// `umem` must be the [`Umem`] this `RxQueue` is tied to, and the
+    /// packets must not be accessed once their frames have been handed
+    /// back to the kernel.
+    #[inline]
+    pub unsafe fn parsed_iter<'a>(&'a mut self, umem: &'a Umem, max: usize) -> ParsedIter<'a> {
+        ParsedIter::new(&mut self.ring, &mut self.desc_check, umem, max)
+    }
+
+    /// The number of received frames waiting on the ring to be
+    /// consumed, i.e. how backed up this queue is right now.
+    ///
+    /// This is a single atomic load of the kernel's producer position
+    /// from shared memory, with acquire ordering, compared against
+    /// this queue's own consumer position. No syscall is made, so it's
+    /// cheap enough to sample on every iteration of a receive loop.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.ring.depth() as usize
+    }
+
+    /// The number of times a consume was cut short by a descriptor
+    /// that looked incompletely written, i.e. had a zero length or a
+    /// length larger than a frame. See [`consume`](Self::consume).
+    ///
+    /// The ring's producer index is read with acquire ordering by
+    /// `libxdp` (pairing with the kernel's release store after writing
+    /// the descriptors), so every descriptor up to it should already be
+    /// fully visible and this is expected to stay at zero. A non-zero
+    /// count points to a memory ordering problem, e.g. a platform or
+    /// `libxdp` build where that assumption doesn't hold.
+    #[inline]
+    pub fn truncated_batches(&self) -> u64 {
+        self.desc_check.truncated
+    }
+
     /// Polls the socket, returning `true` if there is data to read.
     #[inline]
     pub fn poll(&mut self, poll_timeout: i32) -> io::Result<bool> {
+        #[cfg(feature = "ring-stats")]
+        self.ring.record_call();
+
         self.socket.fd.poll_read(poll_timeout)
     }
 
+    /// The raw flags word shared with the kernel for the rx ring.
+    ///
+    /// The kernel currently only defines [`XDP_RING_NEED_WAKEUP`] (bit
+    /// 0), which it uses on the fill and tx rings, so this is expected
+    /// to be zero. It is exposed for debugging and in case of flags
+    /// added by future kernels.
+    ///
+    /// [`XDP_RING_NEED_WAKEUP`]: libxdp_sys::XDP_RING_NEED_WAKEUP
+    #[inline]
+    pub fn ring_flags(&self) -> u32 {
+        self.ring.flags()
+    }
+
+    /// Userspace side statistics for this queue's ring. See
+    /// [`RingStats`] for details.
+    ///
+    /// Only available with the `ring-stats` feature enabled.
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn ring_stats(&self) -> RingStats {
+        self.ring.stats()
+    }
+
+    /// A moving average of the number of frames consumed between
+    /// consecutive calls to [`poll`](Self::poll), including those made
+    /// by the `poll_and_*` methods, weighted towards recent polls.
+    ///
+    /// An advisory metric for tuning the poll strategy: a value close
+    /// to zero means most polls find nothing to do, e.g. because the
+    /// timeout is too short or polling too eager, at the cost of a
+    /// syscall each time.
+    ///
+    /// Only available with the `ring-stats` feature enabled.
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn frames_per_poll(&self) -> f64 {
+        self.ring.frames_per_call()
+    }
+
+    /// The underlying [`Socket`].
+    #[inline]
+    pub fn socket(&self) -> &Socket {
+        &self.socket
+    }
+
     /// A reference to the underlying [`Socket`]'s file descriptor.
     #[inline]
     pub fn fd(&self) -> &Fd {
@@ -160,3 +658,25 @@ impl RxQueue {
         &mut self.socket.fd
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incomplete_desc_is_rejected_once_then_accepted() {
+        let mut check = DescCheck::new(100);
+
+        assert!(check.accept(0, 10));
+
+        assert!(!check.accept(1, 0));
+        assert_eq!(check.truncated, 1);
+
+        // Seen again at the same index, so let it through
+        assert!(check.accept(1, 0));
+
+        assert!(!check.accept(2, 101));
+        assert!(check.accept(2, 50));
+        assert_eq!(check.truncated, 2);
+    }
+}
@@ -0,0 +1,207 @@
+//! Sending packets without managing frames by hand.
+
+use std::{
+    io::{self, Write},
+    thread,
+};
+
+use crate::umem::{frame::FrameDesc, CompQueue, Umem};
+
+use super::TxQueue;
+
+/// Number of completed frames reclaimed at a time.
+const RECLAIM_BATCH_SIZE: usize = 64;
+
+/// Sends packets from a pool of frames which it recycles itself, so
+/// that sending is just a matter of handing over the bytes.
+///
+/// Each [`send`](Self::send) takes a free frame from the pool, copies
+/// the packet into it and submits it to the tx ring, waking up the
+/// kernel if need be. Frames are returned to the pool as their sends
+/// complete, which is checked for on every call.
+///
+/// This suits applications which don't need to build packets in place,
+/// as every packet is copied in. When no frames are free, either
+/// because they're all in flight or have been submitted but not yet
+/// sent, `send` waits for one to complete by default. The wait is a
+/// busy spin, kicking the kernel and yielding the thread between
+/// checks but never sleeping, and has no timeout, so keeps a core busy
+/// for as long as the kernel takes to send. Call
+/// [`set_nonblocking`](Self::set_nonblocking) to have it fail with
+/// [`WouldBlock`](io::ErrorKind::WouldBlock) instead.
+#[derive(Debug)]
+pub struct Sender {
+    umem: Umem,
+    tx_q: TxQueue,
+    cq: CompQueue,
+    free: Vec<FrameDesc>,
+    nonblocking: bool,
+}
+
+impl Sender {
+    /// Creates a new `Sender` over a socket's tx and completion
+    /// queues, whose pool starts out as the frames in `descs`.
+    ///
+    /// # Safety
+    ///
+    /// The queues must belong to the same socket, and `descs` must
+    /// describe frames of its [`Umem`] which aren't in use anywhere
+    /// else, including on either of the queues. Every completion is
+    /// treated as a frame of the pool's, so nothing else may be sent
+    /// via `tx_q` while the `Sender` has it.
+    pub unsafe fn new(tx_q: TxQueue, cq: CompQueue, descs: Vec<FrameDesc>) -> Self {
+        let umem = cq.umem().clone();
+
+        Self {
+            umem,
+            tx_q,
+            cq,
+            free: descs,
+            nonblocking: false,
+        }
+    }
+
+    /// Whether [`send`](Self::send) fails with
+    /// [`WouldBlock`](io::ErrorKind::WouldBlock) rather than spinning
+    /// when there's no free frame to send from, or no room on the tx
+    /// ring. Off by default.
+    pub fn set_nonblocking(&mut self, nonblocking: bool) {
+        self.nonblocking = nonblocking;
+    }
+
+    /// The number of frames currently free to send from, as of the last
+    /// time completions were reclaimed.
+    #[inline]
+    pub fn free_frames(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Send `bytes` as a single packet. Fails with
+    /// [`InvalidInput`](io::ErrorKind::InvalidInput) if it won't fit in
+    /// a frame, in which case nothing is sent.
+    ///
+    /// Returns once the packet is on the tx ring and the kernel has
+    /// been woken up if it needs to be, not once it has been sent.
+    pub fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let mut desc = self.next_frame()?;
+
+        // SAFETY: `desc` is from the pool, so free to be written to,
+        // and the unsafe contract of `new` guarantees it belongs to
+        // the queue's `Umem`.
+        let submitted = unsafe { write_packet(&self.umem, &mut desc, bytes) }
+            .and_then(|()| unsafe { produce_spinning(&mut self.tx_q, &desc, self.nonblocking) });
+
+        if let Err(err) = submitted {
+            self.free.push(desc);
+
+            return Err(err);
+        }
+
+        if self.tx_q.needs_wakeup() {
+            self.tx_q.wakeup()?;
+        }
+
+        Ok(())
+    }
+
+    /// Unwraps the underlying queues and the frames currently in the
+    /// pool. Frames still in flight aren't included, and should be
+    /// reclaimed from the completion queue.
+    pub fn into_inner(self) -> (TxQueue, CompQueue, Vec<FrameDesc>) {
+        (self.tx_q, self.cq, self.free)
+    }
+
+    /// Move every frame on the completion ring back to the pool.
+    fn reclaim(&mut self) {
+        let mut buf = [FrameDesc::default(); RECLAIM_BATCH_SIZE];
+
+        loop {
+            // SAFETY: only frames sent from the pool are on the
+            // completion ring, so they're ours again once completed.
+            let completed = unsafe { self.cq.consume(&mut buf) };
+
+            self.free.extend_from_slice(&buf[..completed]);
+
+            if completed < buf.len() {
+                break;
+            }
+        }
+    }
+
+    /// Take a free frame from the pool, kicking the kernel until one
+    /// has completed if need be.
+    fn next_frame(&mut self) -> io::Result<FrameDesc> {
+        loop {
+            self.reclaim();
+
+            if let Some(desc) = self.free.pop() {
+                return Ok(desc);
+            }
+
+            // Sends only complete once the kernel gets round to them
+            self.tx_q.wakeup()?;
+
+            if self.nonblocking {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            thread::yield_now();
+        }
+    }
+}
+
+/// Copy `bytes` into the data segment of the frame `desc` points at,
+/// replacing its contents. Fails with
+/// [`InvalidInput`](io::ErrorKind::InvalidInput), leaving the frame
+/// untouched, if they won't fit.
+///
+/// # Safety
+///
+/// See [`Umem::data_mut`].
+pub(super) unsafe fn write_packet(
+    umem: &Umem,
+    desc: &mut FrameDesc,
+    bytes: &[u8],
+) -> io::Result<()> {
+    let mut data = unsafe { umem.data_mut(desc) };
+    let mut cursor = data.cursor();
+
+    cursor.set_pos(0);
+
+    if bytes.len() > cursor.buf_len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "packet is larger than a frame",
+        ));
+    }
+
+    cursor.write_all(bytes)
+}
+
+/// Submit `desc` to `tx_q`, kicking the kernel and spinning for as
+/// long as the ring is full. If `nonblocking`, fails with
+/// [`WouldBlock`](io::ErrorKind::WouldBlock) instead of spinning.
+///
+/// The frame has only been submitted if this returns `Ok`. Either way
+/// the kernel may still need waking up to send it.
+///
+/// # Safety
+///
+/// See [`TxQueue::produce`].
+pub(super) unsafe fn produce_spinning(
+    tx_q: &mut TxQueue,
+    desc: &FrameDesc,
+    nonblocking: bool,
+) -> io::Result<()> {
+    while unsafe { tx_q.produce_one(desc) } == 0 {
+        if nonblocking {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        tx_q.wakeup()?;
+
+        thread::yield_now();
+    }
+
+    Ok(())
+}
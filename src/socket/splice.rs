@@ -0,0 +1,127 @@
+//! Forwarding frames between two sockets sharing a UMEM.
+
+use std::io;
+
+use crate::umem::{frame::FrameDesc, CompQueue, FillQueue};
+
+use super::{RxQueue, TxQueue};
+
+/// Number of descriptors moved through the on-stack buffer at a time.
+const SPLICE_BATCH_SIZE: usize = 64;
+
+/// Forward up to `max` frames received on socket A straight out of
+/// socket B, without copying, where both sockets share a
+/// [`Umem`](crate::Umem). Returns the number of frames forwarded.
+///
+/// Each call moves frames one step further along their lifecycle:
+///
+///  1. Frames whose transmission on B has completed are taken off
+///  `comp_b` and handed back to `fq_a`, so A can receive into them
+///  again. Only as many are taken as `fq_a` has room for, the rest
+///  stay on `comp_b` until the next call.
+///
+///  2. Frames received on `rx_a` are submitted to `tx_b` using the
+///  same descriptors. Slots on `tx_b` are reserved first and only as
+///  many frames are taken off `rx_a` as there are slots, so nothing is
+///  ever held back in between.
+///
+/// A frame therefore only returns to A's fill ring once B is done
+/// sending it. Either queue is woken up if it needs to be. To forward
+/// in both directions, call this a second time with the roles of the
+/// sockets swapped.
+///
+/// # Safety
+///
+/// All four queues must belong to sockets sharing the one
+/// [`Umem`](crate::Umem), and `fq_a` must be the fill queue `rx_a`
+/// receives from. Every completion on `comp_b` is treated as a frame
+/// of A's, so `tx_b` must only ever be used through this function,
+/// and no other frames may be in flight on it.
+pub unsafe fn splice(
+    rx_a: &mut RxQueue,
+    tx_b: &mut TxQueue,
+    comp_b: &mut CompQueue,
+    fq_a: &mut FillQueue,
+    max: usize,
+) -> io::Result<usize> {
+    let mut buf = [FrameDesc::default(); SPLICE_BATCH_SIZE];
+
+    // SAFETY: the unsafe contract of this function guarantees the
+    // frames on `comp_b` are A's, and so may be received into again.
+    let recycled = unsafe { recycle(comp_b, fq_a, &mut buf, max) };
+
+    if recycled > 0 && fq_a.needs_wakeup() {
+        fq_a.wakeup(rx_a.fd_mut(), 0)?;
+    }
+
+    let mut tx = tx_b.begin(max);
+
+    while tx.len() < tx.capacity() {
+        let want = std::cmp::min(tx.capacity() - tx.len(), SPLICE_BATCH_SIZE);
+
+        // SAFETY: the queues share a `Umem`, and the frames received
+        // are ours until handed to `tx`.
+        let received = unsafe { rx_a.consume(&mut buf[..want]) };
+
+        for desc in buf[..received].iter() {
+            // There's a slot reserved for every frame received
+            let pushed = unsafe { tx.push(desc) };
+            debug_assert!(pushed);
+        }
+
+        if received < want {
+            break;
+        }
+    }
+
+    let len = tx.len();
+    let forwarded = tx.commit(len);
+
+    if forwarded > 0 && tx_b.needs_wakeup() {
+        tx_b.wakeup()?;
+    }
+
+    Ok(forwarded)
+}
+
+/// Move up to `max` completed frames from `comp_b` to `fq_a`, no more
+/// than `fq_a` has room for. Returns the number moved.
+///
+/// # Safety
+///
+/// See [`splice`].
+unsafe fn recycle(
+    comp_b: &mut CompQueue,
+    fq_a: &mut FillQueue,
+    buf: &mut [FrameDesc],
+    max: usize,
+) -> usize {
+    let mut recycled = 0;
+
+    while recycled < max {
+        let want = std::cmp::min(max - recycled, buf.len());
+        let room = std::cmp::min(want, fq_a.nb_free(want));
+
+        if room == 0 {
+            break;
+        }
+
+        let completed = unsafe { comp_b.consume(&mut buf[..room]) };
+
+        if completed == 0 {
+            break;
+        }
+
+        // Fits, since there was room for at least this many
+        let produced = unsafe { fq_a.produce(&buf[..completed]) };
+        debug_assert_eq!(produced, completed);
+
+        recycled += completed;
+
+        if completed < room {
+            break;
+        }
+    }
+
+    recycled
+}
@@ -0,0 +1,187 @@
+//! A packet at a time, through the standard I/O traits.
+
+use std::{
+    io::{self, Read, Write},
+    thread,
+};
+
+use crate::umem::{frame::FrameDesc, CompQueue, FillQueue, Umem};
+
+use super::{sender, RxQueue, TxQueue};
+
+/// Adapts a socket's queues to [`Read`] and [`Write`], so that it can
+/// be used much like a datagram socket: each [`read`] returns the
+/// bytes of the next packet received, and each [`write`] sends one
+/// packet.
+///
+/// This is a convenience for prototyping and for tools which only
+/// need to send or receive the odd packet, not a way to get anywhere
+/// near line rate. Every call handles exactly one frame, with the
+/// packet copied in or out of it, and may make a syscall of its own,
+/// so none of the batching the rings are designed around takes place.
+/// Anything performance sensitive should use the queues directly.
+///
+/// The frames given to [`new`](Self::new) are split in two. The first
+/// half is used to receive into, and each frame goes straight back to
+/// the fill ring once its packet has been read. The second half is
+/// used to send from, and each frame is reclaimed from the completion
+/// ring once sent.
+///
+/// [`read`]: Read::read
+/// [`write`]: Write::write
+#[derive(Debug)]
+pub struct XskStream {
+    umem: Umem,
+    rx_q: RxQueue,
+    tx_q: TxQueue,
+    fq: FillQueue,
+    cq: CompQueue,
+    to_fill: Vec<FrameDesc>,
+    tx_free: Vec<FrameDesc>,
+}
+
+impl XskStream {
+    /// Creates a new `XskStream` over a socket's queues, circulating
+    /// the frames in `descs`.
+    ///
+    /// # Safety
+    ///
+    /// The queues must all belong to the same socket, and `descs` must
+    /// describe frames of its [`Umem`] which aren't in use anywhere
+    /// else, including on any of the queues.
+    ///
+    /// # Panics
+    ///
+    /// If there are fewer than two frames in `descs`, as there needs to
+    /// be at least one to send from and one to receive into.
+    pub unsafe fn new(
+        rx_q: RxQueue,
+        tx_q: TxQueue,
+        fq: FillQueue,
+        cq: CompQueue,
+        mut descs: Vec<FrameDesc>,
+    ) -> Self {
+        assert!(descs.len() >= 2, "need frames to both send and receive");
+
+        let umem = fq.umem().clone();
+
+        let tx_free = descs.split_off(descs.len() / 2);
+
+        Self {
+            umem,
+            rx_q,
+            tx_q,
+            fq,
+            cq,
+            to_fill: descs,
+            tx_free,
+        }
+    }
+
+    /// Unwraps the underlying queues, e.g. to move on to using them
+    /// directly. Frames currently on any of the rings stay there.
+    pub fn into_inner(self) -> (RxQueue, TxQueue, FillQueue, CompQueue) {
+        (self.rx_q, self.tx_q, self.fq, self.cq)
+    }
+
+    /// Hand as many free rx frames to the fill ring as there's room
+    /// for.
+    fn refill(&mut self) -> io::Result<()> {
+        // SAFETY: the unsafe contract of `new` guarantees these frames
+        // belong to the queue's `Umem` and are ours to hand over.
+        let submitted = unsafe { self.fq.produce_partial(&self.to_fill) };
+
+        self.to_fill.drain(..submitted);
+
+        if submitted > 0 && self.fq.needs_wakeup() {
+            self.fq.wakeup(self.rx_q.fd_mut(), 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Take back a free tx frame, kicking the kernel until one has
+    /// completed if need be.
+    fn next_tx_frame(&mut self) -> io::Result<FrameDesc> {
+        loop {
+            if let Some(desc) = self.tx_free.pop() {
+                return Ok(desc);
+            }
+
+            let mut desc = FrameDesc::default();
+
+            // SAFETY: only frames sent by this stream are on the
+            // completion ring, so it's ours again once completed.
+            if unsafe { self.cq.consume_one(&mut desc) } == 1 {
+                return Ok(desc);
+            }
+
+            self.tx_q.wakeup()?;
+
+            thread::yield_now();
+        }
+    }
+}
+
+impl Read for XskStream {
+    /// Waits for the next packet and copies it into `buf`, returning
+    /// its length. As with a datagram socket, if `buf` is too small
+    /// the rest of the packet is discarded.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.refill()?;
+
+        let mut desc = FrameDesc::default();
+
+        // SAFETY: the unsafe contract of `new` guarantees the queues
+        // share a `Umem`.
+        while unsafe { self.rx_q.consume_one(&mut desc) } == 0 {
+            self.rx_q.poll(-1)?;
+        }
+
+        let data = unsafe { self.umem.data(&desc) };
+
+        let len = data.contents().len().min(buf.len());
+
+        buf[..len].copy_from_slice(&data.contents()[..len]);
+
+        self.to_fill.push(desc);
+        self.refill()?;
+
+        Ok(len)
+    }
+}
+
+impl Write for XskStream {
+    /// Sends all of `buf` as a single packet. Fails with
+    /// [`InvalidInput`](io::ErrorKind::InvalidInput) if it won't fit in
+    /// a frame.
+    ///
+    /// Returns once the packet is on the tx ring and the kernel has
+    /// been woken up, not once it has been sent. If every tx frame is
+    /// in flight this spins until one completes.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut desc = self.next_tx_frame()?;
+
+        // SAFETY: `desc` is one of our frames, free to be written to,
+        // and only this stream sends from this socket's tx ring.
+        let submitted = unsafe { sender::write_packet(&self.umem, &mut desc, buf) }
+            .and_then(|()| unsafe { sender::produce_spinning(&mut self.tx_q, &desc, false) });
+
+        if let Err(err) = submitted {
+            self.tx_free.push(desc);
+
+            return Err(err);
+        }
+
+        if self.tx_q.needs_wakeup() {
+            self.tx_q.wakeup()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Wakes up the kernel to send anything still on the tx ring.
+    fn flush(&mut self) -> io::Result<()> {
+        self.tx_q.wakeup()
+    }
+}
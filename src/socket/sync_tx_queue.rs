@@ -0,0 +1,105 @@
+//! Sharing a single tx ring between multiple threads.
+
+use std::{io, sync::Mutex};
+
+use crate::umem::frame::FrameDesc;
+
+use super::{tx_queue, Socket, TxQueue};
+
+/// A [`TxQueue`] which may be submitted to from multiple threads at
+/// once, e.g. behind an [`Arc`](std::sync::Arc).
+///
+/// The tx ring is single producer, so calls to [`produce`] are
+/// serialised behind a lock. Waking up the kernel only makes a
+/// `sendto` call on the socket's file descriptor, which is safe to do
+/// concurrently, so [`wakeup`] takes no lock at all.
+///
+/// This is a convenience for applications which can't be structured
+/// around one transmitting thread per socket, and it isn't free. Every
+/// submission takes the lock, which is uncontended at best and bounces
+/// its cache line between cores at worst, and threads queue up behind
+/// each other while one of them writes its descriptors. Where
+/// throughput matters, prefer giving each thread its own socket and
+/// [`TxQueue`], or funnelling frames to a single transmitting thread,
+/// and submit in batches large enough to amortise the locking here.
+///
+/// [`produce`]: Self::produce
+/// [`wakeup`]: Self::wakeup
+#[derive(Debug)]
+pub struct SyncTxQueue {
+    queue: Mutex<TxQueue>,
+    socket: Socket,
+}
+
+impl SyncTxQueue {
+    /// Wraps `tx_q` so that it may be shared between threads.
+    pub fn new(tx_q: TxQueue) -> Self {
+        let socket = tx_q.socket().clone();
+
+        Self {
+            queue: Mutex::new(tx_q),
+            socket,
+        }
+    }
+
+    /// Same as [`TxQueue::produce`], waiting for any other thread
+    /// currently submitting to finish first.
+    ///
+    /// # Safety
+    ///
+    /// See [`TxQueue::produce`]. Note that this also rules out
+    /// submitting the same frame from two threads at once.
+    #[inline]
+    pub unsafe fn produce(&self, descs: &[FrameDesc]) -> usize {
+        unsafe { self.queue.lock().unwrap().produce(descs) }
+    }
+
+    /// Same as [`TxQueue::produce_and_wakeup`]. Only the submission
+    /// itself is done under the lock, the wakeup is made after it has
+    /// been released.
+    ///
+    /// # Safety
+    ///
+    /// See [`produce`](Self::produce).
+    #[inline]
+    pub unsafe fn produce_and_wakeup(&self, descs: &[FrameDesc]) -> io::Result<usize> {
+        let (cnt, needs_wakeup) = {
+            let mut tx_q = self.queue.lock().unwrap();
+
+            let cnt = unsafe { tx_q.produce(descs) };
+
+            (cnt, tx_q.needs_wakeup())
+        };
+
+        if needs_wakeup {
+            self.wakeup()?;
+        }
+
+        Ok(cnt)
+    }
+
+    /// Same as [`TxQueue::wakeup`]. Doesn't take the lock, so never
+    /// waits on a thread which is submitting.
+    #[inline]
+    pub fn wakeup(&self) -> io::Result<()> {
+        tx_queue::wakeup(&self.socket)
+    }
+
+    /// Same as [`TxQueue::needs_wakeup`].
+    #[inline]
+    pub fn needs_wakeup(&self) -> bool {
+        self.queue.lock().unwrap().needs_wakeup()
+    }
+
+    /// The underlying [`Socket`].
+    #[inline]
+    pub fn socket(&self) -> &Socket {
+        &self.socket
+    }
+
+    /// Unwraps the underlying [`TxQueue`], e.g. once all but one of
+    /// the transmitting threads have finished.
+    pub fn into_inner(self) -> TxQueue {
+        self.queue.into_inner().unwrap()
+    }
+}
@@ -1,9 +1,13 @@
 use libc::{EAGAIN, EBUSY, ENETDOWN, ENOBUFS, MSG_DONTWAIT};
-use std::{io, os::unix::prelude::AsRawFd, ptr};
+#[cfg(debug_assertions)]
+use log::warn;
+use std::{io, mem, num::NonZeroU32, os::unix::prelude::AsRawFd, ptr};
 
+#[cfg(feature = "ring-stats")]
+use crate::ring::RingStats;
 use crate::{ring::XskRingProd, umem::frame::FrameDesc, util};
 
-use super::{fd::Fd, Socket};
+use super::{fd::Fd, InFlightLimiter, Socket, TxTransaction};
 
 /// The transmitting side of an AF_XDP [`Socket`].
 ///
@@ -13,11 +17,18 @@ use super::{fd::Fd, Socket};
 pub struct TxQueue {
     ring: XskRingProd,
     socket: Socket,
+    #[cfg(debug_assertions)]
+    stall_detector: StallDetector,
 }
 
 impl TxQueue {
     pub(super) fn new(ring: XskRingProd, socket: Socket) -> Self {
-        Self { ring, socket }
+        Self {
+            ring,
+            socket,
+            #[cfg(debug_assertions)]
+            stall_detector: StallDetector::new(NonZeroU32::new(DEFAULT_STALL_WARNING_THRESHOLD)),
+        }
     }
 
     /// Let the kernel know that the frames described by `descs` are
@@ -55,6 +66,8 @@ impl TxQueue {
 
         let cnt = unsafe { libxdp_sys::xsk_ring_prod__reserve(self.ring.as_mut(), nb, &mut idx) };
 
+        self.ring.record_reserve(idx, nb, cnt);
+
         if cnt > 0 {
             for desc in descs.iter().take(cnt as usize) {
                 let send_pkt_desc =
@@ -71,6 +84,9 @@ impl TxQueue {
             unsafe { libxdp_sys::xsk_ring_prod__submit(self.ring.as_mut(), cnt) };
         }
 
+        #[cfg(debug_assertions)]
+        self.stall_detector.record(cnt);
+
         cnt as usize
     }
 
@@ -87,6 +103,8 @@ impl TxQueue {
 
         let cnt = unsafe { libxdp_sys::xsk_ring_prod__reserve(self.ring.as_mut(), 1, &mut idx) };
 
+        self.ring.record_reserve(idx, 1, cnt);
+
         if cnt > 0 {
             let send_pkt_desc =
                 unsafe { libxdp_sys::xsk_ring_prod__tx_desc(self.ring.as_mut(), idx) };
@@ -99,9 +117,58 @@ impl TxQueue {
             unsafe { libxdp_sys::xsk_ring_prod__submit(self.ring.as_mut(), cnt) };
         }
 
+        #[cfg(debug_assertions)]
+        self.stall_detector.record(cnt);
+
         cnt as usize
     }
 
+    /// Same as [`produce`] but submits at most as many frames as
+    /// `limiter` permits to be in flight. Returns the number of frames
+    /// submitted to the kernel, which are then counted as in flight
+    /// until released back to `limiter`.
+    ///
+    /// Only the first [`try_acquire`] frames of `descs` are
+    /// considered, so as with [`produce`], if there isn't space for
+    /// all of these on the underlying ring buffer then none are
+    /// submitted and the acquisition is given back.
+    ///
+    /// # Safety
+    ///
+    /// See [`produce`].
+    ///
+    /// [`produce`]: Self::produce
+    /// [`try_acquire`]: InFlightLimiter::try_acquire
+    #[inline]
+    pub unsafe fn produce_limited(
+        &mut self,
+        descs: &[FrameDesc],
+        limiter: &mut InFlightLimiter,
+    ) -> usize {
+        let permitted = limiter.try_acquire(descs.len());
+
+        let cnt = unsafe { self.produce(&descs[..permitted]) };
+
+        limiter.release(permitted - cnt);
+
+        cnt
+    }
+
+    /// Reserve up to `max` slots on the tx ring, returning a
+    /// [`TxTransaction`] to write descriptors to them with before
+    /// committing some or all of them, or aborting.
+    ///
+    /// Unlike [`produce`], which submits all or nothing, this reserves
+    /// as many of the `max` slots as are free. While the transaction
+    /// is open the queue can't be used for anything else, and dropping
+    /// it without committing leaves the ring as it was.
+    ///
+    /// [`produce`]: Self::produce
+    #[inline]
+    pub fn begin(&mut self, max: usize) -> TxTransaction<'_> {
+        TxTransaction::new(&mut self.ring, max)
+    }
+
     /// Same as [`produce`] but wake up the kernel to continue
     /// processing produced frames (if required).
     ///
@@ -144,33 +211,42 @@ impl TxQueue {
         Ok(cnt)
     }
 
+    /// Make sure the kernel will send everything produced so far,
+    /// waking it up if it needs to be and doing nothing otherwise.
+    ///
+    /// Splits staging frames from sending them, so that several calls
+    /// to [`produce`] can be followed by a single flush, rather than
+    /// each making a [`produce_and_wakeup`] check of its own.
+    ///
+    /// [`produce`]: Self::produce
+    /// [`produce_and_wakeup`]: Self::produce_and_wakeup
+    #[inline]
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.needs_wakeup() {
+            self.wakeup()?;
+        }
+
+        Ok(())
+    }
+
     /// Wake up the kernel to continue processing produced frames.
     ///
+    /// By default no destination address is passed to `sendto`, unless
+    /// [`wakeup_with_address`] was set and the socket shares its
+    /// [`Umem`], in which case the socket's bound address is used.
+    ///
     /// See [`produce_and_wakeup`] for a link to docs with further
     /// explanation.
     ///
+    /// [`wakeup_with_address`]: crate::config::SocketConfigBuilder::wakeup_with_address
+    /// [`Umem`]: crate::Umem
     /// [`produce_and_wakeup`]: Self::produce_and_wakeup
     #[inline]
     pub fn wakeup(&self) -> io::Result<()> {
-        let ret = unsafe {
-            libc::sendto(
-                self.socket.fd.as_raw_fd(),
-                ptr::null(),
-                0,
-                MSG_DONTWAIT,
-                ptr::null(),
-                0,
-            )
-        };
+        #[cfg(feature = "ring-stats")]
+        self.ring.record_call();
 
-        if ret < 0 {
-            match util::get_errno() {
-                ENOBUFS | EAGAIN | EBUSY | ENETDOWN => (),
-                _ => return Err(io::Error::last_os_error()),
-            }
-        }
-
-        Ok(())
+        wakeup(&self.socket)
     }
 
     /// Check if the [`XDP_USE_NEED_WAKEUP`] flag is set on the tx
@@ -188,12 +264,96 @@ impl TxQueue {
         unsafe { libxdp_sys::xsk_ring_prod__needs_wakeup(self.ring.as_ref()) != 0 }
     }
 
+    /// The raw flags word shared with the kernel for the tx ring.
+    ///
+    /// A lower level alternative to [`needs_wakeup`], for debugging or
+    /// custom wakeup logic. The bits currently defined by the kernel
+    /// are:
+    ///
+    /// - [`XDP_RING_NEED_WAKEUP`] (bit 0): set when the kernel must be
+    ///   woken up, e.g. via [`wakeup`], before it will process any
+    ///   further frames produced to the ring. Only ever set if the
+    ///   socket was bound with [`XDP_USE_NEED_WAKEUP`].
+    ///
+    /// Any other bits are reserved for future use by the kernel and
+    /// are returned as is.
+    ///
+    /// [`needs_wakeup`]: Self::needs_wakeup
+    /// [`wakeup`]: Self::wakeup
+    /// [`XDP_RING_NEED_WAKEUP`]: libxdp_sys::XDP_RING_NEED_WAKEUP
+    /// [`XDP_USE_NEED_WAKEUP`]: libxdp_sys::XDP_USE_NEED_WAKEUP
+    #[inline]
+    pub fn ring_flags(&self) -> u32 {
+        self.ring.flags()
+    }
+
+    /// Set how many consecutive calls to [`produce`] (or any of its
+    /// variants) may fail to submit a single frame before a warning
+    /// is logged. `None` disables the warning. Default is
+    /// [`DEFAULT_STALL_WARNING_THRESHOLD`].
+    ///
+    /// A `TxQueue` that stays full is usually a sign that frames
+    /// aren't being reclaimed from the [`CompQueue`], so the warning
+    /// is there to help catch this during development. It is only
+    /// compiled into debug builds, and in release builds this
+    /// function does nothing.
+    ///
+    /// [`produce`]: Self::produce
+    /// [`CompQueue`]: crate::CompQueue
+    #[inline]
+    pub fn set_stall_warning_threshold(&mut self, threshold: Option<NonZeroU32>) {
+        #[cfg(debug_assertions)]
+        {
+            self.stall_detector = StallDetector::new(threshold);
+        }
+
+        #[cfg(not(debug_assertions))]
+        let _ = threshold;
+    }
+
     /// Polls the socket, returning `true` if it is ready to write.
     #[inline]
     pub fn poll(&mut self, poll_timeout: i32) -> io::Result<bool> {
         self.socket.fd.poll_write(poll_timeout)
     }
 
+    /// Userspace side statistics for this queue's ring. See
+    /// [`RingStats`] for details.
+    ///
+    /// Only available with the `ring-stats` feature enabled.
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn ring_stats(&self) -> RingStats {
+        self.ring.stats()
+    }
+
+    /// A moving average of the number of frames reserved on the tx
+    /// ring between consecutive calls to [`wakeup`](Self::wakeup),
+    /// weighted towards recent wakeups.
+    ///
+    /// This is an advisory metric for tuning rather than an exact
+    /// count. Each wakeup is a syscall, so a low value means frames
+    /// are being submitted in batches too small to amortise them,
+    /// whereas with [`XDP_USE_NEED_WAKEUP`] set and the kernel keeping
+    /// up it should rise as fewer wakeups are needed. Only wakeups made
+    /// through this queue count, not those made on its behalf by a
+    /// [`SyncTxQueue`](super::SyncTxQueue).
+    ///
+    /// Only available with the `ring-stats` feature enabled.
+    ///
+    /// [`XDP_USE_NEED_WAKEUP`]: crate::config::BindFlags::XDP_USE_NEED_WAKEUP
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn frames_per_wakeup(&self) -> f64 {
+        self.ring.frames_per_call()
+    }
+
+    /// The underlying [`Socket`].
+    #[inline]
+    pub fn socket(&self) -> &Socket {
+        &self.socket
+    }
+
     /// A reference to the underlying [`Socket`]'s file descriptor.
     #[inline]
     pub fn fd(&self) -> &Fd {
@@ -206,3 +366,119 @@ impl TxQueue {
         &mut self.socket.fd
     }
 }
+
+/// Kick the kernel into processing frames produced to `socket`'s tx
+/// ring. Only makes a `sendto` call on the socket's file descriptor,
+/// so needs no access to the ring itself.
+#[inline]
+pub(super) fn wakeup(socket: &Socket) -> io::Result<()> {
+    let addr = socket.wakeup_addr();
+
+    let (addr_ptr, addr_len) = match addr.as_ref() {
+        Some(addr) => (
+            addr as *const _ as *const libc::sockaddr,
+            mem::size_of_val(addr) as libc::socklen_t,
+        ),
+        None => (ptr::null(), 0),
+    };
+
+    let ret = unsafe {
+        libc::sendto(
+            socket.fd.as_raw_fd(),
+            ptr::null(),
+            0,
+            MSG_DONTWAIT,
+            addr_ptr,
+            addr_len,
+        )
+    };
+
+    if ret < 0 {
+        match util::get_errno() {
+            ENOBUFS | EAGAIN | EBUSY => (),
+            ENETDOWN if !socket.fd.report_link_down() => (),
+            _ => return Err(io::Error::last_os_error()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Default number of consecutive calls to [`TxQueue::produce`] that
+/// may submit nothing before a warning is logged in debug builds.
+pub const DEFAULT_STALL_WARNING_THRESHOLD: u32 = 1000;
+
+/// Counts consecutive produce calls which submitted no frames,
+/// warning once the streak reaches a threshold.
+#[cfg(debug_assertions)]
+#[derive(Debug)]
+struct StallDetector {
+    threshold: Option<NonZeroU32>,
+    streak: u32,
+}
+
+#[cfg(debug_assertions)]
+impl StallDetector {
+    fn new(threshold: Option<NonZeroU32>) -> Self {
+        Self {
+            threshold,
+            streak: 0,
+        }
+    }
+
+    /// Record the number of frames submitted by a produce call
+    /// (zero-length calls shouldn't be recorded). Returns `true` if
+    /// this call triggered a warning.
+    fn record(&mut self, submitted: u32) -> bool {
+        let threshold = match self.threshold {
+            Some(threshold) => threshold.get(),
+            None => return false,
+        };
+
+        if submitted > 0 {
+            self.streak = 0;
+            return false;
+        }
+
+        self.streak = self.streak.saturating_add(1);
+
+        if self.streak == threshold {
+            warn!(
+                "tx queue has been full for {} consecutive produce calls, \
+                 check that frames are being reclaimed from the completion queue",
+                threshold
+            );
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stall_detector_warns_once_per_streak() {
+        let mut detector = StallDetector::new(NonZeroU32::new(3));
+
+        assert!(!detector.record(0));
+        assert!(!detector.record(0));
+        assert!(detector.record(0));
+        assert!(!detector.record(0));
+
+        assert!(!detector.record(1));
+
+        assert!(!detector.record(0));
+        assert!(!detector.record(0));
+        assert!(detector.record(0));
+    }
+
+    #[test]
+    fn stall_detector_can_be_disabled() {
+        let mut detector = StallDetector::new(None);
+
+        assert!((0..10).all(|_| !detector.record(0)));
+    }
+}
@@ -0,0 +1,125 @@
+//! Building up a batch of tx descriptors before deciding to send it.
+
+use crate::{ring::XskRingProd, umem::frame::FrameDesc};
+
+/// A reservation of slots on the tx ring, returned by
+/// [`TxQueue::begin`](super::TxQueue::begin).
+///
+/// Descriptors are written to the reserved slots in order via
+/// [`push`](Self::push), which may be spread across any number of
+/// calls, and nothing is visible to the kernel until
+/// [`commit`](Self::commit). Dropping the transaction without
+/// committing aborts it.
+///
+/// The slots are only claimed from the ring on commit, so until then
+/// the ring's producer position is left untouched and aborting, or
+/// even leaking, the transaction leaves the ring as it was.
+#[derive(Debug)]
+pub struct TxTransaction<'a> {
+    ring: &'a mut XskRingProd,
+    idx: u32,
+    reserved: u32,
+    written: u32,
+}
+
+impl<'a> TxTransaction<'a> {
+    pub(super) fn new(ring: &'a mut XskRingProd, max: usize) -> Self {
+        let nb = max as u32;
+
+        // The free slots start at the cached producer position, which
+        // only moves once they're reserved in `commit`.
+        let idx = ring.as_ref().cached_prod;
+
+        let reserved = if nb > 0 { nb.min(ring.nb_free(nb)) } else { 0 };
+
+        Self {
+            ring,
+            idx,
+            reserved,
+            written: 0,
+        }
+    }
+
+    /// The number of slots available to write to, which may be fewer
+    /// than asked for if the ring didn't have room.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.reserved as usize
+    }
+
+    /// The number of descriptors written so far.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.written as usize
+    }
+
+    /// Whether no descriptors have been written yet.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.written == 0
+    }
+
+    /// Write `desc` to the next reserved slot, returning `false` if
+    /// they've all been written to already.
+    ///
+    /// # Safety
+    ///
+    /// See [`TxQueue::produce`](super::TxQueue::produce). A frame
+    /// written here is only handed to the kernel if it's committed,
+    /// otherwise it remains the caller's once the transaction ends.
+    #[inline]
+    pub unsafe fn push(&mut self, desc: &FrameDesc) -> bool {
+        if self.written == self.reserved {
+            return false;
+        }
+
+        // SAFETY: the slot is one of the `reserved` free slots past
+        // the producer position, which the kernel isn't reading from.
+        let send_pkt_desc = unsafe {
+            libxdp_sys::xsk_ring_prod__tx_desc(
+                self.ring.as_mut(),
+                self.idx.wrapping_add(self.written),
+            )
+        };
+
+        // SAFETY: unsafe contract of this function guarantees `desc`
+        // describes a frame belonging to the same UMEM as this queue.
+        desc.write_xdp_desc(unsafe { &mut *send_pkt_desc });
+
+        self.written += 1;
+
+        true
+    }
+
+    /// Submit the first `n` descriptors written to the kernel,
+    /// discarding the rest. Returns the number submitted, which is `n`
+    /// capped at [`len`](Self::len).
+    ///
+    /// As with [`TxQueue::produce`](super::TxQueue::produce), the
+    /// kernel may still need a [`wakeup`](super::TxQueue::wakeup) to
+    /// start sending them.
+    #[inline]
+    pub fn commit(self, n: usize) -> usize {
+        let cnt = (n as u32).min(self.written);
+
+        if cnt == 0 {
+            return 0;
+        }
+
+        let mut idx = 0;
+
+        // SAFETY: the ring has had `cnt` slots free since `new` and
+        // nothing else can have reserved them in the meantime, since
+        // the transaction borrows the ring mutably.
+        let got = unsafe { libxdp_sys::xsk_ring_prod__reserve(self.ring.as_mut(), cnt, &mut idx) };
+
+        debug_assert_eq!(got, cnt);
+        debug_assert_eq!(idx, self.idx);
+
+        self.ring.record_reserve(idx, cnt, got);
+
+        unsafe { libxdp_sys::xsk_ring_prod__submit(self.ring.as_mut(), got) };
+
+        got as usize
+    }
+}
@@ -0,0 +1,125 @@
+//! A typed wrapper around an XDP program's `XSKMAP`.
+
+use libxdp_sys::{bpf_map_info, bpf_map_type_BPF_MAP_TYPE_XSKMAP, BPF_ANY};
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    mem,
+    os::unix::prelude::{AsRawFd, RawFd},
+};
+
+use super::Socket;
+
+/// A handle on an XDP program's `XSKMAP`, used to direct packets
+/// arriving on each queue to an AF_XDP [`Socket`].
+///
+/// For use when loading your own XDP program, e.g. after creating
+/// sockets with [`XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD`] set. The map is
+/// keyed by queue id, and the program redirects a packet to whichever
+/// socket is registered for the queue it arrived on.
+///
+/// The map file descriptor is borrowed rather than owned, so must
+/// remain open for as long as this handle is in use.
+///
+/// [`XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD`]: crate::config::LibxdpFlags::XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD
+#[derive(Debug)]
+pub struct XsksMap {
+    fd: RawFd,
+    max_entries: u32,
+    entries: HashMap<u32, RawFd>,
+}
+
+impl XsksMap {
+    /// Wrap the BPF map referred to by `map_fd`.
+    ///
+    /// Fails if the map's type is not `BPF_MAP_TYPE_XSKMAP`.
+    pub fn new(map_fd: RawFd) -> io::Result<Self> {
+        // SAFETY: all-zero is a valid `bpf_map_info`.
+        let mut info: bpf_map_info = unsafe { mem::zeroed() };
+        let mut info_len = mem::size_of::<bpf_map_info>() as u32;
+
+        let err = unsafe { libxdp_sys::bpf_map_get_info_by_fd(map_fd, &mut info, &mut info_len) };
+
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(-err));
+        }
+
+        if info.type_ != bpf_map_type_BPF_MAP_TYPE_XSKMAP {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "expected map of type BPF_MAP_TYPE_XSKMAP ({}), got {}",
+                    bpf_map_type_BPF_MAP_TYPE_XSKMAP, info.type_
+                ),
+            ));
+        }
+
+        Ok(Self {
+            fd: map_fd,
+            max_entries: info.max_entries,
+            entries: HashMap::new(),
+        })
+    }
+
+    /// The maximum number of entries the map holds. Queue ids must be
+    /// less than this.
+    #[inline]
+    pub fn max_entries(&self) -> u32 {
+        self.max_entries
+    }
+
+    /// Direct packets received on `queue_id` to `socket`, replacing
+    /// any socket previously registered for that queue.
+    pub fn insert(&mut self, queue_id: u32, socket: &Socket) -> io::Result<()> {
+        let socket_fd = socket.fd.as_raw_fd();
+
+        // SAFETY: the key and value are both live `u32`s, matching the
+        // `XSKMAP` key and value sizes.
+        let err = unsafe {
+            libxdp_sys::bpf_map_update_elem(
+                self.fd,
+                &queue_id as *const _ as *const libc::c_void,
+                &socket_fd as *const _ as *const libc::c_void,
+                BPF_ANY as u64,
+            )
+        };
+
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(-err));
+        }
+
+        self.entries.insert(queue_id, socket_fd);
+
+        Ok(())
+    }
+
+    /// Remove the socket registered for `queue_id`, if any.
+    pub fn remove(&mut self, queue_id: u32) -> io::Result<()> {
+        // SAFETY: the key is a live `u32`, matching the `XSKMAP` key
+        // size.
+        let err = unsafe {
+            libxdp_sys::bpf_map_delete_elem(self.fd, &queue_id as *const _ as *const libc::c_void)
+        };
+
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(-err));
+        }
+
+        self.entries.remove(&queue_id);
+
+        Ok(())
+    }
+
+    /// The file descriptor of the socket registered for `queue_id`
+    /// through this handle, if any.
+    ///
+    /// The kernel doesn't support reading `XSKMAP` entries from
+    /// userspace, so rather than querying the map this reflects the
+    /// changes made via [`insert`](Self::insert) and
+    /// [`remove`](Self::remove). Entries added by the XDP program's
+    /// loader or any other handle on the same map aren't seen.
+    #[inline]
+    pub fn get(&self, queue_id: u32) -> Option<RawFd> {
+        self.entries.get(&queue_id).copied()
+    }
+}
@@ -1,6 +1,11 @@
-use crate::ring::XskRingCons;
+use log::warn;
+use std::{ops::Deref, slice};
 
-use super::{frame::FrameDesc, Umem};
+#[cfg(feature = "ring-stats")]
+use crate::ring::RingStats;
+use crate::{ring::XskRingCons, util};
+
+use super::{frame::FrameDesc, CompletionCallbacks, Umem};
 
 /// Used to transfer ownership of [`Umem`](super::Umem) frames from
 /// kernel-space to user-space.
@@ -13,12 +18,18 @@ use super::{frame::FrameDesc, Umem};
 #[derive(Debug)]
 pub struct CompQueue {
     ring: XskRingCons,
-    _umem: Umem,
+    umem: Umem,
 }
 
 impl CompQueue {
     pub(crate) fn new(ring: XskRingCons, umem: Umem) -> Self {
-        Self { ring, _umem: umem }
+        Self { ring, umem }
+    }
+
+    /// The [`Umem`] this queue's frames belong to.
+    #[inline]
+    pub(crate) fn umem(&self) -> &Umem {
+        &self.umem
     }
 
     /// Update `descs` with details of frames whose contents have been
@@ -52,6 +63,8 @@ impl CompQueue {
 
         let cnt = unsafe { libxdp_sys::xsk_ring_cons__peek(self.ring.as_mut(), nb, &mut idx) };
 
+        self.ring.record_peek(idx, nb, cnt);
+
         if cnt > 0 {
             for desc in descs.iter_mut().take(cnt as usize) {
                 let addr =
@@ -84,6 +97,8 @@ impl CompQueue {
 
         let cnt = unsafe { libxdp_sys::xsk_ring_cons__peek(self.ring.as_mut(), 1, &mut idx) };
 
+        self.ring.record_peek(idx, 1, cnt);
+
         if cnt > 0 {
             let addr = unsafe { *libxdp_sys::xsk_ring_cons__comp_addr(self.ring.as_ref(), idx) };
 
@@ -97,4 +112,352 @@ impl CompQueue {
 
         cnt as usize
     }
+
+    /// Same as [`consume`], but writes to `out` the index of each
+    /// completed frame within the [`Umem`] this queue is tied to,
+    /// rather than a descriptor, reading at most `max` entries.
+    /// Returns the number of elements of `out` updated.
+    ///
+    /// Indices are as given by [`Umem::frame_index`], so for a frame
+    /// pool kept as a table indexed by frame this avoids converting
+    /// each completed address by hand. In debug builds each index is
+    /// checked to be within the `Umem`.
+    ///
+    /// # Safety
+    ///
+    /// See [`consume`].
+    ///
+    /// [`consume`]: Self::consume
+    #[inline]
+    pub unsafe fn consume_indices(&mut self, out: &mut [usize], max: usize) -> usize {
+        let nb = util::min_usize(max, out.len()) as u32;
+
+        if nb == 0 {
+            return 0;
+        }
+
+        let mut idx = 0;
+
+        let cnt = unsafe { libxdp_sys::xsk_ring_cons__peek(self.ring.as_mut(), nb, &mut idx) };
+
+        self.ring.record_peek(idx, nb, cnt);
+
+        if cnt > 0 {
+            for index in out.iter_mut().take(cnt as usize) {
+                let addr =
+                    unsafe { *libxdp_sys::xsk_ring_cons__comp_addr(self.ring.as_ref(), idx) };
+
+                *index = self.umem.mem.frame_index(addr as usize);
+
+                debug_assert!(
+                    *index < self.umem.mem.frame_count(),
+                    "completed address {:#x} is outside the UMEM",
+                    addr
+                );
+
+                idx += 1;
+            }
+
+            unsafe { libxdp_sys::xsk_ring_cons__release(self.ring.as_mut(), cnt) };
+        }
+
+        cnt as usize
+    }
+
+    /// Same as [`consume_indices`], but rather than writing out each
+    /// completed frame's index, sets the corresponding bit in
+    /// `bitmap`, reading at most `max` entries. Returns the number of
+    /// entries consumed.
+    ///
+    /// Frame `i` is bit `i % 64` of `bitmap[i / 64]`. Bits are only
+    /// ever set, never cleared, so completions accumulate across calls
+    /// until the caller clears the bitmap. This is meant as a
+    /// diagnostic for tracking down frame accounting bugs: comparing
+    /// the bitmap against a record of the frames handed to the
+    /// [`TxQueue`](crate::TxQueue) shows up those which were lost,
+    /// i.e. sent but never completed. A frame completed twice sets a
+    /// bit which is already set, so if fewer bits end up set than the
+    /// total of the counts returned since the bitmap was cleared, and
+    /// no frame was sent more than once in that time, something was
+    /// completed twice.
+    ///
+    /// Any completed address whose index falls outside the `Umem` or
+    /// `bitmap` is consumed but otherwise skipped, with a warning.
+    ///
+    /// # Safety
+    ///
+    /// See [`consume_indices`].
+    ///
+    /// [`consume_indices`]: Self::consume_indices
+    pub unsafe fn consume_into_bitmap(&mut self, bitmap: &mut [u64], max: usize) -> usize {
+        let nb = util::min_usize(max, u32::MAX as usize) as u32;
+
+        if nb == 0 {
+            return 0;
+        }
+
+        let mut idx = 0;
+
+        let cnt = unsafe { libxdp_sys::xsk_ring_cons__peek(self.ring.as_mut(), nb, &mut idx) };
+
+        self.ring.record_peek(idx, nb, cnt);
+
+        if cnt > 0 {
+            let frame_count = util::min_usize(self.umem.mem.frame_count(), bitmap.len() * 64);
+
+            for _ in 0..cnt {
+                let addr =
+                    unsafe { *libxdp_sys::xsk_ring_cons__comp_addr(self.ring.as_ref(), idx) };
+
+                let index = self.umem.mem.frame_index(addr as usize);
+
+                if index < frame_count {
+                    bitmap[index / 64] |= 1 << (index % 64);
+                } else {
+                    warn!(
+                        "completed address {:#x} has frame index {} outside of the bitmap",
+                        addr, index
+                    );
+                }
+
+                idx += 1;
+            }
+
+            unsafe { libxdp_sys::xsk_ring_cons__release(self.ring.as_mut(), cnt) };
+        }
+
+        cnt as usize
+    }
+
+    /// Same as [`consume`], but additionally invokes any callbacks
+    /// registered in `callbacks` for the consumed frames, in the
+    /// order they were completed, before returning.
+    ///
+    /// # Safety
+    ///
+    /// See [`consume`].
+    ///
+    /// [`consume`]: Self::consume
+    #[inline]
+    pub unsafe fn consume_with_callbacks(
+        &mut self,
+        descs: &mut [FrameDesc],
+        callbacks: &mut CompletionCallbacks,
+    ) -> usize {
+        let cnt = unsafe { self.consume(descs) };
+
+        callbacks.complete(&descs[..cnt]);
+
+        cnt
+    }
+
+    /// The raw flags word shared with the kernel for the completion
+    /// ring.
+    ///
+    /// As with the [`RxQueue`](crate::RxQueue), no flags are currently
+    /// defined for this ring so this is expected to be zero.
+    #[inline]
+    pub fn ring_flags(&self) -> u32 {
+        self.ring.flags()
+    }
+
+    /// Userspace side statistics for this queue's ring. See
+    /// [`RingStats`] for details.
+    ///
+    /// Only available with the `ring-stats` feature enabled.
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn ring_stats(&self) -> RingStats {
+        self.ring.stats()
+    }
+
+    /// Consume up to `max` completed frame addresses, exposing them
+    /// directly from the ring rather than copying them into a
+    /// [`FrameDesc`] buffer.
+    ///
+    /// The returned [`ReleaseGuard`] dereferences to the consumed
+    /// addresses, which point at the start of each frame's packet
+    /// data segment as they did when submitted to the [`TxQueue`].
+    /// Since the guard borrows the ring's shared memory, the
+    /// addresses are only accessible while it's alive. Once dropped,
+    /// the entries are handed back to the kernel to reuse and any
+    /// addresses should already have been dealt with. To release only
+    /// some of them, and leave the remainder to be consumed again
+    /// later, use [`ReleaseGuard::release`].
+    ///
+    /// Fewer than `max` addresses may be returned even if more are
+    /// available, since the slice cannot wrap around the end of the
+    /// ring.
+    ///
+    /// [`TxQueue`]: crate::socket::TxQueue
+    #[inline]
+    pub fn consume_raw(&mut self, max: usize) -> ReleaseGuard<'_> {
+        ReleaseGuard::new(&mut self.ring, max)
+    }
+}
+
+/// A batch of completed frame addresses borrowed directly from the
+/// [`CompQueue`]'s ring. Returned by [`CompQueue::consume_raw`].
+///
+/// Releases the addresses back to the kernel on drop.
+#[derive(Debug)]
+pub struct ReleaseGuard<'a> {
+    ring: &'a mut XskRingCons,
+    addrs: &'a [u64],
+    nb_release: usize,
+}
+
+impl<'a> ReleaseGuard<'a> {
+    pub(crate) fn new(ring: &'a mut XskRingCons, max: usize) -> Self {
+        let mut idx = 0;
+
+        let cnt = unsafe { libxdp_sys::xsk_ring_cons__peek(ring.as_mut(), max as u32, &mut idx) };
+
+        if max > 0 {
+            ring.record_peek(idx, max as u32, cnt);
+        }
+
+        let r = ring.as_mut();
+
+        let contiguous = util::min_usize(cnt as usize, (r.size - (idx & r.mask)) as usize);
+
+        // Un-peek anything past the end of the ring so it's returned
+        // by the next call instead.
+        if cnt > contiguous as u32 {
+            unsafe { libxdp_sys::xsk_ring_cons__cancel(r, cnt - contiguous as u32) };
+        }
+
+        let addrs = if contiguous > 0 {
+            // SAFETY: entries `idx..idx + contiguous` have been
+            // published by the kernel and lie within the ring. They
+            // won't be modified until released, which can only happen
+            // once this guard, and so the borrow, has been dropped.
+            unsafe {
+                slice::from_raw_parts(
+                    libxdp_sys::xsk_ring_cons__comp_addr(ring.as_ref(), idx),
+                    contiguous,
+                )
+            }
+        } else {
+            &[]
+        };
+
+        Self {
+            ring,
+            addrs,
+            nb_release: contiguous,
+        }
+    }
+
+    /// The consumed frame addresses.
+    #[inline]
+    pub fn addrs(&self) -> &[u64] {
+        self.addrs
+    }
+
+    /// Release only the first `n` addresses back to the kernel. The
+    /// remainder will be returned again by the next call to
+    /// [`consume_raw`](CompQueue::consume_raw).
+    #[inline]
+    pub fn release(mut self, n: usize) {
+        self.nb_release = util::min_usize(n, self.addrs.len());
+    }
+}
+
+impl Deref for ReleaseGuard<'_> {
+    type Target = [u64];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.addrs
+    }
+}
+
+impl Drop for ReleaseGuard<'_> {
+    fn drop(&mut self) {
+        let r = self.ring.as_mut();
+
+        if self.addrs.len() > self.nb_release {
+            unsafe {
+                libxdp_sys::xsk_ring_cons__cancel(r, (self.addrs.len() - self.nb_release) as u32)
+            };
+        }
+
+        if self.nb_release > 0 {
+            unsafe { libxdp_sys::xsk_ring_cons__release(r, self.nb_release as u32) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use crate::{
+        config::{QueueSize, SocketConfig, UmemConfig},
+        socket::TxQueue,
+        umem::loopback::Loopback,
+    };
+
+    use super::*;
+
+    fn send(loopback: &mut Loopback, tx_q: &mut TxQueue, descs: &[FrameDesc]) {
+        assert_eq!(unsafe { tx_q.produce(descs) }, descs.len());
+
+        loopback.tick();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn consume_raw_exposes_completed_addrs() {
+        let (mut loopback, mut queues, descs) = Loopback::new(
+            UmemConfig::default(),
+            SocketConfig::default(),
+            8.try_into().unwrap(),
+        );
+
+        send(&mut loopback, &mut queues.tx_q, &descs[..3]);
+
+        {
+            let guard = queues.cq.consume_raw(2);
+
+            assert_eq!(
+                guard.addrs(),
+                &[descs[0].addr() as u64, descs[1].addr() as u64]
+            );
+
+            // Only release the first, so the second is returned again
+            guard.release(1);
+        }
+
+        let guard = queues.cq.consume_raw(8);
+
+        assert_eq!(
+            &guard[..],
+            &[descs[1].addr() as u64, descs[2].addr() as u64]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn consume_raw_stops_at_end_of_ring() {
+        let umem_config = UmemConfig::builder()
+            .comp_queue_size(QueueSize::new(4).unwrap())
+            .build()
+            .unwrap();
+
+        let (mut loopback, mut queues, descs) =
+            Loopback::new(umem_config, SocketConfig::default(), 8.try_into().unwrap());
+
+        send(&mut loopback, &mut queues.tx_q, &descs[..3]);
+
+        assert_eq!(queues.cq.consume_raw(8).len(), 3);
+
+        send(&mut loopback, &mut queues.tx_q, &descs[3..6]);
+
+        // Ring position is now 3, so only one entry before wrapping
+        assert_eq!(queues.cq.consume_raw(8).len(), 1);
+        assert_eq!(queues.cq.consume_raw(8).len(), 2);
+        assert_eq!(queues.cq.consume_raw(8).len(), 0);
+    }
 }
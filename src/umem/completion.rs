@@ -0,0 +1,135 @@
+//! Callbacks invoked as transmitted frames are completed.
+
+use std::{collections::HashMap, fmt};
+
+use super::frame::FrameDesc;
+
+type Callback = Box<dyn FnOnce(&FrameDesc) + Send>;
+
+/// A table of callbacks keyed by frame address, each invoked once the
+/// frame it was registered against is consumed from the
+/// [`CompQueue`](super::CompQueue), i.e. once the kernel has finished
+/// transmitting it.
+///
+/// Callbacks are invoked exactly once, in the order their frames are
+/// completed, by whichever thread calls
+/// [`consume_with_callbacks`](super::CompQueue::consume_with_callbacks).
+/// Keep them short to avoid stalling frame reclamation.
+///
+/// Each registered callback costs a boxed closure and a hash map
+/// entry, so this is best suited to workflows where only some frames
+/// need correlating with their completion, rather than every frame on
+/// a high rate path.
+#[derive(Default)]
+pub struct CompletionCallbacks {
+    callbacks: HashMap<usize, Callback>,
+}
+
+impl CompletionCallbacks {
+    /// Creates a new, empty, `CompletionCallbacks` table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `f` to be invoked when the frame described by `desc`
+    /// is completed. Should be called before the frame is submitted
+    /// to the [`TxQueue`](crate::TxQueue), to avoid racing with its
+    /// completion.
+    ///
+    /// A frame can only have one callback registered at a time, so any
+    /// existing callback for the frame is replaced and returned
+    /// without being invoked.
+    pub fn register<F>(&mut self, desc: &FrameDesc, f: F) -> Option<Callback>
+    where
+        F: FnOnce(&FrameDesc) + Send + 'static,
+    {
+        self.callbacks.insert(desc.addr(), Box::new(f))
+    }
+
+    /// Remove the callback registered for the frame described by
+    /// `desc`, if any, without invoking it.
+    pub fn unregister(&mut self, desc: &FrameDesc) -> Option<Callback> {
+        self.callbacks.remove(&desc.addr())
+    }
+
+    /// The number of callbacks awaiting completion of their frames.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.callbacks.len()
+    }
+
+    /// Returns `true` if no callbacks are awaiting completion.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.is_empty()
+    }
+
+    /// Invoke, in order, the callbacks registered for each of the
+    /// completed frames in `descs`. Frames without a callback are
+    /// skipped.
+    pub(super) fn complete(&mut self, descs: &[FrameDesc]) {
+        if self.callbacks.is_empty() {
+            return;
+        }
+
+        for desc in descs {
+            if let Some(f) = self.callbacks.remove(&desc.addr()) {
+                f(desc);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for CompletionCallbacks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompletionCallbacks")
+            .field("pending", &self.callbacks.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn callbacks_are_invoked_once_in_completion_order() {
+        let mut callbacks = CompletionCallbacks::new();
+
+        let order = Arc::new(Mutex::new(vec![]));
+
+        let descs: Vec<FrameDesc> = (0..3).map(|i| FrameDesc::new(i * 2048)).collect();
+
+        for (i, desc) in descs.iter().enumerate() {
+            let order = Arc::clone(&order);
+
+            callbacks.register(desc, move |desc| {
+                order.lock().unwrap().push((i, desc.addr()));
+            });
+        }
+
+        assert_eq!(callbacks.len(), 3);
+
+        // Completed out of submission order
+        callbacks.complete(&[descs[2], descs[0]]);
+        callbacks.complete(&[descs[2], descs[0], descs[1]]);
+
+        assert!(callbacks.is_empty());
+
+        assert_eq!(*order.lock().unwrap(), vec![(2, 4096), (0, 0), (1, 2048)]);
+    }
+
+    #[test]
+    fn registering_twice_replaces_existing_callback() {
+        let mut callbacks = CompletionCallbacks::new();
+
+        let desc = FrameDesc::new(0);
+
+        assert!(callbacks.register(&desc, |_| ()).is_none());
+        assert!(callbacks.register(&desc, |_| ()).is_some());
+        assert!(callbacks.unregister(&desc).is_some());
+        assert!(callbacks.is_empty());
+    }
+}
@@ -1,5 +1,7 @@
 use std::io;
 
+#[cfg(feature = "ring-stats")]
+use crate::ring::RingStats;
 use crate::{ring::XskRingProd, socket::Fd};
 
 use super::{frame::FrameDesc, Umem};
@@ -15,12 +17,25 @@ use super::{frame::FrameDesc, Umem};
 #[derive(Debug)]
 pub struct FillQueue {
     ring: XskRingProd,
-    _umem: Umem,
+    umem: Umem,
 }
 
 impl FillQueue {
     pub(crate) fn new(ring: XskRingProd, umem: Umem) -> Self {
-        Self { ring, _umem: umem }
+        Self { ring, umem }
+    }
+
+    /// The [`Umem`] this queue's frames belong to.
+    #[inline]
+    pub(crate) fn umem(&self) -> &Umem {
+        &self.umem
+    }
+
+    /// The number of free slots on the ring, checking the kernel's
+    /// position only if fewer than `nb` are known to be free.
+    #[inline]
+    pub(crate) fn nb_free(&mut self, nb: usize) -> usize {
+        self.ring.nb_free(nb as u32) as usize
     }
 
     /// Let the kernel know that the [`Umem`] frames described by
@@ -29,7 +44,10 @@ impl FillQueue {
     ///
     /// Note that if the length of `descs` is greater than the number
     /// of available spaces on the underlying ring buffer then no
-    /// frames at all will be handed over to the kernel.
+    /// frames at all will be handed over to the kernel. When refilling
+    /// with more frames than there may be room for, e.g. after a burst
+    /// of drops, use [`produce_partial`](Self::produce_partial) so the
+    /// ring doesn't go without.
     ///
     /// Once the frames have been submitted to this queue they should
     /// not be used again until consumed via the [`RxQueue`].
@@ -58,6 +76,8 @@ impl FillQueue {
 
         let cnt = unsafe { libxdp_sys::xsk_ring_prod__reserve(self.ring.as_mut(), nb, &mut idx) };
 
+        self.ring.record_reserve(idx, nb, cnt);
+
         if cnt > 0 {
             for desc in descs.iter().take(cnt as usize) {
                 unsafe {
@@ -74,6 +94,29 @@ impl FillQueue {
         cnt as usize
     }
 
+    /// Same as [`produce`], but if there isn't room on the ring for
+    /// all of `descs`, submits as many as there is room for rather than
+    /// none. Returns the number submitted.
+    ///
+    /// The frames submitted are always those at the start of `descs`,
+    /// so the overflow is `&descs[n..]` for a return value of `n`.
+    /// Those frames are still the caller's, and should be held on to
+    /// and submitted again once the kernel has made room by receiving
+    /// into some of the frames already on the ring. A return value of
+    /// zero means the ring is full.
+    ///
+    /// # Safety
+    ///
+    /// See [`produce`].
+    ///
+    /// [`produce`]: Self::produce
+    #[inline]
+    pub unsafe fn produce_partial(&mut self, descs: &[FrameDesc]) -> usize {
+        let nb = self.nb_free(descs.len()).min(descs.len());
+
+        unsafe { self.produce(&descs[..nb]) }
+    }
+
     /// Same as [`produce`] but for a single frame descriptor.
     ///
     /// # Safety
@@ -87,6 +130,8 @@ impl FillQueue {
 
         let cnt = unsafe { libxdp_sys::xsk_ring_prod__reserve(self.ring.as_mut(), 1, &mut idx) };
 
+        self.ring.record_reserve(idx, 1, cnt);
+
         if cnt > 0 {
             unsafe {
                 *libxdp_sys::xsk_ring_prod__fill_addr(self.ring.as_mut(), idx) = desc.addr as u64
@@ -151,6 +196,60 @@ impl FillQueue {
         Ok(cnt)
     }
 
+    /// Reset `descs` to the state [`Umem::new`] handed them out in,
+    /// i.e. pointing at the start of their frame's packet data with
+    /// zero lengths and no options, and submit as many as the ring has
+    /// room for. Returns the number submitted, which are those at the
+    /// start of `descs`. The rest are still reset, and are the caller's
+    /// to retry.
+    ///
+    /// This saves a separate pass over frames being recycled, e.g.
+    /// received frames that have been dropped, and means they can't be
+    /// reused later with a stale length or data offset. As with
+    /// [`produce_partial`], a ring with too little room for all of
+    /// `descs` still takes what fits.
+    ///
+    /// # Safety
+    ///
+    /// See [`produce`].
+    ///
+    /// [`produce`]: Self::produce
+    /// [`produce_partial`]: Self::produce_partial
+    pub unsafe fn recycle_reset(&mut self, descs: &mut [FrameDesc]) -> usize {
+        for desc in descs.iter_mut() {
+            self.umem.mem.reset_desc(desc);
+        }
+
+        unsafe { self.produce_partial(descs) }
+    }
+
+    /// Fill the ring with as many of `descs` as it has room for and
+    /// kick the kernel straight away, so the driver has its rx
+    /// descriptors posted before the first packet arrives. Returns the
+    /// number of frames submitted.
+    ///
+    /// Intended to be called once, after the socket is set up and
+    /// before traffic is expected. Unlike [`produce_and_wakeup`] it
+    /// doesn't stop at the first frame that doesn't fit, and the kernel
+    /// is kicked with a `recvfrom` whether or not it has asked for a
+    /// wakeup, since some drivers only arm their rx queue once woken.
+    /// The frames in `descs` beyond the returned count weren't
+    /// submitted and remain the caller's.
+    ///
+    /// # Safety
+    ///
+    /// See [`produce`].
+    ///
+    /// [`produce_and_wakeup`]: Self::produce_and_wakeup
+    /// [`produce`]: Self::produce
+    pub unsafe fn prime(&mut self, descs: &[FrameDesc], socket_fd: &mut Fd) -> io::Result<usize> {
+        let cnt = unsafe { self.produce_partial(descs) };
+
+        socket_fd.recvfrom_wakeup()?;
+
+        Ok(cnt)
+    }
+
     /// Wake up the kernel to let it know it can continue using the
     /// fill ring to process received data.
     ///
@@ -164,6 +263,45 @@ impl FillQueue {
         Ok(())
     }
 
+    /// Same as [`wakeup`], but parks the calling thread until there
+    /// are frames to receive rather than returning straight away.
+    ///
+    /// This suits a dedicated rx thread per queue, which would
+    /// otherwise have to spin on [`wakeup`] with a zero timeout. Note
+    /// that while it's blocked the thread is tied to this one socket,
+    /// and won't return until a packet arrives or a signal interrupts
+    /// it, so it isn't a fit for threads servicing several sockets.
+    ///
+    /// The kernel rejects `recvfrom` on an AF_XDP socket without
+    /// `MSG_DONTWAIT` (with `EOPNOTSUPP`), so the blocking is done with
+    /// an untimed `poll` instead, which drives the fill ring the same
+    /// way.
+    ///
+    /// [`wakeup`]: Self::wakeup
+    #[inline]
+    pub fn wakeup_blocking(&self, fd: &mut Fd) -> io::Result<()> {
+        self.wakeup(fd, -1)
+    }
+
+    /// Same as [`wakeup`], but first sets the socket's busy poll
+    /// budget to `budget` and then kicks the kernel with a
+    /// non-blocking `recvfrom` rather than a `poll`.
+    ///
+    /// With busy polling enabled on the socket this makes the calling
+    /// thread process up to `budget` packets in the driver's napi
+    /// context, trading CPU time for lower latency. See
+    /// [`Fd::set_busy_poll_budget`] for the requirements, in particular
+    /// that `SO_BUSY_POLL_BUDGET` is only available from linux 5.11.
+    /// On older kernels this returns an `ENOPROTOOPT` error without
+    /// waking the kernel.
+    ///
+    /// [`wakeup`]: Self::wakeup
+    #[inline]
+    pub fn wakeup_with_budget(&self, fd: &mut Fd, budget: u32) -> io::Result<()> {
+        fd.set_busy_poll_budget(budget)?;
+        fd.recvfrom_wakeup()
+    }
+
     /// Check if the [`XDP_USE_NEED_WAKEUP`] flag is set on the fill
     /// ring. If so then this means a call to [`wakeup`] will be
     /// required to continue processing received data.
@@ -178,4 +316,33 @@ impl FillQueue {
     pub fn needs_wakeup(&self) -> bool {
         unsafe { libxdp_sys::xsk_ring_prod__needs_wakeup(self.ring.as_ref()) != 0 }
     }
+
+    /// The raw flags word shared with the kernel for the fill ring.
+    ///
+    /// Of the bits currently defined by the kernel, only
+    /// [`XDP_RING_NEED_WAKEUP`] (bit 0) is used on this ring. It's set
+    /// when the kernel has run out of fill ring entries and must be
+    /// woken up, e.g. via [`wakeup`], to resume receiving, and is only
+    /// ever set if the socket was bound with [`XDP_USE_NEED_WAKEUP`].
+    /// This is the bit checked by [`needs_wakeup`]. Any other bits are
+    /// returned as is.
+    ///
+    /// [`needs_wakeup`]: Self::needs_wakeup
+    /// [`wakeup`]: Self::wakeup
+    /// [`XDP_RING_NEED_WAKEUP`]: libxdp_sys::XDP_RING_NEED_WAKEUP
+    /// [`XDP_USE_NEED_WAKEUP`]: libxdp_sys::XDP_USE_NEED_WAKEUP
+    #[inline]
+    pub fn ring_flags(&self) -> u32 {
+        self.ring.flags()
+    }
+
+    /// Userspace side statistics for this queue's ring. See
+    /// [`RingStats`] for details.
+    ///
+    /// Only available with the `ring-stats` feature enabled.
+    #[cfg(feature = "ring-stats")]
+    #[inline]
+    pub fn ring_stats(&self) -> RingStats {
+        self.ring.stats()
+    }
 }
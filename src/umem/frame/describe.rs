@@ -0,0 +1,135 @@
+//! Decoding frame descriptors for logging.
+
+use std::fmt;
+
+use super::{super::mem::split_addr, FrameDesc, XDP_PKT_CONTD};
+
+/// Option bits [`DescInfo`] knows the names of.
+const NAMED_OPTIONS: [(u32, &str); 1] = [(XDP_PKT_CONTD, "PKT_CONTD")];
+
+/// A decoded view of a [`FrameDesc`], returned by
+/// [`FrameDesc::describe`].
+///
+/// Formats as a single line suitable for logging, e.g.
+/// `frame 3 addr=0x1900 len=60 headroom=0 options=PKT_CONTD`. An
+/// unaligned mode offset, if present, is split out of the address,
+/// and any option bits without a name are shown in hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescInfo {
+    frame_index: usize,
+    addr: usize,
+    offset: usize,
+    data_len: usize,
+    headroom_len: usize,
+    options: u32,
+}
+
+impl DescInfo {
+    pub(super) fn new(desc: &FrameDesc, frame_index: usize) -> Self {
+        let (addr, offset) = split_addr(desc.addr);
+
+        Self {
+            frame_index,
+            addr,
+            offset,
+            data_len: desc.lengths.data,
+            headroom_len: desc.lengths.headroom,
+            options: desc.options,
+        }
+    }
+
+    /// The index of the descriptor's frame within its
+    /// [`Umem`](crate::Umem).
+    #[inline]
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    /// The descriptor's address, less any unaligned mode offset.
+    #[inline]
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// The unaligned mode offset carried in the upper bits of the
+    /// descriptor's address, zero in aligned mode.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for DescInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "frame {} addr={:#x}", self.frame_index, self.addr)?;
+
+        if self.offset != 0 {
+            write!(f, " offset={}", self.offset)?;
+        }
+
+        write!(
+            f,
+            " len={} headroom={} options=",
+            self.data_len, self.headroom_len
+        )?;
+
+        if self.options == 0 {
+            return write!(f, "0");
+        }
+
+        let mut remaining = self.options;
+        let mut sep = "";
+
+        for (bit, name) in NAMED_OPTIONS {
+            if remaining & bit != 0 {
+                write!(f, "{}{}", sep, name)?;
+                remaining &= !bit;
+                sep = "|";
+            }
+        }
+
+        if remaining != 0 {
+            write!(f, "{}{:#x}", sep, remaining)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(addr: usize, options: u32) -> DescInfo {
+        let mut desc = FrameDesc::new(addr);
+        desc.lengths.data = 60;
+        desc.options = options;
+
+        DescInfo::new(&desc, 3)
+    }
+
+    #[test]
+    fn options_are_decoded_into_names() {
+        assert_eq!(
+            info(0x1900, 0).to_string(),
+            "frame 3 addr=0x1900 len=60 headroom=0 options=0"
+        );
+
+        assert_eq!(
+            info(0x1900, XDP_PKT_CONTD | 0x4).to_string(),
+            "frame 3 addr=0x1900 len=60 headroom=0 options=PKT_CONTD|0x4"
+        );
+    }
+
+    #[test]
+    fn unaligned_offset_is_split_out() {
+        let info = info(0x1800 | (0x100 << 48), 0);
+
+        assert_eq!(info.addr(), 0x1800);
+        assert_eq!(info.offset(), 0x100);
+        assert_eq!(
+            info.to_string(),
+            "frame 3 addr=0x1800 offset=256 len=60 headroom=0 options=0"
+        );
+    }
+}
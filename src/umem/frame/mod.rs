@@ -4,11 +4,21 @@
 mod cursor;
 pub use cursor::Cursor;
 
+mod describe;
+pub use describe::DescInfo;
+
 use std::{
     borrow::{Borrow, BorrowMut},
     ops::{Deref, DerefMut},
 };
 
+use super::Umem;
+
+/// Set in a [`FrameDesc`]'s [`options`](FrameDesc::options) when the
+/// packet continues in the next descriptor, i.e. all but the last
+/// frame of a multi-buffer packet.
+pub const XDP_PKT_CONTD: u32 = 1 << 0;
+
 /// The length (in bytes) of data in a frame's packet data and
 /// headroom segments.
 ///
@@ -97,6 +107,16 @@ impl FrameDesc {
         self.options = options
     }
 
+    /// A decoded, human readable view of this descriptor for logging,
+    /// including the index of its frame within `umem`.
+    ///
+    /// The raw values remain available through this descriptor's
+    /// accessors.
+    #[inline]
+    pub fn describe(&self, umem: &Umem) -> DescInfo {
+        DescInfo::new(self, umem.frame_index(self))
+    }
+
     #[inline]
     pub(crate) fn write_xdp_desc(&self, desc: &mut libxdp_sys::xdp_desc) {
         desc.addr = self.addr as u64;
@@ -383,6 +403,94 @@ impl DerefMut for DataMut<'_> {
     }
 }
 
+/// A mutable view of an entire [`Umem`](crate::umem::Umem) frame,
+/// returned by [`Umem::frame_bytes_mut`](crate::umem::Umem::frame_bytes_mut).
+///
+/// A frame is laid out as three consecutive regions:
+///
+/// | Region       | Offset                             | Length             |
+/// |--------------|------------------------------------|--------------------|
+/// | [`metadata`] | `0`                                | [`xdp_headroom`]   |
+/// | [`headroom`] | [`xdp_headroom`]                   | [`frame_headroom`] |
+/// | [`data`]     | `xdp_headroom + frame_headroom`    | [`mtu`]            |
+///
+/// Unlike [`HeadroomMut`] and [`DataMut`], each region is exposed in
+/// full regardless of the lengths held in the frame's descriptor,
+/// which are left untouched. This makes it possible to, for example,
+/// shift a packet's contents across region boundaries, after which
+/// the descriptor will need updating to match by the caller.
+///
+/// [`metadata`]: Self::metadata
+/// [`headroom`]: Self::headroom
+/// [`data`]: Self::data
+/// [`xdp_headroom`]: crate::config::UmemConfig::xdp_headroom
+/// [`frame_headroom`]: crate::config::UmemConfig::frame_headroom
+/// [`mtu`]: crate::config::UmemConfig::mtu
+#[derive(Debug)]
+pub struct FrameBytes<'umem> {
+    buf: &'umem mut [u8],
+    xdp_headroom: usize,
+    frame_headroom: usize,
+}
+
+impl<'umem> FrameBytes<'umem> {
+    pub(super) fn new(buf: &'umem mut [u8], xdp_headroom: usize, frame_headroom: usize) -> Self {
+        debug_assert!(xdp_headroom + frame_headroom <= buf.len());
+
+        Self {
+            buf,
+            xdp_headroom,
+            frame_headroom,
+        }
+    }
+
+    /// The entire frame.
+    #[inline]
+    pub fn as_slice(&self) -> &[u8] {
+        self.buf
+    }
+
+    /// The entire frame, mutably.
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.buf
+    }
+
+    /// Offset of the headroom region from the start of the frame.
+    #[inline]
+    pub fn headroom_offset(&self) -> usize {
+        self.xdp_headroom
+    }
+
+    /// Offset of the packet data region from the start of the frame.
+    #[inline]
+    pub fn data_offset(&self) -> usize {
+        self.xdp_headroom + self.frame_headroom
+    }
+
+    /// The XDP headroom region at the start of the frame, reserved by
+    /// the kernel and available to the XDP program, e.g. for passing
+    /// metadata.
+    #[inline]
+    pub fn metadata(&mut self) -> &mut [u8] {
+        &mut self.buf[..self.xdp_headroom]
+    }
+
+    /// The user headroom region.
+    #[inline]
+    pub fn headroom(&mut self) -> &mut [u8] {
+        let offset = self.headroom_offset();
+        &mut self.buf[offset..offset + self.frame_headroom]
+    }
+
+    /// The packet data region.
+    #[inline]
+    pub fn data(&mut self) -> &mut [u8] {
+        let offset = self.data_offset();
+        &mut self.buf[offset..]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use core::slice;
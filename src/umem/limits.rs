@@ -0,0 +1,120 @@
+//! Estimating how much memory the host can provide for a UMEM.
+
+use std::{fs, io};
+
+/// Bit of the effective capability set for `CAP_IPC_LOCK`, which
+/// exempts a process from `RLIMIT_MEMLOCK` when registering a UMEM.
+const CAP_IPC_LOCK: u32 = 14;
+
+/// An upper bound on the number of frames of `frame_size` bytes that
+/// a new UMEM could be created with. See
+/// [`Umem::max_frames`](super::Umem::max_frames).
+pub(super) fn max_frames(frame_size: usize, use_huge_pages: bool) -> io::Result<usize> {
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+
+    let mut max_bytes = if use_huge_pages {
+        huge_pages_free_bytes(&meminfo)
+    } else {
+        meminfo_kb(&meminfo, "MemAvailable").map(|kb| kb.saturating_mul(1024))
+    }
+    .ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Other,
+            "failed to find available memory in `/proc/meminfo`",
+        )
+    })?;
+
+    let exempt_from_memlock = fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| has_cap_ipc_lock(&status))
+        .unwrap_or(false);
+
+    if !exempt_from_memlock {
+        if let Some(limit) = memlock_limit()? {
+            max_bytes = max_bytes.min(limit);
+        }
+    }
+
+    Ok(max_bytes / frame_size)
+}
+
+/// The soft `RLIMIT_MEMLOCK` in bytes, or `None` if unlimited.
+fn memlock_limit() -> io::Result<Option<usize>> {
+    let mut rlim = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rlim) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if rlim.rlim_cur == libc::RLIM_INFINITY {
+        Ok(None)
+    } else {
+        Ok(Some(rlim.rlim_cur as usize))
+    }
+}
+
+/// The value of `/proc/meminfo` field `key`, which (bar the huge page
+/// counts) is given in kB.
+fn meminfo_kb(meminfo: &str, key: &str) -> Option<usize> {
+    meminfo.lines().find_map(|line| {
+        let (name, rest) = line.split_once(':')?;
+
+        if name.trim() == key {
+            rest.split_whitespace().next()?.parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+/// The total size of the free default sized huge pages.
+fn huge_pages_free_bytes(meminfo: &str) -> Option<usize> {
+    let free = meminfo_kb(meminfo, "HugePages_Free")?;
+    let page_kb = meminfo_kb(meminfo, "Hugepagesize")?;
+
+    Some(free.saturating_mul(page_kb).saturating_mul(1024))
+}
+
+/// Whether `CAP_IPC_LOCK` is in the effective capability set listed
+/// in `/proc/<pid>/status`.
+fn has_cap_ipc_lock(status: &str) -> Option<bool> {
+    let cap_eff = status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))?
+        .trim();
+
+    let caps = u64::from_str_radix(cap_eff, 16).ok()?;
+
+    Some(caps & (1 << CAP_IPC_LOCK) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMINFO: &str = "MemTotal:       16318144 kB
+MemFree:         1263400 kB
+MemAvailable:    9046596 kB
+HugePages_Total:      64
+HugePages_Free:       60
+Hugepagesize:       2048 kB
+";
+
+    #[test]
+    fn meminfo_fields_are_parsed() {
+        assert_eq!(meminfo_kb(MEMINFO, "MemAvailable"), Some(9046596));
+        assert_eq!(meminfo_kb(MEMINFO, "Mem"), None);
+
+        assert_eq!(huge_pages_free_bytes(MEMINFO), Some(60 * 2048 * 1024));
+    }
+
+    #[test]
+    fn cap_ipc_lock_is_detected() {
+        assert_eq!(has_cap_ipc_lock("CapEff:\t000001ffffffffff\n"), Some(true));
+        assert_eq!(has_cap_ipc_lock("CapEff:\t0000000000000000\n"), Some(false));
+        assert_eq!(has_cap_ipc_lock("Name:\tfoo\n"), None);
+    }
+}
@@ -0,0 +1,524 @@
+//! A mocked kernel for a single AF_XDP socket, which loops any frames
+//! submitted on the tx ring back around to the rx ring.
+//!
+//! Allows the full frame lifecycle, i.e. fill ring -> rx ring and tx
+//! ring -> comp ring, to be exercised through the crate's own queue
+//! types in unit tests without requiring a real interface or any
+//! special privileges.
+
+use libxdp_sys::{xdp_desc, XDP_RING_NEED_WAKEUP};
+use std::{
+    cell::UnsafeCell,
+    num::NonZeroU32,
+    ptr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{
+    config::{BindFlags, SocketConfig, UmemConfig},
+    ring::{XskRingCons, XskRingProd},
+    socket::{RxQueue, Socket, TxQueue},
+};
+
+use super::{frame::FrameDesc, mem::UmemRegion, CompQueue, FillQueue, FrameLayout, Umem};
+
+/// The memory backing a single ring, i.e. what the kernel would
+/// allocate and userspace would then `mmap`.
+struct RingMem<T> {
+    producer: Box<AtomicU32>,
+    consumer: Box<AtomicU32>,
+    flags: Box<AtomicU32>,
+    descs: Box<[UnsafeCell<T>]>,
+    size: u32,
+}
+
+impl<T: Copy> RingMem<T> {
+    fn new(size: u32, init: T) -> Self {
+        Self {
+            producer: Box::new(AtomicU32::new(0)),
+            consumer: Box::new(AtomicU32::new(0)),
+            flags: Box::new(AtomicU32::new(0)),
+            descs: (0..size).map(|_| UnsafeCell::new(init)).collect(),
+            size,
+        }
+    }
+
+    /// A userspace producer handle for this ring, initialised in the
+    /// same manner as libxdp would.
+    fn prod(&self) -> XskRingProd {
+        let mut ring = XskRingProd::default();
+        let r = ring.as_mut();
+
+        r.cached_prod = 0;
+        r.cached_cons = self.size;
+        r.mask = self.size - 1;
+        r.size = self.size;
+        r.producer = self.producer.as_ptr();
+        r.consumer = self.consumer.as_ptr();
+        r.ring = self.descs.as_ptr() as *mut _;
+        r.flags = self.flags.as_ptr();
+
+        ring
+    }
+
+    /// A userspace consumer handle for this ring, initialised in the
+    /// same manner as libxdp would.
+    fn cons(&self) -> XskRingCons {
+        let mut ring = XskRingCons::default();
+        let r = ring.as_mut();
+
+        r.cached_prod = 0;
+        r.cached_cons = 0;
+        r.mask = self.size - 1;
+        r.size = self.size;
+        r.producer = self.producer.as_ptr();
+        r.consumer = self.consumer.as_ptr();
+        r.ring = self.descs.as_ptr() as *mut _;
+        r.flags = self.flags.as_ptr();
+
+        ring
+    }
+
+    /// Number of entries the kernel may consume.
+    fn pending(&self) -> u32 {
+        self.producer
+            .load(Ordering::Acquire)
+            .wrapping_sub(self.consumer.load(Ordering::Relaxed))
+    }
+
+    /// Number of entries the kernel may produce.
+    fn free(&self) -> u32 {
+        self.size
+            - self
+                .producer
+                .load(Ordering::Relaxed)
+                .wrapping_sub(self.consumer.load(Ordering::Acquire))
+    }
+
+    fn pop(&self) -> Option<T> {
+        if self.pending() == 0 {
+            return None;
+        }
+
+        let cons = self.consumer.load(Ordering::Relaxed);
+
+        // SAFETY: the entry at `cons` has been published by the
+        // producer and won't be touched again until we release it.
+        let entry = unsafe { *self.descs[(cons & (self.size - 1)) as usize].get() };
+
+        self.consumer.store(cons.wrapping_add(1), Ordering::Release);
+
+        Some(entry)
+    }
+
+    fn push(&self, entry: T) -> bool {
+        if self.free() == 0 {
+            return false;
+        }
+
+        let prod = self.producer.load(Ordering::Relaxed);
+
+        // SAFETY: the entry at `prod` has been released by the
+        // consumer and won't be read until we publish it.
+        unsafe { *self.descs[(prod & (self.size - 1)) as usize].get() = entry };
+
+        self.producer.store(prod.wrapping_add(1), Ordering::Release);
+
+        true
+    }
+
+    fn set_needs_wakeup(&self, needs_wakeup: bool) {
+        if needs_wakeup {
+            self.flags.fetch_or(XDP_RING_NEED_WAKEUP, Ordering::Release);
+        } else {
+            self.flags
+                .fetch_and(!XDP_RING_NEED_WAKEUP, Ordering::Release);
+        }
+    }
+}
+
+/// The userspace queues of a socket whose kernel side is a
+/// [`Loopback`], sharing a mock [`Umem`] over the loopback's memory.
+///
+/// These refer to memory owned by the [`Loopback`] and so must not
+/// outlive it. The socket was never created, so anything needing its
+/// file descriptor, e.g. a wakeup, fails. Use
+/// [`Loopback::wakeup`] instead.
+#[derive(Debug)]
+pub(crate) struct LoopbackQueues {
+    pub fq: FillQueue,
+    pub cq: CompQueue,
+    pub tx_q: TxQueue,
+    pub rx_q: RxQueue,
+}
+
+/// Plays the part of the kernel and driver for a socket whose tx
+/// ring is wired directly to its rx ring.
+///
+/// Ring sizes are taken from the provided configs and respected in
+/// the same way the kernel would, i.e. frames are dropped if the fill
+/// ring is empty or the rx ring is full, and tx descriptors are only
+/// consumed while there is space on the comp ring. If the
+/// [`XDP_USE_NEED_WAKEUP`](BindFlags::XDP_USE_NEED_WAKEUP) bind flag
+/// is set then nothing is processed until [`wakeup`](Self::wakeup) is
+/// called.
+pub(crate) struct Loopback {
+    layout: FrameLayout,
+    mem: UmemRegion,
+    fill: RingMem<u64>,
+    comp: RingMem<u64>,
+    tx: RingMem<xdp_desc>,
+    rx: RingMem<xdp_desc>,
+    need_wakeup: bool,
+    rx_ring_full: u64,
+    rx_fill_ring_empty_descs: u64,
+    tx_invalid_descs: u64,
+}
+
+impl Loopback {
+    pub(crate) fn new(
+        umem_config: UmemConfig,
+        socket_config: SocketConfig,
+        frame_count: NonZeroU32,
+    ) -> (Self, LoopbackQueues, Vec<FrameDesc>) {
+        let layout: FrameLayout = umem_config.into();
+
+        let mem = UmemRegion::new(frame_count, layout, false).expect("failed to create mock UMEM");
+
+        let empty_desc = xdp_desc {
+            addr: 0,
+            len: 0,
+            options: 0,
+        };
+
+        let loopback = Self {
+            layout,
+            mem,
+            fill: RingMem::new(umem_config.fill_queue_size().get(), 0),
+            comp: RingMem::new(umem_config.comp_queue_size().get(), 0),
+            tx: RingMem::new(socket_config.tx_queue_size().get(), empty_desc),
+            rx: RingMem::new(socket_config.rx_queue_size().get(), empty_desc),
+            need_wakeup: socket_config
+                .bind_flags()
+                .contains(BindFlags::XDP_USE_NEED_WAKEUP),
+            rx_ring_full: 0,
+            rx_fill_ring_empty_descs: 0,
+            tx_invalid_descs: 0,
+        };
+
+        // As per the kernel, the tx ring must be woken up the first
+        // time if using need wakeup.
+        loopback.tx.set_needs_wakeup(loopback.need_wakeup);
+
+        let umem = Umem::mock(loopback.mem.clone(), umem_config);
+
+        let (fq, cq) = (loopback.fill.prod(), loopback.comp.cons());
+
+        let (tx_q, rx_q) = Socket::mock(
+            socket_config,
+            &umem,
+            loopback.tx.prod(),
+            loopback.rx.cons(),
+            &fq,
+            &cq,
+        );
+
+        let queues = LoopbackQueues {
+            fq: FillQueue::new(fq, umem.clone()),
+            cq: CompQueue::new(cq, umem),
+            tx_q,
+            rx_q,
+        };
+
+        let descs = (0..frame_count.get() as usize)
+            .map(|i| {
+                FrameDesc::new(
+                    (i * layout.frame_size()) + layout.xdp_headroom + layout.frame_headroom,
+                )
+            })
+            .collect();
+
+        (loopback, queues, descs)
+    }
+
+    /// The UMEM region frames are read from and written to.
+    pub(crate) fn mem(&self) -> &UmemRegion {
+        &self.mem
+    }
+
+    /// Simulate the driver getting a chance to run without being
+    /// explicitly woken up, e.g. due to an interrupt. Does nothing if
+    /// the tx ring is flagged as needing a wakeup.
+    pub(crate) fn tick(&mut self) {
+        if self.tx.flags.load(Ordering::Acquire) & XDP_RING_NEED_WAKEUP == 0 {
+            self.process();
+        }
+    }
+
+    /// Simulate a wakeup syscall, i.e. a `sendto`, `recvfrom` or
+    /// `poll` on the socket's file descriptor.
+    pub(crate) fn wakeup(&mut self) {
+        self.tx.set_needs_wakeup(false);
+        self.fill.set_needs_wakeup(false);
+
+        self.process();
+
+        // The driver goes back to sleep once there's nothing left to
+        // process.
+        if self.need_wakeup {
+            self.tx.set_needs_wakeup(true);
+        }
+    }
+
+    /// Packets dropped due to the rx ring being full.
+    pub(crate) fn rx_ring_full(&self) -> u64 {
+        self.rx_ring_full
+    }
+
+    /// Packets dropped due to the fill ring being empty.
+    pub(crate) fn rx_fill_ring_empty_descs(&self) -> u64 {
+        self.rx_fill_ring_empty_descs
+    }
+
+    /// Tx descriptors skipped since they didn't describe a valid
+    /// region of the UMEM.
+    pub(crate) fn tx_invalid_descs(&self) -> u64 {
+        self.tx_invalid_descs
+    }
+
+    fn process(&mut self) {
+        while self.comp.free() > 0 {
+            let desc = match self.tx.pop() {
+                Some(desc) => desc,
+                None => break,
+            };
+
+            let addr = desc.addr as usize;
+            let len = desc.len as usize;
+
+            if len > self.layout.mtu || addr + len > self.mem.len() {
+                self.tx_invalid_descs += 1;
+                continue;
+            }
+
+            self.receive(addr, len);
+
+            self.comp.push(desc.addr);
+        }
+    }
+
+    fn receive(&mut self, src_addr: usize, len: usize) {
+        if self.rx.free() == 0 {
+            self.rx_ring_full += 1;
+            return;
+        }
+
+        let fill_addr = match self.fill.pop() {
+            Some(addr) => addr as usize,
+            None => {
+                self.rx_fill_ring_empty_descs += 1;
+                self.fill.set_needs_wakeup(self.need_wakeup);
+                return;
+            }
+        };
+
+        let frame_size = self.layout.frame_size();
+
+        let dst_addr = (fill_addr - (fill_addr % frame_size))
+            + self.layout.xdp_headroom
+            + self.layout.frame_headroom;
+
+        // SAFETY: both addresses have been checked to lie within the
+        // UMEM and, after submission to the tx and fill rings
+        // respectively, userspace should not be accessing either
+        // frame.
+        unsafe {
+            let base = self.mem.as_ptr() as *mut u8;
+            ptr::copy(base.add(src_addr), base.add(dst_addr), len);
+        }
+
+        self.rx.push(xdp_desc {
+            addr: dst_addr as u64,
+            len: len as u32,
+            options: 0,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::TryInto, io::Write};
+
+    use crate::config::QueueSize;
+
+    use super::*;
+
+    const FRAME_COUNT: u32 = 16;
+
+    fn build_loopback(
+        need_wakeup: bool,
+        rx_q_size: u32,
+    ) -> (Loopback, LoopbackQueues, Vec<FrameDesc>) {
+        let umem_config = UmemConfig::builder().frame_headroom(32).build().unwrap();
+
+        let bind_flags = if need_wakeup {
+            BindFlags::XDP_USE_NEED_WAKEUP
+        } else {
+            BindFlags::empty()
+        };
+
+        let socket_config = SocketConfig::builder()
+            .rx_queue_size(QueueSize::new(rx_q_size).unwrap())
+            .bind_flags(bind_flags)
+            .build();
+
+        Loopback::new(umem_config, socket_config, FRAME_COUNT.try_into().unwrap())
+    }
+
+    fn consume_rx(rx_q: &mut RxQueue, nb: usize) -> Vec<FrameDesc> {
+        let mut descs = vec![FrameDesc::default(); nb];
+
+        let cnt = unsafe { rx_q.consume(&mut descs) };
+
+        descs.truncate(cnt);
+        descs
+    }
+
+    fn consume_comp(cq: &mut CompQueue, nb: usize) -> Vec<usize> {
+        let mut descs = vec![FrameDesc::default(); nb];
+
+        let cnt = unsafe { cq.consume(&mut descs) };
+
+        descs[..cnt].iter().map(|desc| desc.addr()).collect()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn frames_sent_are_received_and_completed() {
+        let (mut loopback, mut queues, mut descs) = build_loopback(false, 4);
+
+        unsafe { loopback.mem().data_mut(&mut descs[0]) }
+            .cursor()
+            .write_all(b"hello")
+            .unwrap();
+
+        assert_eq!(unsafe { queues.fq.produce(&descs[1..2]) }, 1);
+        assert_eq!(unsafe { queues.tx_q.produce(&descs[..1]) }, 1);
+
+        loopback.tick();
+
+        let recvd = consume_rx(&mut queues.rx_q, 4);
+
+        assert_eq!(recvd.len(), 1);
+        assert_eq!(recvd[0].addr(), descs[1].addr());
+        assert_eq!(recvd[0].lengths().data(), 5);
+
+        assert_eq!(
+            unsafe { loopback.mem().data(&recvd[0]) }.contents(),
+            b"hello"
+        );
+
+        assert_eq!(consume_comp(&mut queues.cq, 4), vec![descs[0].addr()]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn completed_and_received_frames_can_be_cycled_again() {
+        let (mut loopback, mut queues, mut descs) = build_loopback(false, 4);
+
+        let (tx_descs, rx_descs) = descs.split_at_mut(4);
+
+        assert_eq!(unsafe { queues.fq.produce(&rx_descs[..4]) }, 4);
+
+        for round in 0..3u8 {
+            for desc in tx_descs.iter_mut() {
+                unsafe { loopback.mem().data_mut(desc) }
+                    .cursor()
+                    .write_all(&[round; 8])
+                    .unwrap();
+            }
+
+            assert_eq!(unsafe { queues.tx_q.produce(tx_descs) }, 4);
+
+            loopback.tick();
+
+            assert_eq!(unsafe { queues.rx_q.consume(&mut rx_descs[..4]) }, 4);
+
+            for desc in rx_descs[..4].iter() {
+                assert_eq!(unsafe { loopback.mem().data(desc) }.contents(), &[round; 8]);
+            }
+
+            // Both sides are ours again, so hand them straight back
+            assert_eq!(unsafe { queues.cq.consume(tx_descs) }, 4);
+            assert_eq!(unsafe { queues.fq.produce(&rx_descs[..4]) }, 4);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn frames_are_dropped_but_completed_if_fill_ring_empty() {
+        let (mut loopback, mut queues, descs) = build_loopback(true, 4);
+
+        assert_eq!(unsafe { queues.tx_q.produce(&descs[..2]) }, 2);
+
+        loopback.wakeup();
+
+        assert!(consume_rx(&mut queues.rx_q, 4).is_empty());
+        assert_eq!(loopback.rx_fill_ring_empty_descs(), 2);
+        assert!(queues.fq.needs_wakeup());
+
+        assert_eq!(consume_comp(&mut queues.cq, 4).len(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn nothing_is_processed_until_woken_if_using_need_wakeup() {
+        let (mut loopback, mut queues, descs) = build_loopback(true, 4);
+
+        assert!(queues.tx_q.needs_wakeup());
+
+        assert_eq!(unsafe { queues.fq.produce(&descs[1..2]) }, 1);
+        assert_eq!(unsafe { queues.tx_q.produce(&descs[..1]) }, 1);
+
+        loopback.tick();
+
+        assert!(consume_comp(&mut queues.cq, 4).is_empty());
+        assert!(queues.tx_q.needs_wakeup());
+
+        loopback.wakeup();
+
+        assert_eq!(consume_rx(&mut queues.rx_q, 4).len(), 1);
+        assert_eq!(consume_comp(&mut queues.cq, 4).len(), 1);
+        assert!(queues.tx_q.needs_wakeup());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn frames_are_dropped_if_rx_ring_full() {
+        let (mut loopback, mut queues, descs) = build_loopback(false, 2);
+
+        assert_eq!(unsafe { queues.fq.produce(&descs[4..8]) }, 4);
+        assert_eq!(unsafe { queues.tx_q.produce(&descs[..4]) }, 4);
+
+        loopback.tick();
+
+        assert_eq!(loopback.rx_ring_full(), 2);
+        assert_eq!(consume_rx(&mut queues.rx_q, 4).len(), 2);
+        assert_eq!(consume_comp(&mut queues.cq, 4).len(), 4);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn oversized_tx_descs_are_skipped() {
+        let (mut loopback, mut queues, mut descs) = build_loopback(false, 4);
+
+        descs[0].lengths.data = loopback.layout.mtu + 1;
+
+        assert_eq!(unsafe { queues.tx_q.produce(&descs[..1]) }, 1);
+
+        loopback.tick();
+
+        assert_eq!(loopback.tx_invalid_descs(), 1);
+        assert!(consume_comp(&mut queues.cq, 4).is_empty());
+    }
+}
@@ -4,16 +4,31 @@ use mmap::Mmap;
 use std::{
     io,
     num::NonZeroU32,
-    ptr::NonNull,
+    ptr::{self, NonNull},
     slice,
     sync::{Arc, Mutex},
 };
 
 use super::{
-    frame::{Data, DataMut, FrameDesc, Headroom, HeadroomMut},
-    FrameLayout,
+    frame::{Data, DataMut, FrameBytes, FrameDesc, Headroom, HeadroomMut},
+    FrameLayout, InvalidDescReason,
 };
 
+const XSK_UNALIGNED_BUF_OFFSET_SHIFT: u64 = 48;
+const XSK_UNALIGNED_BUF_ADDR_MASK: u64 = (1 << XSK_UNALIGNED_BUF_OFFSET_SHIFT) - 1;
+
+/// Split `addr` into its base address and any unaligned mode offset
+/// carried in its upper bits, which is zero for aligned mode.
+#[inline]
+pub(crate) fn split_addr(addr: usize) -> (usize, usize) {
+    let addr = addr as u64;
+
+    (
+        (addr & XSK_UNALIGNED_BUF_ADDR_MASK) as usize,
+        (addr >> XSK_UNALIGNED_BUF_OFFSET_SHIFT) as usize,
+    )
+}
+
 /// A framed, memory mapped region which functions as the working
 /// memory for some UMEM.
 #[derive(Clone, Debug)]
@@ -66,6 +81,136 @@ impl UmemRegion {
         self.addr.as_ptr()
     }
 
+    /// The size of each frame's packet data segment.
+    #[inline]
+    pub fn mtu(&self) -> usize {
+        self.layout.mtu
+    }
+
+    /// See docs for [`super::Umem::fill_pattern`].
+    #[inline]
+    pub unsafe fn fill_pattern(&self, pattern: &[u8]) {
+        if pattern.is_empty() {
+            return;
+        }
+
+        // SAFETY: see `super::Umem::fill_pattern`.
+        let region = unsafe { slice::from_raw_parts_mut(self.as_ptr() as *mut u8, self.len) };
+
+        for chunk in region.chunks_mut(pattern.len()) {
+            chunk.copy_from_slice(&pattern[..chunk.len()]);
+        }
+    }
+
+    /// See docs for [`super::Umem::frame_index`].
+    #[inline]
+    pub fn frame_index(&self, addr: usize) -> usize {
+        let base = (addr as u64 & XSK_UNALIGNED_BUF_ADDR_MASK) as usize;
+
+        base / self.layout.frame_size()
+    }
+
+    /// The number of frames in the region.
+    #[inline]
+    pub fn frame_count(&self) -> usize {
+        self.len / self.layout.frame_size()
+    }
+
+    /// Reset `desc` to the state its frame was handed out in by
+    /// [`Umem::new`](super::Umem::new): pointing at the start of the
+    /// frame's packet data segment, with no data, headroom or options.
+    #[inline]
+    pub fn reset_desc(&self, desc: &mut FrameDesc) {
+        desc.addr = self.frame_index(desc.addr) * self.layout.frame_size()
+            + self.layout.xdp_headroom
+            + self.layout.frame_headroom;
+        desc.options = 0;
+        desc.lengths = Default::default();
+    }
+
+    /// Read the first byte of each page overlapped by the frame at
+    /// `addr`, stepping by `page_size`.
+    ///
+    /// # Safety
+    ///
+    /// See docs for [`super::Umem::prefault_descs`].
+    #[inline]
+    pub unsafe fn prefault(&self, addr: usize, page_size: usize) {
+        let frame_size = self.layout.frame_size();
+
+        let start = self.frame_index(addr) * frame_size;
+        let end = std::cmp::min(start + frame_size, self.len);
+
+        let base = self.as_ptr() as *const u8;
+
+        let mut offset = start;
+
+        while offset < end {
+            // SAFETY: `offset` is within the region, and the unsafe
+            // contract of `Umem::prefault_descs` guarantees nothing is
+            // writing to the frame.
+            unsafe { ptr::read_volatile(base.add(offset)) };
+
+            // Step to the start of the next page
+            offset = (offset / page_size + 1) * page_size;
+        }
+    }
+
+    /// See docs for [`super::Umem::available_headroom`].
+    #[inline]
+    pub fn available_headroom(&self, desc: &FrameDesc) -> usize {
+        let addr = desc.addr as u64;
+
+        // An unaligned mode address carries the data offset in its
+        // upper bits, with the lower bits giving the chunk's base.
+        let offset = addr >> XSK_UNALIGNED_BUF_OFFSET_SHIFT;
+
+        let (base, data_start) = if offset != 0 {
+            let base = (addr & XSK_UNALIGNED_BUF_ADDR_MASK) as usize;
+            (base, base + offset as usize)
+        } else {
+            let frame_size = self.layout.frame_size();
+            (desc.addr - (desc.addr % frame_size), desc.addr)
+        };
+
+        data_start.saturating_sub(base + self.layout.xdp_headroom)
+    }
+
+    /// Why `addr` can't point into one of this region's frames, if
+    /// it can't. Only aligned mode addresses are accepted.
+    #[inline]
+    fn check_addr(&self, addr: usize) -> Option<InvalidDescReason> {
+        if (addr as u64) >> XSK_UNALIGNED_BUF_OFFSET_SHIFT != 0 {
+            return Some(InvalidDescReason::UnalignedAddress);
+        }
+
+        if addr / self.layout.frame_size() >= self.frame_count() {
+            return Some(InvalidDescReason::OutOfRange);
+        }
+
+        None
+    }
+
+    /// See docs for [`super::Umem::validate_descs`].
+    pub fn validate_desc(&self, desc: &FrameDesc) -> Option<InvalidDescReason> {
+        if let Some(reason) = self.check_addr(desc.addr) {
+            return Some(reason);
+        }
+
+        let offset = desc.addr % self.layout.frame_size();
+
+        if offset != self.layout.xdp_headroom + self.layout.frame_headroom {
+            return Some(InvalidDescReason::Misaligned { offset });
+        }
+
+        if desc.lengths.headroom > self.layout.frame_headroom || desc.lengths.data > self.layout.mtu
+        {
+            return Some(InvalidDescReason::LengthOutOfRange);
+        }
+
+        None
+    }
+
     /// A pointer to the headroom segment of the frame described by
     /// `desc`.
     ///
@@ -157,4 +302,221 @@ impl UmemRegion {
 
         DataMut::new(&mut desc.lengths.data, data)
     }
+
+    /// See docs for [`super::Umem::frame_bytes_mut`].
+    #[inline]
+    pub unsafe fn frame_bytes_mut(&mut self, desc: &FrameDesc) -> Option<FrameBytes> {
+        if self.check_addr(desc.addr).is_some() {
+            return None;
+        }
+
+        let frame_size = self.layout.frame_size();
+        let base = desc.addr - (desc.addr % frame_size);
+
+        // SAFETY: `check_addr` ensures the frame at `base` lies within
+        // the region, the rest is covered by
+        // `super::Umem::frame_bytes_mut`.
+        let buf =
+            unsafe { slice::from_raw_parts_mut((self.as_ptr() as *mut u8).add(base), frame_size) };
+
+        Some(FrameBytes::new(
+            buf,
+            self.layout.xdp_headroom,
+            self.layout.frame_headroom,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::TryInto, io::Write, slice};
+
+    use super::*;
+
+    #[test]
+    fn fill_pattern_repeats_across_whole_region() {
+        let layout = FrameLayout {
+            xdp_headroom: 4,
+            frame_headroom: 8,
+            mtu: 12,
+        };
+
+        let umem_region = UmemRegion::new(2.try_into().unwrap(), layout, false).unwrap();
+
+        let pattern = [0xde, 0xad, 0xbe, 0xef, 0x01];
+
+        unsafe { umem_region.fill_pattern(&pattern) };
+
+        let region =
+            unsafe { slice::from_raw_parts(umem_region.as_ptr() as *const u8, umem_region.len()) };
+
+        let expected: Vec<u8> = pattern.iter().copied().cycle().take(region.len()).collect();
+
+        assert_eq!(region, &expected[..]);
+    }
+
+    #[test]
+    fn available_headroom_is_distance_from_xdp_headroom_to_data() {
+        let layout = FrameLayout {
+            xdp_headroom: 4,
+            frame_headroom: 8,
+            mtu: 12,
+        };
+
+        let umem_region = UmemRegion::new(2.try_into().unwrap(), layout, false).unwrap();
+
+        let frame_size = layout.frame_size();
+
+        // Untouched frame, data starts straight after the headroom
+        let desc = FrameDesc::new(frame_size + layout.xdp_headroom + layout.frame_headroom);
+        assert_eq!(umem_region.available_headroom(&desc), layout.frame_headroom);
+
+        // Data start moved forward, e.g. by an XDP program
+        let desc = FrameDesc::new(frame_size + layout.xdp_headroom + layout.frame_headroom + 3);
+        assert_eq!(
+            umem_region.available_headroom(&desc),
+            layout.frame_headroom + 3
+        );
+
+        // Unaligned mode address with the offset in the upper bits
+        let base = frame_size as u64;
+        let offset = (layout.xdp_headroom + 5) as u64;
+        let desc = FrameDesc::new((base | (offset << XSK_UNALIGNED_BUF_OFFSET_SHIFT)) as usize);
+        assert_eq!(umem_region.available_headroom(&desc), 5);
+    }
+
+    #[test]
+    fn frame_index_ignores_offsets_within_frame() {
+        let layout = FrameLayout {
+            xdp_headroom: 4,
+            frame_headroom: 8,
+            mtu: 12,
+        };
+
+        let umem_region = UmemRegion::new(3.try_into().unwrap(), layout, false).unwrap();
+
+        let frame_size = layout.frame_size();
+
+        assert_eq!(umem_region.frame_count(), 3);
+        assert_eq!(umem_region.frame_index(0), 0);
+        assert_eq!(umem_region.frame_index(2 * frame_size + 7), 2);
+
+        let base = frame_size as u64;
+        let addr = base | (3 << XSK_UNALIGNED_BUF_OFFSET_SHIFT);
+        assert_eq!(umem_region.frame_index(addr as usize), 1);
+    }
+
+    #[test]
+    fn reset_desc_restores_initial_frame_state() {
+        let layout = FrameLayout {
+            xdp_headroom: 4,
+            frame_headroom: 8,
+            mtu: 12,
+        };
+
+        let umem_region = UmemRegion::new(2.try_into().unwrap(), layout, false).unwrap();
+
+        let initial = layout.frame_size() + layout.xdp_headroom + layout.frame_headroom;
+
+        let mut desc = FrameDesc::new(initial + 3);
+
+        unsafe { umem_region.data_mut(&mut desc) }
+            .cursor()
+            .write_all(b"hi")
+            .unwrap();
+
+        desc.options = 1;
+
+        umem_region.reset_desc(&mut desc);
+
+        assert_eq!(desc.addr, initial);
+        assert_eq!(desc.options, 0);
+        assert_eq!(desc.lengths.data, 0);
+    }
+
+    #[test]
+    fn frame_bytes_regions_follow_layout() {
+        let layout = FrameLayout {
+            xdp_headroom: 4,
+            frame_headroom: 8,
+            mtu: 12,
+        };
+
+        let mut umem_region = UmemRegion::new(2.try_into().unwrap(), layout, false).unwrap();
+
+        let frame_size = layout.frame_size();
+
+        let mut desc = FrameDesc::new(frame_size + layout.xdp_headroom + layout.frame_headroom);
+
+        unsafe { umem_region.data_mut(&mut desc) }
+            .cursor()
+            .write_all(b"hello")
+            .unwrap();
+
+        // Data start moved forward by 2 bytes, still the same frame
+        let shifted = FrameDesc::new(desc.addr + 2);
+
+        // Past the last frame
+        let beyond = FrameDesc::new(2 * frame_size + layout.xdp_headroom);
+
+        assert!(unsafe { umem_region.frame_bytes_mut(&beyond) }.is_none());
+
+        let mut bytes = unsafe { umem_region.frame_bytes_mut(&shifted) }.unwrap();
+
+        assert_eq!(bytes.as_slice().len(), frame_size);
+        assert_eq!(bytes.metadata().len(), layout.xdp_headroom);
+        assert_eq!(bytes.headroom().len(), layout.frame_headroom);
+        assert_eq!(bytes.data().len(), layout.mtu);
+        assert_eq!(&bytes.data()[..5], b"hello");
+
+        // Grow the packet into the headroom
+        bytes.headroom()[layout.frame_headroom - 1] = b'>';
+
+        assert_eq!(bytes.as_slice()[bytes.data_offset() - 1], b'>');
+    }
+
+    #[test]
+    fn validate_desc_identifies_each_kind_of_bad_descriptor() {
+        let layout = FrameLayout {
+            xdp_headroom: 4,
+            frame_headroom: 8,
+            mtu: 12,
+        };
+
+        let umem_region = UmemRegion::new(2.try_into().unwrap(), layout, false).unwrap();
+
+        let frame_size = layout.frame_size();
+        let data_offset = layout.xdp_headroom + layout.frame_headroom;
+
+        let desc = FrameDesc::new(frame_size + data_offset);
+        assert_eq!(umem_region.validate_desc(&desc), None);
+
+        let desc = FrameDesc::new(frame_size + data_offset + 1);
+        assert_eq!(
+            umem_region.validate_desc(&desc),
+            Some(InvalidDescReason::Misaligned {
+                offset: data_offset + 1
+            })
+        );
+
+        let desc = FrameDesc::new(2 * frame_size + data_offset);
+        assert_eq!(
+            umem_region.validate_desc(&desc),
+            Some(InvalidDescReason::OutOfRange)
+        );
+
+        let desc =
+            FrameDesc::new((data_offset as u64 | (1 << XSK_UNALIGNED_BUF_OFFSET_SHIFT)) as usize);
+        assert_eq!(
+            umem_region.validate_desc(&desc),
+            Some(InvalidDescReason::UnalignedAddress)
+        );
+
+        let mut desc = FrameDesc::new(data_offset);
+        desc.lengths.data = layout.mtu + 1;
+        assert_eq!(
+            umem_region.validate_desc(&desc),
+            Some(InvalidDescReason::LengthOutOfRange)
+        );
+    }
 }
@@ -4,27 +4,41 @@ mod mem;
 use mem::UmemRegion;
 
 pub mod frame;
-use frame::{Data, DataMut, FrameDesc, Headroom, HeadroomMut};
+use frame::{Data, DataMut, FrameBytes, FrameDesc, Headroom, HeadroomMut, XDP_PKT_CONTD};
 
 mod fill_queue;
 pub use fill_queue::FillQueue;
 
 mod comp_queue;
-pub use comp_queue::CompQueue;
+pub use comp_queue::{CompQueue, ReleaseGuard};
+
+mod completion;
+pub use completion::CompletionCallbacks;
+
+mod limits;
+
+mod pages;
+
+#[cfg(test)]
+pub(crate) mod loopback;
 
 use libxdp_sys::xsk_umem;
 use log::error;
 use std::{
     borrow::Borrow,
     error::Error,
-    fmt, io,
+    fmt,
+    io::{self, Write},
     num::NonZeroU32,
     ptr::{self, NonNull},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
 use crate::{
-    config::UmemConfig,
+    config::{FrameSize, UmemConfig, UmemFlags},
     ring::{XskRingCons, XskRingProd},
 };
 
@@ -53,6 +67,13 @@ impl XskUmem {
 
 impl Drop for XskUmem {
     fn drop(&mut self) {
+        // A mock UMEM was never registered, so there's nothing to
+        // delete.
+        #[cfg(test)]
+        if self.0 == NonNull::dangling() {
+            return;
+        }
+
         // SAFETY: unsafe constructor contract guarantees that the
         // UMEM has not been deleted already.
         let err = unsafe { libxdp_sys::xsk_umem__delete(self.0.as_ptr()) };
@@ -80,13 +101,19 @@ impl Drop for XskUmem {
 struct UmemInner {
     ptr: XskUmem,
     saved_fq_and_cq: Option<(Box<XskRingProd>, Box<XskRingCons>)>,
+    config: UmemConfig,
 }
 
 impl UmemInner {
-    fn new(ptr: XskUmem, saved_fq_and_cq: Option<(Box<XskRingProd>, Box<XskRingCons>)>) -> Self {
+    fn new(
+        ptr: XskUmem,
+        saved_fq_and_cq: Option<(Box<XskRingProd>, Box<XskRingCons>)>,
+        config: UmemConfig,
+    ) -> Self {
         Self {
             ptr,
             saved_fq_and_cq,
+            config,
         }
     }
 }
@@ -98,6 +125,9 @@ impl UmemInner {
 pub struct Umem {
     // `inner` must appear before `mem` to ensure correct drop order.
     inner: Arc<Mutex<UmemInner>>,
+    // Kept outside `inner` so it can be checked while a timed out
+    // socket creation still holds the lock.
+    poisoned: Arc<AtomicBool>,
     mem: UmemRegion,
 }
 
@@ -124,58 +154,7 @@ impl Umem {
             }
         })?;
 
-        let mut umem_ptr = ptr::null_mut();
-        let mut fq: Box<XskRingProd> = Box::default();
-        let mut cq: Box<XskRingCons> = Box::default();
-
-        let err = unsafe {
-            libxdp_sys::xsk_umem__create(
-                &mut umem_ptr,
-                mem.as_ptr(),
-                mem.len() as u64,
-                fq.as_mut().as_mut(), // double deref due to to Box
-                cq.as_mut().as_mut(),
-                &config.into(),
-            )
-        };
-
-        if err != 0 {
-            return Err(UmemCreateError {
-                reason: "non-zero error code returned when creating UMEM",
-                err: io::Error::from_raw_os_error(-err),
-            });
-        }
-
-        let umem_ptr = match NonNull::new(umem_ptr) {
-            Some(umem_ptr) => {
-                // SAFETY: this is the only `XskUmem` instance for
-                // this pointer, and no other pointers to the UMEM
-                // exist.
-                unsafe { XskUmem::new(umem_ptr) }
-            }
-            None => {
-                return Err(UmemCreateError {
-                    reason: "UMEM is null",
-                    err: io::Error::from_raw_os_error(-err),
-                });
-            }
-        };
-
-        if fq.is_ring_null() {
-            return Err(UmemCreateError {
-                reason: "fill queue ring is null",
-                err: io::Error::from_raw_os_error(-err),
-            });
-        };
-
-        if cq.is_ring_null() {
-            return Err(UmemCreateError {
-                reason: "comp queue ring is null",
-                err: io::Error::from_raw_os_error(-err),
-            });
-        }
-
-        let inner = UmemInner::new(umem_ptr, Some((fq, cq)));
+        let inner = Self::register(&mem, config)?;
 
         let frame_count = frame_count.get() as usize;
 
@@ -191,12 +170,44 @@ impl Umem {
 
         let umem = Umem {
             inner: Arc::new(Mutex::new(inner)),
+            poisoned: Arc::new(AtomicBool::new(false)),
             mem,
         };
 
         Ok((umem, frame_descs))
     }
 
+    /// A best-effort upper bound on the number of frames of
+    /// `frame_size` that the host could back if a `Umem` were created
+    /// now, for sizing a `Umem` up front rather than finding out via a
+    /// failed `mmap` or registration.
+    ///
+    /// The bound is the lower of the memory currently available (free
+    /// huge pages of the default size if `use_huge_pages` is set,
+    /// otherwise `MemAvailable` from `/proc/meminfo`) and the soft
+    /// `RLIMIT_MEMLOCK`, which registration is charged against unless
+    /// the process has `CAP_IPC_LOCK`. Memory already locked by other
+    /// `Umem`s or processes under the same user also counts against
+    /// that limit but isn't accounted for here, and the memory needed
+    /// for the rings isn't either, so leave some margin.
+    pub fn max_frames(frame_size: FrameSize, use_huge_pages: bool) -> io::Result<usize> {
+        limits::max_frames(frame_size.get() as usize, use_huge_pages)
+    }
+
+    /// The size of the huge pages actually backing this `Umem`'s
+    /// memory, or `None` if it's backed by base pages, as reported by
+    /// the kernel in `/proc/self/smaps`.
+    ///
+    /// This is how to check that `use_huge_pages` had the intended
+    /// effect, e.g. that the region really did end up on 1GB pages
+    /// rather than the default huge page size. `None` here despite
+    /// having asked for huge pages means they weren't used. Only
+    /// `hugetlbfs` pages count, transparent huge pages are reported as
+    /// base pages.
+    pub fn huge_page_size(&self) -> io::Result<Option<usize>> {
+        pages::huge_page_size(self.mem.as_ptr() as usize)
+    }
+
     /// The headroom and packet data segments of the `Umem` frame
     /// pointed at by `desc`. Contents are read-only.
     ///
@@ -308,6 +319,421 @@ impl Umem {
         unsafe { self.mem.data_mut(desc) }
     }
 
+    /// A mutable view of the whole of the `Umem` frame pointed at by
+    /// `desc`, spanning the XDP headroom, user headroom and packet
+    /// data regions. See [`FrameBytes`] for the layout.
+    ///
+    /// The frame is located by rounding `desc`'s address down to the
+    /// start of its chunk, so this works even if the data start has
+    /// been moved. Returns `None` if `desc` is an unaligned mode
+    /// address or doesn't point into one of this `Umem`'s frames.
+    /// Descriptor lengths are neither used nor updated.
+    ///
+    /// # Safety
+    ///
+    /// See [`frame_mut`](Self::frame_mut). In addition, the XDP
+    /// headroom region must not be accessed while the kernel may be
+    /// using it.
+    #[inline]
+    pub unsafe fn frame_bytes_mut(&mut self, desc: &FrameDesc) -> Option<FrameBytes<'_>> {
+        // SAFETY: see `frame_mut`.
+        unsafe { self.mem.frame_bytes_mut(desc) }
+    }
+
+    /// Copy the contents of the data segment of the `Umem` frame
+    /// pointed at by `desc` into a newly allocated [`Vec`].
+    ///
+    /// Once copied, the frame may be immediately recycled, e.g. by
+    /// returning it to the [`FillQueue`], while the copy is handed
+    /// off elsewhere, for example to another thread which may hold
+    /// onto it for longer. This allocates on every call so isn't
+    /// suited to the hot path, where reading the frame in place via
+    /// [`data`](Self::data) should be preferred.
+    ///
+    /// # Safety
+    ///
+    /// See [`data`](Self::data).
+    #[inline]
+    pub unsafe fn copy_out(&self, desc: &FrameDesc) -> Vec<u8> {
+        // SAFETY: see `data`.
+        unsafe { self.mem.data(desc) }.contents().to_vec()
+    }
+
+    /// Write `src` to the data segments of the frames pointed at by
+    /// `descs`, splitting it across as many as needed. Returns the
+    /// number of frames used, which will be written to sequentially
+    /// from the start of `descs`.
+    ///
+    /// Each frame holds up to [`mtu`] bytes. All but the last frame
+    /// used have the [`XDP_PKT_CONTD`] option set, and the last has it
+    /// cleared, so the frames may be submitted to the [`TxQueue`] in
+    /// order as a single multi-buffer packet. Any other option bits are
+    /// left untouched. An empty `src` still uses one (empty) frame.
+    ///
+    /// The socket must have been bound with multi-buffer support for
+    /// the kernel to accept packets spanning more than one frame.
+    ///
+    /// If `descs` is too short to hold all of `src` then nothing is
+    /// written and a [`NeedMoreFrames`] error is returned, detailing
+    /// how many additional frames are required.
+    ///
+    /// # Safety
+    ///
+    /// See [`frame_mut`](Self::frame_mut). This applies to each of the
+    /// frames used.
+    ///
+    /// [`mtu`]: crate::config::UmemConfig::mtu
+    /// [`TxQueue`]: crate::TxQueue
+    pub unsafe fn write_large_frame(
+        &self,
+        descs: &mut [FrameDesc],
+        src: &[u8],
+    ) -> Result<usize, NeedMoreFrames> {
+        let mtu = self.mem.mtu();
+
+        let required = if src.is_empty() {
+            1
+        } else {
+            src.len().div_ceil(mtu)
+        };
+
+        if descs.len() < required {
+            return Err(NeedMoreFrames {
+                additional: required - descs.len(),
+            });
+        }
+
+        let mut chunks = src.chunks(mtu);
+
+        for (i, desc) in descs.iter_mut().take(required).enumerate() {
+            let chunk = chunks.next().unwrap_or(&[]);
+
+            {
+                // SAFETY: see `frame_mut`.
+                let mut data = unsafe { self.mem.data_mut(desc) };
+                let mut cursor = data.cursor();
+
+                cursor.set_pos(0);
+                cursor
+                    .write_all(chunk)
+                    .expect("chunk is no larger than the frame's data segment");
+            }
+
+            if i + 1 < required {
+                desc.options |= XDP_PKT_CONTD;
+            } else {
+                desc.options &= !XDP_PKT_CONTD;
+            }
+        }
+
+        Ok(required)
+    }
+
+    /// Write the packets concatenated in `src` to the data segments of
+    /// the frames pointed at by `descs`, one packet per frame, where
+    /// `lengths` gives the length of each packet in turn. Returns the
+    /// number of frames written to, sequentially from the start of
+    /// `descs`.
+    ///
+    /// This is the bulk counterpart to writing each frame via
+    /// [`data_mut`](Self::data_mut), e.g. for replaying a capture
+    /// loaded into a single buffer. There must be a length for every
+    /// descriptor, otherwise nothing is written and a
+    /// [`WriteError::LengthMismatch`] error is returned. Writing stops
+    /// early if `src` runs out, so a packet whose length runs past the
+    /// end of `src` isn't written, nor is anything after it.
+    ///
+    /// Each packet must fit in a frame, i.e. be no longer than
+    /// [`mtu`]. If any of those that would be written don't, nothing is
+    /// written at all and a [`WriteError::PacketTooLarge`] error is
+    /// returned for the first of them.
+    ///
+    /// # Safety
+    ///
+    /// See [`frame_mut`](Self::frame_mut). This applies to each of the
+    /// frames used.
+    ///
+    /// [`mtu`]: crate::config::UmemConfig::mtu
+    pub unsafe fn write_frames_from(
+        &self,
+        descs: &mut [FrameDesc],
+        src: &[u8],
+        lengths: &[usize],
+    ) -> Result<usize, WriteError> {
+        if lengths.len() != descs.len() {
+            return Err(WriteError::LengthMismatch {
+                descs: descs.len(),
+                lengths: lengths.len(),
+            });
+        }
+
+        let mtu = self.mem.mtu();
+
+        let mut count = 0;
+        let mut end = 0;
+
+        for (index, &len) in lengths.iter().enumerate() {
+            if len > mtu {
+                return Err(WriteError::PacketTooLarge { index, len });
+            }
+
+            if src.len() - end < len {
+                break;
+            }
+
+            end += len;
+            count += 1;
+        }
+
+        let mut start = 0;
+
+        for (desc, &len) in descs.iter_mut().zip(lengths).take(count) {
+            // SAFETY: see `frame_mut`.
+            let mut data = unsafe { self.mem.data_mut(desc) };
+            let mut cursor = data.cursor();
+
+            cursor.set_pos(0);
+            cursor
+                .write_all(&src[start..start + len])
+                .expect("packet is no larger than the frame's data segment");
+
+            start += len;
+        }
+
+        Ok(count)
+    }
+
+    /// The number of bytes available in front of the current start of
+    /// the data segment of the frame pointed at by `desc`, i.e. the
+    /// distance between the end of the frame's XDP headroom and the
+    /// start of its data.
+    ///
+    /// Useful for checking whether a header will fit in front of the
+    /// packet before writing it. For a frame which hasn't had its data
+    /// start moved (e.g. by an XDP program adjusting the packet head)
+    /// this will equal [`frame_headroom`]. Any bytes already written to
+    /// the frame's [`headroom`](Self::headroom) segment occupy the end
+    /// of this space.
+    ///
+    /// Addresses carrying an unaligned mode offset in their upper bits
+    /// are accounted for.
+    ///
+    /// [`frame_headroom`]: crate::config::UmemConfig::frame_headroom
+    #[inline]
+    pub fn available_headroom(&self, desc: &FrameDesc) -> usize {
+        self.mem.available_headroom(desc)
+    }
+
+    /// The index of the frame `desc` belongs to, counting from the
+    /// start of the `Umem`, e.g. for looking up per frame state in a
+    /// table.
+    ///
+    /// Any address within a frame maps to that frame's index, and an
+    /// unaligned mode offset in the upper bits of the address is
+    /// ignored.
+    #[inline]
+    pub fn frame_index(&self, desc: &FrameDesc) -> usize {
+        self.mem.frame_index(desc.addr)
+    }
+
+    /// Check that each of `descs` points at the start of the packet
+    /// data segment of one of this `Umem`'s frames, and that its
+    /// segment lengths fit within the frame.
+    ///
+    /// A debugging aid for catching bookkeeping bugs, e.g. in custom
+    /// frame pool logic, after descriptors have been manipulated. On
+    /// failure every bad descriptor is reported, along with its index
+    /// in `descs` and why it was rejected. Note this assumes the
+    /// `Umem` is used in aligned mode, as is the case for all frames
+    /// produced by this crate, so descriptors whose data start has
+    /// been moved (e.g. by an XDP program adjusting the packet head)
+    /// are reported as misaligned.
+    pub fn validate_descs(&self, descs: &[FrameDesc]) -> Result<(), Vec<InvalidDesc>> {
+        let invalid: Vec<InvalidDesc> = descs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, desc)| {
+                self.mem
+                    .validate_desc(desc)
+                    .map(|reason| InvalidDesc { index, reason })
+            })
+            .collect();
+
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid)
+        }
+    }
+
+    /// Overwrite the entire `Umem` region with repetitions of
+    /// `pattern`, e.g. `0xDEADBEEF`.
+    ///
+    /// A debugging aid for tracking down uninitialised or stale
+    /// reads: if the pattern turns up in a received or captured
+    /// packet then a frame was sent before being written to. This
+    /// touches every byte of the region so treat it as a one-time
+    /// setup cost, best done straight after creation, and not as
+    /// something for the hot path. An empty `pattern` is a no-op.
+    ///
+    /// # Safety
+    ///
+    /// No frame of this `Umem` may be accessed anywhere else while
+    /// this runs, either in userspace or by the kernel. The simplest
+    /// way to ensure this is to call it before any frames have been
+    /// submitted to the [`FillQueue`] or [`TxQueue`].
+    ///
+    /// [`TxQueue`]: crate::TxQueue
+    #[inline]
+    pub unsafe fn fill_pattern(&self, pattern: &[u8]) {
+        // SAFETY: unsafe contract of this function guarantees there
+        // are no other accesses to the region.
+        unsafe { self.mem.fill_pattern(pattern) }
+    }
+
+    /// Touch the pages backing the frames described by `descs`, e.g.
+    /// just before a latency critical burst of writes to them, by
+    /// reading one byte from each page each frame overlaps.
+    ///
+    /// The region is mapped with `MAP_POPULATE` and pinned by the
+    /// kernel on registration, so its pages are always resident. What
+    /// this warms is everything in front of them, i.e. the TLB and,
+    /// for the bytes read, the cache, for only the frames about to be
+    /// used rather than the whole `Umem`. Only a read is made, so it
+    /// costs a cache line per page per frame.
+    ///
+    /// # Safety
+    ///
+    /// The frames must not be written to by anything else while this
+    /// runs, in particular the kernel, i.e. they shouldn't be on the
+    /// [`FillQueue`] or [`TxQueue`]. `descs` must belong to this
+    /// `Umem`.
+    ///
+    /// [`TxQueue`]: crate::TxQueue
+    pub unsafe fn prefault_descs(&self, descs: &[FrameDesc]) {
+        let page_size = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+            size if size > 0 => size as usize,
+            _ => 4096,
+        };
+
+        for desc in descs {
+            // SAFETY: see this function's unsafe contract.
+            unsafe { self.mem.prefault(desc.addr, page_size) };
+        }
+    }
+
+    /// Re-register the `Umem`'s existing memory region with the
+    /// kernel using `flags`, keeping the rest of its config as is.
+    ///
+    /// This saves tearing down and recreating the memory mapped region
+    /// itself, which may be large, but the `Umem` must be completely
+    /// quiescent: no [`Socket`](crate::Socket), [`FillQueue`],
+    /// [`CompQueue`] or clone of this `Umem` may still exist, since
+    /// these all refer to the current registration. If any do, an
+    /// error is returned and the current registration is left intact,
+    /// as it is if re-registering fails for any other reason.
+    ///
+    /// Any frame descriptors held remain valid afterwards, however the
+    /// fill and completion queues returned on creating the next socket
+    /// will be fresh, so any frames that were left in the old ones
+    /// must be considered free again.
+    pub fn reregister(&mut self, flags: UmemFlags) -> Result<(), UmemCreateError> {
+        if Arc::strong_count(&self.inner) != 1 {
+            return Err(UmemCreateError {
+                reason: "UMEM still in use by a socket, queue or clone",
+                err: io::Error::from_raw_os_error(libc::EBUSY),
+            });
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+
+        let mut config = inner.config;
+        config.set_flags(flags);
+
+        // Register anew before dropping the old registration so that
+        // it survives in the case of failure.
+        *inner = Self::register(&self.mem, config)?;
+
+        Ok(())
+    }
+
+    /// The config the `Umem` is currently registered with.
+    #[inline]
+    pub fn config(&self) -> UmemConfig {
+        self.inner.lock().unwrap().config
+    }
+
+    /// Register `mem` with the kernel as a UMEM, using `config`.
+    fn register(mem: &UmemRegion, config: UmemConfig) -> Result<UmemInner, UmemCreateError> {
+        let mut umem_ptr = ptr::null_mut();
+        let mut fq: Box<XskRingProd> = Box::default();
+        let mut cq: Box<XskRingCons> = Box::default();
+
+        let err = unsafe {
+            libxdp_sys::xsk_umem__create(
+                &mut umem_ptr,
+                mem.as_ptr(),
+                mem.len() as u64,
+                fq.as_mut().as_mut(), // double deref due to to Box
+                cq.as_mut().as_mut(),
+                &config.into(),
+            )
+        };
+
+        if err != 0 {
+            return Err(UmemCreateError {
+                reason: "non-zero error code returned when creating UMEM",
+                err: io::Error::from_raw_os_error(-err),
+            });
+        }
+
+        let umem_ptr = match NonNull::new(umem_ptr) {
+            Some(umem_ptr) => {
+                // SAFETY: this is the only `XskUmem` instance for
+                // this pointer, and no other pointers to the UMEM
+                // exist.
+                unsafe { XskUmem::new(umem_ptr) }
+            }
+            None => {
+                return Err(UmemCreateError {
+                    reason: "UMEM is null",
+                    err: io::Error::from_raw_os_error(-err),
+                });
+            }
+        };
+
+        if fq.is_ring_null() {
+            return Err(UmemCreateError {
+                reason: "fill queue ring is null",
+                err: io::Error::from_raw_os_error(-err),
+            });
+        };
+
+        if cq.is_ring_null() {
+            return Err(UmemCreateError {
+                reason: "comp queue ring is null",
+                err: io::Error::from_raw_os_error(-err),
+            });
+        }
+
+        Ok(UmemInner::new(umem_ptr, Some((fq, cq)), config))
+    }
+
+    /// Mark the `Umem` as unusable for creating any further sockets,
+    /// after a socket creation was given up on while still holding
+    /// its lock and possibly its saved fill and comp queues.
+    #[inline]
+    pub(crate) fn poison(&self) {
+        self.poisoned.store(true, Ordering::Release);
+    }
+
+    /// Whether [`poison`](Self::poison) has been called on this
+    /// `Umem` or any of its clones.
+    #[inline]
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Acquire)
+    }
+
     /// Intended to be called on socket creation, this passes the
     /// create function a pointer to the UMEM and any saved fill queue
     /// or completion queue.
@@ -326,6 +752,23 @@ impl Umem {
     }
 }
 
+#[cfg(test)]
+impl Umem {
+    /// A `Umem` over `mem` which was never registered with the kernel,
+    /// for driving queues against the mock kernel in [`loopback`].
+    pub(crate) fn mock(mem: UmemRegion, config: UmemConfig) -> Self {
+        // SAFETY: the pointer is never dereferenced, nor deleted on
+        // drop.
+        let ptr = unsafe { XskUmem::new(NonNull::dangling()) };
+
+        Self {
+            inner: Arc::new(Mutex::new(UmemInner::new(ptr, None, config))),
+            poisoned: Arc::new(AtomicBool::new(false)),
+            mem,
+        }
+    }
+}
+
 /// Error detailing why [`Umem`] creation failed.
 #[derive(Debug)]
 pub struct UmemCreateError {
@@ -345,6 +788,125 @@ impl Error for UmemCreateError {
     }
 }
 
+/// Error returned by [`Umem::write_large_frame`] when there aren't
+/// enough frames to hold the packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NeedMoreFrames {
+    additional: usize,
+}
+
+impl NeedMoreFrames {
+    /// The number of frames needed in addition to those provided.
+    #[inline]
+    pub fn additional(&self) -> usize {
+        self.additional
+    }
+}
+
+impl fmt::Display for NeedMoreFrames {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "packet requires {} more frame(s) than provided",
+            self.additional
+        )
+    }
+}
+
+impl Error for NeedMoreFrames {}
+
+/// Error returned by [`Umem::write_frames_from`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteError {
+    /// The number of lengths given doesn't match the number of
+    /// descriptors.
+    LengthMismatch {
+        /// The number of descriptors.
+        descs: usize,
+        /// The number of lengths.
+        lengths: usize,
+    },
+    /// A packet is too large to fit in a frame.
+    PacketTooLarge {
+        /// The index of the packet in the lengths given.
+        index: usize,
+        /// The length of the packet.
+        len: usize,
+    },
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::LengthMismatch { descs, lengths } => {
+                write!(f, "{} length(s) given for {} descriptor(s)", lengths, descs)
+            }
+            Self::PacketTooLarge { index, len } => write!(
+                f,
+                "packet {} of length {} is larger than a frame",
+                index, len
+            ),
+        }
+    }
+}
+
+impl Error for WriteError {}
+
+/// A descriptor rejected by [`Umem::validate_descs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidDesc {
+    index: usize,
+    reason: InvalidDescReason,
+}
+
+impl InvalidDesc {
+    /// The index of the descriptor in the slice that was validated.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Why the descriptor was rejected.
+    #[inline]
+    pub fn reason(&self) -> InvalidDescReason {
+        self.reason
+    }
+}
+
+/// The reason a descriptor failed validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidDescReason {
+    /// The address has an unaligned mode offset set in its upper
+    /// bits.
+    UnalignedAddress,
+    /// The address lies beyond the end of the [`Umem`].
+    OutOfRange,
+    /// The address doesn't point at the start of a frame's packet data
+    /// segment. `offset` is its distance from the start of the frame.
+    Misaligned {
+        /// Offset of the address from the start of its frame.
+        offset: usize,
+    },
+    /// The descriptor's headroom or data length is larger than the
+    /// corresponding frame segment.
+    LengthOutOfRange,
+}
+
+impl fmt::Display for InvalidDescReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnalignedAddress => write!(f, "address carries an unaligned mode offset"),
+            Self::OutOfRange => write!(f, "address lies outside the UMEM"),
+            Self::Misaligned { offset } => write!(
+                f,
+                "address is at offset {} within its frame rather than the start of packet data",
+                offset
+            ),
+            Self::LengthOutOfRange => write!(f, "segment length exceeds frame segment size"),
+        }
+    }
+}
+
 /// Dimensions of a [`Umem`] frame.
 #[derive(Debug, Clone, Copy)]
 struct FrameLayout {
@@ -0,0 +1,87 @@
+//! Finding out which size of page backs a UMEM's memory.
+
+use std::{fs, io};
+
+/// The size of the huge pages backing the mapping containing `addr`,
+/// or `None` if it's backed by base pages. See
+/// [`Umem::huge_page_size`](super::Umem::huge_page_size).
+pub(super) fn huge_page_size(addr: usize) -> io::Result<Option<usize>> {
+    let smaps = fs::read_to_string("/proc/self/smaps")?;
+
+    let page_size = kernel_page_size_kb(&smaps, addr)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "failed to find UMEM mapping in `/proc/self/smaps`",
+            )
+        })?
+        .saturating_mul(1024);
+
+    let base_page_size = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    };
+
+    if page_size > base_page_size {
+        Ok(Some(page_size))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The `KernelPageSize` in kB of the mapping in `smaps` which
+/// contains `addr`.
+fn kernel_page_size_kb(smaps: &str, addr: usize) -> Option<usize> {
+    let mut in_mapping = false;
+
+    for line in smaps.lines() {
+        let first = line.split_whitespace().next().unwrap_or_default();
+
+        if let Some((start, end)) = address_range(first) {
+            in_mapping = start <= addr && addr < end;
+        } else if in_mapping {
+            if let Some(rest) = line.strip_prefix("KernelPageSize:") {
+                return rest.split_whitespace().next()?.parse().ok();
+            }
+        }
+    }
+
+    None
+}
+
+/// Parses the `start-end` address range that begins each mapping's
+/// entry in `smaps`.
+fn address_range(s: &str) -> Option<(usize, usize)> {
+    let (start, end) = s.split_once('-')?;
+
+    Some((
+        usize::from_str_radix(start, 16).ok()?,
+        usize::from_str_radix(end, 16).ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMAPS: &str = "55d0c0a00000-55d0c0a21000 rw-p 00000000 00:00 0          [heap]
+Size:                132 kB
+KernelPageSize:        4 kB
+MMUPageSize:           4 kB
+VmFlags: rd wr mr mw me ac sd
+7f3a40000000-7f3a80000000 rw-s 00000000 00:0f 1234       /anon_hugepage (deleted)
+Size:            1048576 kB
+KernelPageSize:  1048576 kB
+MMUPageSize:     1048576 kB
+VmFlags: rd wr sh mr mw me ms de ht sd
+";
+
+    #[test]
+    fn page_size_is_read_from_containing_mapping() {
+        assert_eq!(kernel_page_size_kb(SMAPS, 0x55d0c0a00000), Some(4));
+        assert_eq!(kernel_page_size_kb(SMAPS, 0x7f3a40001000), Some(1048576));
+
+        // End addresses are exclusive
+        assert_eq!(kernel_page_size_kb(SMAPS, 0x55d0c0a21000), None);
+    }
+}
@@ -150,6 +150,80 @@ async fn addr_of_frames_consumed_match_addr_of_those_produced() {
     build_configs_and_run_test(test).await
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn consume_indices_yields_indices_of_frames_produced() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+
+        for desc in xsk1.descs[1..3].iter_mut() {
+            unsafe {
+                xsk1.umem
+                    .data_mut(desc)
+                    .cursor()
+                    .write_all(&ETHERNET_PACKET[..])
+                    .unwrap();
+            }
+        }
+
+        assert_eq!(
+            unsafe { xsk1.tx_q.produce_and_wakeup(&xsk1.descs[1..3]).unwrap() },
+            2
+        );
+
+        // Wait briefly so we don't try to consume too early
+        thread::sleep(Duration::from_millis(5));
+
+        let mut indices = [0; 4];
+
+        let cnt = unsafe { xsk1.cq.consume_indices(&mut indices, 4) };
+
+        assert_eq!(cnt, 2);
+
+        indices[..cnt].sort();
+
+        assert_eq!(indices[..cnt], [1, 2]);
+        assert_eq!(xsk1.umem.frame_index(&xsk1.descs[2]), 2);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn consume_into_bitmap_sets_bits_of_frames_completed() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+
+        for i in [1, 3] {
+            unsafe {
+                xsk1.umem
+                    .data_mut(&mut xsk1.descs[i])
+                    .cursor()
+                    .write_all(&ETHERNET_PACKET[..])
+                    .unwrap();
+            }
+        }
+
+        let sent = [xsk1.descs[1], xsk1.descs[3]];
+
+        assert_eq!(unsafe { xsk1.tx_q.produce_and_wakeup(&sent).unwrap() }, 2);
+
+        // Wait briefly so we don't try to consume too early
+        thread::sleep(Duration::from_millis(5));
+
+        let mut bitmap = [0; 1];
+
+        // Accumulates across calls
+        assert_eq!(unsafe { xsk1.cq.consume_into_bitmap(&mut bitmap, 1) }, 1);
+        assert_eq!(unsafe { xsk1.cq.consume_into_bitmap(&mut bitmap, 4) }, 1);
+
+        assert_eq!(bitmap[0], 0b1010);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 #[serial]
 async fn frame_consumed_with_consume_one_should_match_addr_of_one_produced() {
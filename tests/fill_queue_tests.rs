@@ -1,6 +1,6 @@
 #[allow(dead_code)]
 mod setup;
-use std::convert::TryInto;
+use std::{convert::TryInto, io::Write};
 
 use setup::{PacketGenerator, Xsk, XskConfig};
 
@@ -60,6 +60,28 @@ async fn produce_frames_until_full() {
     build_configs_and_run_test(test).await
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn produce_partial_submits_what_fits_and_leaves_overflow() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+
+        unsafe {
+            assert_eq!(xsk1.fq.produce_partial(&xsk1.descs[..3]), 3);
+
+            // Where `produce` would submit nothing
+            assert_eq!(xsk1.fq.produce(&xsk1.descs[3..6]), 0);
+            assert_eq!(xsk1.fq.produce_partial(&xsk1.descs[3..6]), 1);
+
+            // Exhausted, so the overflow `descs[4..6]` stays with us
+            assert_eq!(xsk1.fq.produce_partial(&xsk1.descs[4..6]), 0);
+            assert_eq!(xsk1.fq.produce_partial(&[]), 0);
+        }
+    }
+
+    build_configs_and_run_test(test).await
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 #[serial]
 async fn produce_one_is_ok() {
@@ -72,6 +94,82 @@ async fn produce_one_is_ok() {
     build_configs_and_run_test(test).await
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn recycle_reset_clears_lengths_and_takes_what_fits() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+
+        let initial: Vec<_> = xsk1.descs.iter().map(|desc| desc.addr()).collect();
+
+        for desc in xsk1.descs[..6].iter_mut() {
+            unsafe {
+                xsk1.umem
+                    .data_mut(desc)
+                    .cursor()
+                    .write_all(b"stale")
+                    .unwrap();
+            }
+
+            desc.set_options(1);
+        }
+
+        assert_eq!(
+            unsafe { xsk1.fq.recycle_reset(&mut xsk1.descs[..6]) },
+            FQ_SIZE as usize
+        );
+
+        // Including those that didn't fit, which are ready to retry
+        for (desc, addr) in xsk1.descs[..6].iter().zip(&initial) {
+            assert_eq!(desc.addr(), *addr);
+            assert_eq!(desc.lengths().data(), 0);
+            assert_eq!(desc.options(), 0);
+        }
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn prime_fills_ring_to_capacity() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+
+        assert_eq!(unsafe { xsk1.fq.produce(&xsk1.descs[..1]) }, 1);
+
+        // Only takes what fits rather than nothing at all
+        assert_eq!(
+            unsafe { xsk1.fq.prime(&xsk1.descs[1..], xsk1.rx_q.fd_mut()) }.unwrap(),
+            FQ_SIZE as usize - 1
+        );
+
+        assert_eq!(
+            unsafe { xsk1.fq.produce_one(&xsk1.descs[FQ_SIZE as usize]) },
+            0
+        );
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn wakeup_with_budget_sets_budget_and_wakes() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+
+        assert_eq!(unsafe { xsk1.fq.produce(&xsk1.descs[..4]) }, 4);
+
+        xsk1.fq.wakeup_with_budget(xsk1.rx_q.fd_mut(), 4).unwrap();
+
+        // Out of range budgets are rejected by the kernel
+        assert!(xsk1.rx_q.fd().set_busy_poll_budget(u32::MAX).is_err());
+    }
+
+    build_configs_and_run_test(test).await
+}
+
 async fn build_configs_and_run_test<F>(test: F)
 where
     F: Fn((Xsk, PacketGenerator), (Xsk, PacketGenerator)) + Send + 'static,
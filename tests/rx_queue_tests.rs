@@ -4,8 +4,18 @@ use setup::{PacketGenerator, Xsk, XskConfig, ETHERNET_PACKET};
 
 use libxdp_sys::XDP_PACKET_HEADROOM;
 use serial_test::serial;
-use std::{convert::TryInto, io::Write};
-use xsk_rs::config::{FrameSize, QueueSize, SocketConfig, UmemConfig, XDP_UMEM_MIN_CHUNK_SIZE};
+use std::{
+    convert::TryInto,
+    io::Write,
+    ops::ControlFlow,
+    thread,
+    time::{Duration, Instant},
+};
+use xsk_rs::{
+    config::{FrameSize, QueueSize, SocketConfig, UmemConfig, XDP_UMEM_MIN_CHUNK_SIZE},
+    socket::FairScheduler,
+    umem::frame::FrameDesc,
+};
 
 const CQ_SIZE: u32 = 4;
 const FQ_SIZE: u32 = 4;
@@ -95,6 +105,41 @@ async fn nothing_is_consumed_when_tx_sent_but_fill_q_empty() {
     build_configs_and_run_test(test).await
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn fill_queue_wakeup_blocks_only_when_asked_to() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let mut xsk2 = dev2.0;
+
+        unsafe {
+            assert_eq!(xsk2.fq.produce(&xsk2.descs[0..1]), 1);
+
+            // Nothing to receive yet, so the non-blocking wakeup returns
+            // without anything arriving
+            xsk2.fq.wakeup(xsk2.rx_q.fd_mut(), 0).unwrap();
+            assert_eq!(xsk2.rx_q.consume(&mut xsk2.descs[..1]), 0);
+        }
+
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            unsafe { setup::transmit_pkt(&mut xsk1, 0..1, &ETHERNET_PACKET) };
+        });
+
+        let start = Instant::now();
+
+        xsk2.fq.wakeup_blocking(xsk2.rx_q.fd_mut()).unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(unsafe { xsk2.rx_q.consume(&mut xsk2.descs[..1]) }, 1);
+        assert_eq!(xsk2.umem.data(&xsk2.descs[0]).contents(), ETHERNET_PACKET);
+
+        sender.join().unwrap();
+    }
+
+    build_configs_and_run_test(test).await
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 #[serial]
 async fn consumed_frame_data_matches_what_was_sent() {
@@ -418,6 +463,262 @@ async fn xdp_statistics_report_dropped_packet() {
     build_configs_and_run_test(test).await
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn fair_scheduler_limits_each_queue_to_budget_per_round() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let mut xsk2 = dev2.0;
+
+        unsafe {
+            assert_eq!(xsk1.fq.produce(&xsk1.descs[..4]), 4);
+            assert_eq!(xsk2.fq.produce(&xsk2.descs[..4]), 4);
+
+            // Four packets to dev2, one to dev1
+            setup::transmit_pkt(&mut xsk1, 4..8, &ETHERNET_PACKET);
+            setup::transmit_pkt(&mut xsk2, 4..5, &ETHERNET_PACKET);
+        }
+
+        let mut scheduler = FairScheduler::new(vec![xsk1.rx_q, xsk2.rx_q], 2.try_into().unwrap());
+
+        let mut descs = vec![FrameDesc::default(); 4];
+        let mut totals = [0; 2];
+
+        for _ in 0..2 {
+            let consumed = unsafe {
+                scheduler.run_round(&mut descs, |queue_idx, batch| {
+                    assert!(batch.len() <= 2);
+                    totals[queue_idx] += batch.len();
+                })
+            };
+
+            assert!(consumed.iter().all(|cnt| *cnt <= 2));
+        }
+
+        assert_eq!(totals, [1, 4]);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn recv_deadline_times_out_or_returns_received_frames() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let mut xsk2 = dev2.0;
+
+        unsafe {
+            // Nothing sent, so should wait out the deadline
+            let start = Instant::now();
+            let deadline = start + Duration::from_millis(50);
+
+            assert_eq!(
+                xsk2.rx_q.recv_deadline(&mut xsk2.descs, deadline).unwrap(),
+                0
+            );
+            assert!(Instant::now() >= deadline);
+
+            assert_eq!(xsk2.fq.produce(&xsk2.descs[0..1]), 1);
+
+            setup::transmit_pkt(&mut xsk1, 0..1, &ETHERNET_PACKET);
+
+            let deadline = Instant::now() + Duration::from_secs(1);
+
+            assert_eq!(
+                xsk2.rx_q.recv_deadline(&mut xsk2.descs, deadline).unwrap(),
+                1
+            );
+            assert_eq!(xsk2.umem.data(&xsk2.descs[0]).contents(), ETHERNET_PACKET);
+        }
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn consume_owned_is_capped_by_buffer_capacity() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let mut xsk2 = dev2.0;
+
+        let mut rx_q = xsk2.rx_q.with_consume_buffer(2);
+
+        unsafe {
+            assert!(rx_q.consume_owned(4).is_empty());
+
+            assert_eq!(xsk2.fq.produce(&xsk2.descs[..3]), 3);
+
+            setup::transmit_pkt(&mut xsk1, 0..3, &ETHERNET_PACKET);
+
+            let descs = rx_q.consume_owned(4);
+
+            assert_eq!(descs.len(), 2);
+            assert_eq!(xsk2.umem.data(&descs[0]).contents(), ETHERNET_PACKET);
+
+            assert_eq!(rx_q.consume_owned(4).len(), 1);
+        }
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn depth_tracks_frames_waiting_to_be_consumed() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let mut xsk2 = dev2.0;
+
+        assert_eq!(xsk2.rx_q.depth(), 0);
+
+        unsafe {
+            assert_eq!(xsk2.fq.produce(&xsk2.descs[..4]), 4);
+
+            setup::transmit_pkt(&mut xsk1, 0..3, &ETHERNET_PACKET);
+
+            assert_eq!(xsk2.rx_q.depth(), 3);
+
+            assert_eq!(xsk2.rx_q.consume(&mut xsk2.descs[..2]), 2);
+        }
+
+        assert_eq!(xsk2.rx_q.depth(), 1);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn consume_with_more_reports_frames_left_on_ring() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let mut xsk2 = dev2.0;
+
+        unsafe {
+            assert_eq!(xsk2.fq.produce(&xsk2.descs[..3]), 3);
+
+            setup::transmit_pkt(&mut xsk1, 0..3, &ETHERNET_PACKET);
+
+            assert_eq!(xsk2.rx_q.consume_with_more(&mut xsk2.descs[..2]), (2, true));
+            assert_eq!(
+                xsk2.rx_q.consume_with_more(&mut xsk2.descs[2..4]),
+                (1, false)
+            );
+        }
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn consume_classified_separates_oversized_descs() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let mut xsk2 = dev2.0;
+
+        let mut good = vec![FrameDesc::default(); 4];
+        let mut bad = Vec::new();
+
+        unsafe {
+            assert_eq!(xsk2.fq.produce(&xsk2.descs[..4]), 4);
+
+            setup::transmit_pkt(&mut xsk1, 0..2, &ETHERNET_PACKET);
+
+            assert_eq!(xsk2.rx_q.consume_classified(&mut good, &mut bad, 4), 2);
+            assert!(bad.is_empty());
+
+            xsk2.rx_q.set_max_desc_len(ETHERNET_PACKET.len() - 1);
+
+            setup::transmit_pkt(&mut xsk1, 2..4, &ETHERNET_PACKET);
+
+            assert_eq!(xsk2.rx_q.consume_classified(&mut good, &mut bad, 4), 0);
+            assert_eq!(bad.len(), 2);
+            assert_eq!(bad[0].lengths().data(), ETHERNET_PACKET.len());
+        }
+
+        assert_eq!(xsk2.rx_q.truncated_batches(), 0);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn parsed_iter_exposes_udp_headers() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let mut xsk2 = dev2.0;
+
+        let pkt = dev1.1.generate_packet(1234, 5678, 32).unwrap();
+
+        unsafe {
+            assert_eq!(xsk2.fq.produce(&xsk2.descs[..2]), 2);
+
+            setup::transmit_pkt(&mut xsk1, 0..2, &pkt);
+
+            {
+                // Only look at the first, leaving the second on the ring
+                let mut iter = xsk2.rx_q.parsed_iter(&xsk2.umem, 2);
+
+                let pkt = iter.next().unwrap();
+
+                assert_eq!(pkt.eth().unwrap().ethertype(), 0x0800);
+
+                let udp = pkt.udp().unwrap();
+
+                assert_eq!(udp.src_port(), 1234);
+                assert_eq!(udp.dst_port(), 5678);
+                assert_eq!(udp.payload().len(), 32);
+            }
+
+            let mut descs = [FrameDesc::default(); 2];
+
+            assert_eq!(xsk2.rx_q.consume(&mut descs), 1);
+            assert_eq!(xsk2.umem.data(&descs[0]).contents(), &pkt[..]);
+        }
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn run_calls_closure_per_frame_until_break() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let mut xsk2 = dev2.0;
+
+        unsafe {
+            assert_eq!(xsk2.fq.produce(&xsk2.descs[..4]), 4);
+
+            setup::transmit_pkt(&mut xsk1, 0..3, &ETHERNET_PACKET);
+
+            let mut received = 0;
+
+            xsk2.rx_q
+                .run(&mut xsk2.fq, |data| {
+                    assert_eq!(data, &ETHERNET_PACKET[..]);
+
+                    received += 1;
+
+                    if received == 3 {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                })
+                .unwrap();
+
+            assert_eq!(received, 3);
+        }
+    }
+
+    build_configs_and_run_test(test).await
+}
+
 async fn build_configs_and_run_test<F>(test: F)
 where
     F: Fn((Xsk, PacketGenerator), (Xsk, PacketGenerator)) + Send + 'static,
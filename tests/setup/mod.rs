@@ -4,7 +4,7 @@ pub use util::PacketGenerator;
 pub mod veth_setup;
 pub use veth_setup::{LinkIpAddr, VethDevConfig};
 
-use std::{net::Ipv4Addr, num::NonZeroU32};
+use std::{io::Write, net::Ipv4Addr, num::NonZeroU32, ops::Range, thread, time::Duration};
 use xsk_rs::{
     config::{Interface, SocketConfig, UmemConfig},
     socket::{RxQueue, Socket, TxQueue},
@@ -77,6 +77,29 @@ pub fn build_socket_and_umem(
     }
 }
 
+/// Write `pkt` into each of `sender`'s frames in `descs` and submit them to
+/// the TX ring, then give the receiving side a moment to pick them up.
+///
+/// # Safety
+///
+/// The frames in `descs` must not be in use by the kernel or any other
+/// queue.
+pub unsafe fn transmit_pkt(sender: &mut Xsk, descs: Range<usize>, pkt: &[u8]) {
+    for desc in sender.descs[descs.clone()].iter_mut() {
+        sender.umem.data_mut(desc).cursor().write_all(pkt).unwrap();
+    }
+
+    assert_eq!(
+        sender
+            .tx_q
+            .produce_and_wakeup(&sender.descs[descs.clone()])
+            .unwrap(),
+        descs.len()
+    );
+
+    thread::sleep(Duration::from_millis(50));
+}
+
 pub async fn run_test<F>(xsk1_config: XskConfig, xsk2_config: XskConfig, test: F)
 where
     F: Fn((Xsk, PacketGenerator), (Xsk, PacketGenerator)) + Send + 'static,
@@ -0,0 +1,1052 @@
+#[allow(dead_code)]
+mod setup;
+use setup::{veth_setup, PacketGenerator, VethDevConfig, Xsk, XskConfig};
+
+use serial_test::serial;
+use std::{
+    convert::TryInto,
+    error::Error,
+    fs,
+    io::{self, Read, Write},
+    os::unix::prelude::{AsRawFd, RawFd},
+    ptr, thread,
+    time::Duration,
+};
+use xsk_rs::{
+    config::{BindFlags, QueueSize, SocketConfig, UmemConfig, XdpAction},
+    socket::{self, BindMode, PollStatus, Reflector, Sender, XdpActFlags, XskStream, XsksMap},
+    umem::frame::FrameDesc,
+    Socket, Umem,
+};
+
+const FRAME_COUNT: u32 = 16;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn meminfo_reports_a_receive_buffer_limit() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let xsk1 = dev1.0;
+
+        let meminfo = xsk1.rx_q.fd().meminfo().unwrap();
+
+        assert!(meminfo.rcvbuf() > 0);
+        assert!(meminfo.sndbuf() > 0);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn mmap_offsets_place_producer_and_consumer_before_descs() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let xsk1 = dev1.0;
+
+        let offsets = xsk1.rx_q.fd().mmap_offsets().unwrap();
+
+        for ring in [offsets.rx(), offsets.tx(), offsets.fill(), offsets.comp()] {
+            assert_ne!(ring.producer(), ring.consumer());
+            assert!(ring.desc() > ring.producer());
+            assert!(ring.desc() > ring.consumer());
+        }
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn veth_does_not_support_tx_checksum_offload() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let xsk1 = dev1.0;
+
+        assert!(!xsk1.tx_q.socket().tx_checksum_supported().unwrap());
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn xsks_map_rejects_fd_which_is_not_an_xskmap() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let xsk1 = dev1.0;
+
+        assert!(XsksMap::new(xsk1.tx_q.fd().as_raw_fd()).is_err());
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn xsks_map_inserts_and_removes_sockets() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let xsk1 = dev1.0;
+
+        let map_fd = create_xskmap(4);
+
+        let mut map = XsksMap::new(map_fd).unwrap();
+
+        assert_eq!(map.max_entries(), 4);
+        assert_eq!(map.get(0), None);
+
+        map.insert(0, xsk1.tx_q.socket()).unwrap();
+
+        assert_eq!(map.get(0), Some(xsk1.tx_q.fd().as_raw_fd()));
+
+        // Out of range of the map
+        assert!(map.insert(4, xsk1.tx_q.socket()).is_err());
+        assert_eq!(map.get(4), None);
+
+        map.remove(0).unwrap();
+
+        assert_eq!(map.get(0), None);
+
+        // Nothing left to remove
+        assert!(map.remove(0).is_err());
+
+        unsafe { libc::close(map_fd) };
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn veth_reports_driver_xdp_counters() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let if_name = std::ffi::CString::new(dev1_config.if_name()).unwrap();
+        let if_index = unsafe { libc::if_nametoindex(if_name.as_ptr()) };
+
+        assert_ne!(if_index, 0);
+
+        let stats = socket::driver_xdp_stats(if_index).unwrap();
+
+        assert!(!stats.is_empty());
+        assert!(stats.keys().all(|name| name.contains("xdp")));
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn queue_stats_leaves_unreported_counters_empty() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let if_name = std::ffi::CString::new(dev1_config.if_name()).unwrap();
+        let if_index = unsafe { libc::if_nametoindex(if_name.as_ptr()) };
+
+        // Which counters veth reports for the queues it has varies
+        // between kernels, but it has no counters at all for a queue
+        // it doesn't have
+        socket::queue_stats(if_index, 0).unwrap();
+
+        let stats = socket::queue_stats(if_index, 1000).unwrap();
+
+        assert_eq!(stats.rx_packets(), None);
+        assert_eq!(stats.rx_bytes(), None);
+        assert_eq!(stats.tx_packets(), None);
+        assert_eq!(stats.tx_bytes(), None);
+
+        assert!(socket::queue_stats(u32::MAX, 0).is_err());
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn wait_for_link_sees_veth_link_up() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let if_name = std::ffi::CString::new(dev1_config.if_name()).unwrap();
+        let if_index = unsafe { libc::if_nametoindex(if_name.as_ptr()) };
+
+        assert!(socket::wait_for_link(if_index, Duration::from_secs(1)).unwrap());
+
+        // An interface which doesn't exist is never up
+        assert!(!socket::wait_for_link(u32::MAX, Duration::ZERO).unwrap());
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn wakeups_report_link_down_only_when_configured() {
+    let inner = move |dev1_config: VethDevConfig, dev2_config: VethDevConfig| {
+        let set_link = |state: &str| {
+            for dev in [&dev1_config, &dev2_config] {
+                let status = std::process::Command::new("ip")
+                    .args(["link", "set", "dev", dev.if_name(), state])
+                    .status()
+                    .unwrap();
+
+                assert!(status.success());
+            }
+        };
+
+        let (umem1, _descs1) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let (umem2, _descs2) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let (tx_q1, mut rx_q1, fq_and_cq1) = unsafe {
+            Socket::new(
+                SocketConfig::builder().report_link_down(true).build(),
+                &umem1,
+                &dev1_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        let (tx_q2, _rx_q2, _fq_and_cq2) = unsafe {
+            Socket::new(
+                SocketConfig::default(),
+                &umem2,
+                &dev2_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        let (fq1, _cq1) = fq_and_cq1.unwrap();
+
+        set_link("down");
+
+        assert!(socket::is_link_down(&tx_q1.wakeup().unwrap_err()));
+        assert!(socket::is_link_down(
+            &fq1.wakeup_with_budget(rx_q1.fd_mut(), 4).unwrap_err()
+        ));
+
+        // Ignored as before when not asked for
+        tx_q2.wakeup().unwrap();
+
+        set_link("up");
+
+        let if_name = std::ffi::CString::new(dev1_config.if_name()).unwrap();
+        let if_index = unsafe { libc::if_nametoindex(if_name.as_ptr()) };
+
+        assert!(socket::wait_for_link(if_index, Duration::from_secs(5)).unwrap());
+
+        tx_q1.wakeup().unwrap();
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn socket_created_within_timeout_is_usable() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let (umem, _descs) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let config = SocketConfig::builder()
+            .creation_timeout(Some(Duration::from_secs(10)))
+            .build();
+
+        let (tx_q, _rx_q, fq_and_cq) =
+            unsafe { Socket::new(config, &umem, &dev1_config.if_name().parse().unwrap(), 0) }
+                .unwrap();
+
+        assert!(fq_and_cq.is_some());
+
+        tx_q.wakeup().unwrap();
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn timed_out_socket_creation_stops_umem_being_used_again() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let (umem, _descs) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let if_name = dev1_config.if_name().parse().unwrap();
+
+        // Nothing can be created in no time at all
+        let config = SocketConfig::builder()
+            .creation_timeout(Some(Duration::ZERO))
+            .build();
+
+        let err = unsafe { Socket::new(config, &umem, &if_name, 0) }.unwrap_err();
+
+        let source = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+
+        assert_eq!(source.kind(), io::ErrorKind::TimedOut);
+
+        // Fails straight away rather than waiting on the creation
+        // thread, even without a timeout
+        assert!(unsafe { Socket::new(SocketConfig::default(), &umem, &if_name, 0) }.is_err());
+        assert!(
+            unsafe { Socket::new(SocketConfig::default(), &umem.clone(), &if_name, 0) }.is_err()
+        );
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn nic_ring_params_round_trip_where_supported() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let if_name = std::ffi::CString::new(dev1_config.if_name()).unwrap();
+        let if_index = unsafe { libc::if_nametoindex(if_name.as_ptr()) };
+
+        // Whether veth exposes its rings depends on the kernel
+        let params = match socket::nic_ring_params(if_index) {
+            Ok(params) => params,
+            Err(err) => {
+                assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+                return;
+            }
+        };
+
+        assert!(params.rx_pending() <= params.rx_max_pending());
+        assert!(params.tx_pending() <= params.tx_max_pending());
+
+        match socket::set_nic_ring_params(if_index, params.rx_pending(), params.tx_pending()) {
+            Ok(()) => assert_eq!(socket::nic_ring_params(if_index).unwrap(), params),
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::Unsupported),
+        }
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn veth_reports_basic_xdp_support() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let if_name = std::ffi::CString::new(dev1_config.if_name()).unwrap();
+        let if_index = unsafe { libc::if_nametoindex(if_name.as_ptr()) };
+
+        // Older kernels don't report the features at all
+        if let Some(flags) = socket::xdp_act_flags(if_index).unwrap() {
+            assert!(flags.contains(XdpActFlags::NETDEV_XDP_ACT_BASIC));
+
+            assert!(socket::xdp_act_flags(u32::MAX).is_err());
+        }
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn enomem_is_not_retried_for_the_first_socket_of_a_umem() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        // A tx ring this large is a 32 GiB allocation, which the kernel
+        // refuses outright on a host with less memory than that. The rx
+        // ring is set up first, at the default size, so succeeds.
+        let tx_queue_size = QueueSize::new(1 << 31).unwrap();
+
+        if mem_total_bytes() >= tx_queue_size.get() as u64 * 16 {
+            return;
+        }
+
+        let (umem, _descs) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let config = SocketConfig::builder()
+            .tx_queue_size(tx_queue_size)
+            .enomem_min_queue_size(Some(QueueSize::new(64).unwrap()))
+            .build();
+
+        let err = unsafe { Socket::new(config, &umem, &dev1_config.if_name().parse().unwrap(), 0) }
+            .unwrap_err();
+
+        let err = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+
+        assert_eq!(err.raw_os_error(), Some(libc::ENOMEM));
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn detect_xdp_conflict_finds_program_attached_by_socket() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let if_name = std::ffi::CString::new(dev1_config.if_name()).unwrap();
+        let if_index = unsafe { libc::if_nametoindex(if_name.as_ptr()) };
+
+        assert_eq!(Socket::detect_xdp_conflict(if_index).unwrap(), None);
+
+        let (umem, _descs) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let _sock = unsafe {
+            Socket::new(
+                SocketConfig::default(),
+                &umem,
+                &dev1_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        assert!(Socket::detect_xdp_conflict(if_index).unwrap().is_some());
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn bind_to_device_accepts_bound_interface() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let (umem, _descs) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let (tx_q, _rx_q, _fq_and_cq) = unsafe {
+            Socket::new(
+                SocketConfig::default(),
+                &umem,
+                &dev1_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        tx_q.socket().bind_to_device(dev1_config.if_name()).unwrap();
+
+        let err = tx_q
+            .socket()
+            .bind_to_device("an_interface_name_which_is_too_long")
+            .unwrap_err();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn promiscuous_mode_is_restored_when_socket_dropped() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let is_promisc = || {
+            let flags =
+                std::fs::read_to_string(format!("/sys/class/net/{}/flags", dev1_config.if_name()))
+                    .unwrap();
+
+            u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16).unwrap()
+                & libc::IFF_PROMISC as u32
+                != 0
+        };
+
+        let (umem, _descs) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let (tx_q, rx_q, fq_and_cq) = unsafe {
+            Socket::new(
+                SocketConfig::default(),
+                &umem,
+                &dev1_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        assert!(!is_promisc());
+
+        tx_q.socket().set_promiscuous(true).unwrap();
+
+        assert!(is_promisc());
+
+        drop((tx_q, rx_q, fq_and_cq));
+
+        assert!(!is_promisc());
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn non_pass_default_action_requires_own_program() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let (umem, _descs) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let res = unsafe {
+            Socket::new(
+                SocketConfig::builder()
+                    .default_action(XdpAction::Drop)
+                    .build(),
+                &umem,
+                &dev1_config.if_name().parse().unwrap(),
+                0,
+            )
+        };
+
+        assert!(res.is_err());
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn zerocopy_bind_on_veth_fails_without_fallback() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let (umem, _descs) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let res = unsafe {
+            Socket::new(
+                SocketConfig::builder()
+                    .bind_flags(BindFlags::XDP_ZEROCOPY)
+                    .build(),
+                &umem,
+                &dev1_config.if_name().parse().unwrap(),
+                0,
+            )
+        };
+
+        assert!(res.is_err());
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn zerocopy_bind_on_veth_falls_back_to_copy_mode_if_enabled() {
+    let inner = move |dev1_config: VethDevConfig, _dev2_config: VethDevConfig| {
+        let (umem, _descs) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let (tx_q, rx_q, fq_and_cq) = unsafe {
+            Socket::new(
+                SocketConfig::builder()
+                    .bind_flags(BindFlags::XDP_ZEROCOPY)
+                    .zerocopy_fallback(true)
+                    .build(),
+                &umem,
+                &dev1_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        assert!(fq_and_cq.is_some());
+        assert_eq!(tx_q.socket().effective_bind_mode(), BindMode::Copy);
+        assert_eq!(rx_q.socket().effective_bind_mode(), BindMode::Copy);
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn sharing_socket_stays_shared_after_falling_back_to_copy_mode() {
+    let inner = move |dev1_config: VethDevConfig, dev2_config: VethDevConfig| {
+        let (umem, _descs) = Umem::new(
+            UmemConfig::default(),
+            FRAME_COUNT.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let (first_tx_q, _first_rx_q, first_fq_and_cq) = unsafe {
+            Socket::new(
+                SocketConfig::default(),
+                &umem,
+                &dev1_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        assert!(first_fq_and_cq.is_some());
+
+        let (tx_q, _rx_q, _fq_and_cq) = unsafe {
+            Socket::new(
+                SocketConfig::builder()
+                    .bind_flags(BindFlags::XDP_ZEROCOPY)
+                    .zerocopy_fallback(true)
+                    .build(),
+                &umem,
+                &dev2_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        assert_eq!(tx_q.socket().effective_bind_mode(), BindMode::Copy);
+
+        // Still bound with `XDP_SHARED_UMEM` after the retry
+        assert!(tx_q.socket().wakeup_address_required());
+        assert!(!first_tx_q.socket().wakeup_address_required());
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn copy_mode_bind_reports_copy_mode() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let xsk1 = dev1.0;
+
+        assert_eq!(xsk1.tx_q.socket().effective_bind_mode(), BindMode::Copy);
+    }
+
+    setup::run_test(
+        XskConfig {
+            frame_count: FRAME_COUNT.try_into().unwrap(),
+            umem_config: UmemConfig::default(),
+            socket_config: SocketConfig::builder()
+                .bind_flags(BindFlags::XDP_COPY)
+                .build(),
+        },
+        XskConfig {
+            frame_count: FRAME_COUNT.try_into().unwrap(),
+            umem_config: UmemConfig::default(),
+            socket_config: SocketConfig::default(),
+        },
+        test,
+    )
+    .await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn ring_snapshot_reflects_submitted_frames() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+
+        let before = xsk1.rx_q.socket().ring_snapshot();
+
+        assert_eq!(before.tx().pending(), 0);
+        assert_eq!(before.fill().unwrap().producer(), 0);
+
+        assert_eq!(unsafe { xsk1.fq.produce(&xsk1.descs[..4]) }, 4);
+
+        let after = xsk1.tx_q.socket().ring_snapshot();
+
+        let fill = after.fill().unwrap();
+
+        assert_eq!(fill.producer(), 4);
+        assert!(fill.pending() <= 4);
+        assert!(after.comp().is_some());
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn stats_delta_only_counts_since_previous_call() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let xsk2 = dev2.0;
+
+        let pkt = dev1.1.generate_packet(1234, 5678, 32).unwrap();
+
+        assert_eq!(xsk2.rx_q.socket().stats_delta().unwrap().rx_dropped(), 0);
+
+        // Nothing on the fill ring, so the packet can't be received
+        unsafe { setup::transmit_pkt(&mut xsk1, 0..1, &pkt) };
+
+        let delta = xsk2.rx_q.socket().stats_delta().unwrap();
+
+        assert!(delta.rx_dropped() > 0);
+
+        let delta = xsk2.rx_q.socket().stats_delta().unwrap();
+
+        assert_eq!(delta.rx_dropped(), 0);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn stream_reads_a_packet_per_write() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let xsk1 = dev1.0;
+        let xsk2 = dev2.0;
+
+        let pkt = dev1.1.generate_packet(1234, 5678, 32).unwrap();
+
+        let mut tx_stream =
+            unsafe { XskStream::new(xsk1.rx_q, xsk1.tx_q, xsk1.fq, xsk1.cq, xsk1.descs) };
+
+        let mut rx_stream =
+            unsafe { XskStream::new(xsk2.rx_q, xsk2.tx_q, xsk2.fq, xsk2.cq, xsk2.descs) };
+
+        assert_eq!(
+            tx_stream.write(&[0; 8192]).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+
+        let sent = pkt.clone();
+
+        // Give the reader time to fill its ring before sending
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            tx_stream.write_all(&sent).unwrap();
+            tx_stream.flush().unwrap();
+        });
+
+        let mut buf = [0; 2048];
+
+        let len = rx_stream.read(&mut buf).unwrap();
+
+        assert_eq!(&buf[..len], &pkt[..]);
+
+        writer.join().unwrap();
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn sender_recycles_completed_frames() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let xsk1 = dev1.0;
+        let mut xsk2 = dev2.0;
+
+        let pkt = dev1.1.generate_packet(1234, 5678, 32).unwrap();
+
+        // Nothing in the pool and nothing in flight to reclaim
+        let mut sender = unsafe { Sender::new(xsk1.tx_q, xsk1.cq, Vec::new()) };
+
+        sender.set_nonblocking(true);
+
+        assert_eq!(
+            sender.send(&pkt).unwrap_err().kind(),
+            std::io::ErrorKind::WouldBlock
+        );
+
+        let (tx_q, cq, _) = sender.into_inner();
+
+        // A single frame, so every send after the first has to wait
+        // for the previous one to complete
+        let mut sender = unsafe { Sender::new(tx_q, cq, xsk1.descs[..1].to_vec()) };
+
+        assert_eq!(
+            sender.send(&[0; 8192]).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        assert_eq!(sender.free_frames(), 1);
+
+        unsafe {
+            assert_eq!(xsk2.fq.produce(&xsk2.descs[..4]), 4);
+        }
+
+        for _ in 0..3 {
+            sender.send(&pkt).unwrap();
+        }
+
+        let mut descs = [FrameDesc::default(); 4];
+        let mut received = 0;
+
+        let start = std::time::Instant::now();
+
+        while received < 3 && start.elapsed() < Duration::from_secs(1) {
+            let cnt = unsafe { xsk2.rx_q.poll_and_consume(&mut descs[received..], 100) }.unwrap();
+
+            for desc in descs[received..received + cnt].iter() {
+                assert_eq!(unsafe { xsk2.umem.data(desc) }.contents(), &pkt[..]);
+            }
+
+            received += cnt;
+        }
+
+        assert_eq!(received, 3);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn stream_needs_frames_to_both_send_and_receive() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let xsk1 = dev1.0;
+
+        let descs = xsk1.descs[..1].to_vec();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            XskStream::new(xsk1.rx_q, xsk1.tx_q, xsk1.fq, xsk1.cq, descs)
+        }));
+
+        assert!(result.is_err());
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn reflector_sends_rewritten_frames_back() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let xsk2 = dev2.0;
+
+        let pkt = dev1.1.generate_packet(1234, 5678, 32).unwrap();
+
+        let mut reflector = unsafe {
+            Reflector::new(
+                xsk2.rx_q,
+                xsk2.tx_q,
+                xsk2.fq,
+                xsk2.cq,
+                xsk2.descs,
+                4.try_into().unwrap(),
+            )
+        };
+
+        // First pass just primes the fill ring
+        assert_eq!(reflector.process(|_| true).unwrap(), 0);
+        assert_eq!(reflector.pending_fill(), 0);
+
+        unsafe {
+            assert_eq!(xsk1.fq.produce(&xsk1.descs[..4]), 4);
+
+            xsk1.umem
+                .data_mut(&mut xsk1.descs[4])
+                .cursor()
+                .write_all(&pkt[..])
+                .unwrap();
+
+            assert_eq!(xsk1.tx_q.produce_and_wakeup(&xsk1.descs[4..5]).unwrap(), 1);
+        }
+
+        assert!(reflector.poll(100).unwrap());
+
+        let reflected = reflector
+            .process(|data| {
+                let (dst, src) = data.split_at_mut(6);
+                dst.swap_with_slice(&mut src[..6]);
+                true
+            })
+            .unwrap();
+
+        assert_eq!(reflected, 1);
+        assert_eq!(reflector.in_flight(), 1);
+
+        let mut descs = [FrameDesc::default(); 1];
+
+        assert_eq!(
+            unsafe { xsk1.rx_q.poll_and_consume(&mut descs, 100) }.unwrap(),
+            1
+        );
+
+        let received = unsafe { xsk1.umem.data(&descs[0]) };
+
+        assert_eq!(&received[..6], &pkt[6..12]);
+        assert_eq!(&received[6..12], &pkt[..6]);
+        assert_eq!(&received[12..], &pkt[12..]);
+
+        // The sent frame is only recycled once its completion arrives
+        let start = std::time::Instant::now();
+
+        while reflector.in_flight() > 0 && start.elapsed() < Duration::from_secs(1) {
+            reflector.process(|_| true).unwrap();
+        }
+
+        assert_eq!(reflector.in_flight(), 0);
+        assert_eq!(reflector.pending_fill(), 0);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn poll_adaptive_reports_phase_socket_became_ready_in() {
+    fn test(dev1: (Xsk, PacketGenerator), dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let mut xsk2 = dev2.0;
+
+        assert_eq!(
+            xsk2.rx_q.fd_mut().poll_adaptive(10, 10).unwrap(),
+            PollStatus::TimedOut
+        );
+
+        let pkt = dev1.1.generate_packet(1234, 5678, 32).unwrap();
+
+        unsafe {
+            assert_eq!(xsk2.fq.produce(&xsk2.descs[..1]), 1);
+
+            setup::transmit_pkt(&mut xsk1, 0..1, &pkt);
+        }
+
+        // Already readable, so the first spin should see it
+        assert_eq!(
+            xsk2.rx_q.fd_mut().poll_adaptive(1, 100).unwrap(),
+            PollStatus::ReadyWhileSpinning
+        );
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+/// Create a `BPF_MAP_TYPE_XSKMAP` with room for `max_entries` queues.
+fn create_xskmap(max_entries: u32) -> RawFd {
+    let fd = unsafe {
+        libxdp_sys::bpf_map_create(
+            libxdp_sys::bpf_map_type_BPF_MAP_TYPE_XSKMAP,
+            ptr::null(),
+            4,
+            4,
+            max_entries,
+            ptr::null(),
+        )
+    };
+
+    assert!(fd >= 0, "{}", io::Error::from_raw_os_error(-fd));
+
+    fd
+}
+
+fn mem_total_bytes() -> u64 {
+    let meminfo = fs::read_to_string("/proc/meminfo").unwrap();
+
+    let kb: u64 = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|rest| rest.trim().trim_end_matches("kB").trim().parse().ok())
+        .unwrap();
+
+    kb * 1024
+}
+
+async fn build_configs_and_run_test<F>(test: F)
+where
+    F: Fn((Xsk, PacketGenerator), (Xsk, PacketGenerator)) + Send + 'static,
+{
+    setup::run_test(
+        XskConfig {
+            frame_count: FRAME_COUNT.try_into().unwrap(),
+            umem_config: UmemConfig::default(),
+            socket_config: SocketConfig::default(),
+        },
+        XskConfig {
+            frame_count: FRAME_COUNT.try_into().unwrap(),
+            umem_config: UmemConfig::default(),
+            socket_config: SocketConfig::default(),
+        },
+        test,
+    )
+    .await;
+}
@@ -1,11 +1,14 @@
 #[allow(dead_code)]
 mod setup;
-use std::convert::TryInto;
+use std::{convert::TryInto, io::Write, mem, sync::Arc, thread, time::Duration};
 
-use setup::Xsk;
+use setup::{Xsk, ETHERNET_PACKET};
 
 use serial_test::serial;
-use xsk_rs::config::{QueueSize, SocketConfig, UmemConfig};
+use xsk_rs::{
+    config::{QueueSize, SocketConfig, UmemConfig},
+    socket::{InFlightLimiter, SyncTxQueue},
+};
 
 use crate::setup::{PacketGenerator, XskConfig};
 
@@ -34,6 +37,39 @@ async fn producing_tx_size_frames_is_ok() {
     build_configs_and_run_test(test).await
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn flush_sends_frames_staged_across_produce_calls() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+
+        // Nothing staged yet
+        xsk1.tx_q.flush().unwrap();
+
+        unsafe {
+            for desc in xsk1.descs[..2].iter_mut() {
+                xsk1.umem
+                    .data_mut(desc)
+                    .cursor()
+                    .write_all(&ETHERNET_PACKET[..])
+                    .unwrap();
+            }
+
+            assert_eq!(xsk1.tx_q.produce(&xsk1.descs[..1]), 1);
+            assert_eq!(xsk1.tx_q.produce(&xsk1.descs[1..2]), 1);
+        }
+
+        xsk1.tx_q.flush().unwrap();
+
+        // Wait briefly so we don't try to consume too early
+        thread::sleep(Duration::from_millis(5));
+
+        assert_eq!(unsafe { xsk1.cq.consume(&mut xsk1.descs[2..]) }, 2);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 #[serial]
 async fn produce_greater_than_tx_size_frames_fails() {
@@ -75,6 +111,135 @@ async fn produce_one_is_ok() {
     build_configs_and_run_test(test).await
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn produce_limited_submits_no_more_than_limit() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+        let mut limiter = InFlightLimiter::new(3.try_into().unwrap());
+
+        unsafe {
+            assert_eq!(xsk1.tx_q.produce_limited(&xsk1.descs[..2], &mut limiter), 2);
+            assert_eq!(
+                xsk1.tx_q.produce_limited(&xsk1.descs[2..4], &mut limiter),
+                1
+            );
+            assert_eq!(
+                xsk1.tx_q.produce_limited(&xsk1.descs[3..4], &mut limiter),
+                0
+            );
+        }
+
+        assert_eq!(limiter.in_flight(), 3);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn aborted_transaction_leaves_ring_untouched() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+
+        {
+            let mut tx = xsk1.tx_q.begin(8);
+
+            assert_eq!(tx.capacity(), 4);
+
+            unsafe {
+                assert!(tx.push(&xsk1.descs[0]));
+                assert!(tx.push(&xsk1.descs[1]));
+            }
+        }
+
+        // Nothing was committed so the whole ring is still free
+        assert_eq!(unsafe { xsk1.tx_q.produce(&xsk1.descs[..4]) }, 4);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn leaked_transaction_leaves_ring_untouched() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+
+        let mut tx = xsk1.tx_q.begin(4);
+
+        unsafe {
+            assert!(tx.push(&xsk1.descs[0]));
+        }
+
+        mem::forget(tx);
+
+        // Nothing was reserved so the whole ring is still free
+        assert_eq!(unsafe { xsk1.tx_q.produce(&xsk1.descs[..4]) }, 4);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn committed_transaction_submits_only_requested_slots() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let mut xsk1 = dev1.0;
+
+        let mut tx = xsk1.tx_q.begin(3);
+
+        unsafe {
+            for desc in xsk1.descs[..3].iter() {
+                assert!(tx.push(desc));
+            }
+
+            assert!(!tx.push(&xsk1.descs[3]));
+        }
+
+        assert_eq!(tx.len(), 3);
+        assert_eq!(tx.commit(2), 2);
+
+        // Two slots taken, so two left
+        assert_eq!(unsafe { xsk1.tx_q.produce(&xsk1.descs[2..5]) }, 0);
+        assert_eq!(unsafe { xsk1.tx_q.produce(&xsk1.descs[2..4]) }, 2);
+    }
+
+    build_configs_and_run_test(test).await
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn sync_tx_queue_serialises_produce_across_threads() {
+    fn test(dev1: (Xsk, PacketGenerator), _dev2: (Xsk, PacketGenerator)) {
+        let xsk1 = dev1.0;
+
+        let tx_q = Arc::new(SyncTxQueue::new(xsk1.tx_q));
+
+        let handles = xsk1.descs[..4]
+            .chunks(2)
+            .map(|descs| {
+                let tx_q = Arc::clone(&tx_q);
+                let descs = descs.to_vec();
+
+                thread::spawn(move || unsafe { tx_q.produce(&descs) })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), 2);
+        }
+
+        unsafe {
+            assert_eq!(tx_q.produce(&xsk1.descs[4..5]), 0);
+        }
+
+        tx_q.wakeup().unwrap();
+    }
+
+    build_configs_and_run_test(test).await
+}
+
 async fn build_configs_and_run_test<F>(test: F)
 where
     F: Fn((Xsk, PacketGenerator), (Xsk, PacketGenerator)) + Send + 'static,
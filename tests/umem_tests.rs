@@ -3,9 +3,13 @@ mod setup;
 use setup::{veth_setup, VethDevConfig, Xsk, ETHERNET_PACKET};
 
 use serial_test::serial;
-use std::{convert::TryInto, io::Write};
+use std::{convert::TryInto, io::Write, os::unix::prelude::AsRawFd, thread, time::Duration};
 use xsk_rs::{
-    config::{LibxdpFlags, SocketConfig, UmemConfig},
+    config::{
+        FrameSize, LibxdpFlags, SocketConfig, UmemConfig, UmemFlags, XDP_UMEM_MIN_CHUNK_SIZE,
+    },
+    socket,
+    umem::{frame::XDP_PKT_CONTD, WriteError},
     Socket, Umem,
 };
 
@@ -77,6 +81,81 @@ async fn shared_umem_returns_new_fq_and_cq_when_sockets_are_bound_to_different_d
         .unwrap();
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn sharing_socket_wakes_up_with_its_bound_address_when_configured() {
+    let inner = move |dev1_config: VethDevConfig, dev2_config: VethDevConfig| {
+        let frame_count = 64;
+
+        let (umem, descs) = Umem::new(
+            UmemConfig::default(),
+            frame_count.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let mut sender_descs = descs;
+        let receiver_descs = sender_descs.drain((frame_count / 2) as usize..).collect();
+
+        let (sender_tx_q, sender_rx_q, sender_fq_and_cq) = unsafe {
+            Socket::new(
+                SocketConfig::default(),
+                &umem,
+                &dev1_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        let (sender_fq, sender_cq) = sender_fq_and_cq.unwrap();
+
+        let mut sender = Xsk {
+            umem: umem.clone(),
+            fq: sender_fq,
+            cq: sender_cq,
+            tx_q: sender_tx_q,
+            rx_q: sender_rx_q,
+            descs: sender_descs,
+        };
+
+        let (receiver_tx_q, receiver_rx_q, receiver_fq_and_cq) = unsafe {
+            Socket::new(
+                SocketConfig::builder().wakeup_with_address(true).build(),
+                &umem,
+                &dev2_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        let (receiver_fq, receiver_cq) = receiver_fq_and_cq.unwrap();
+
+        // Only the socket bound with `XDP_SHARED_UMEM` needs an address
+        assert!(!sender.tx_q.socket().wakeup_address_required());
+        assert!(receiver_tx_q.socket().wakeup_address_required());
+
+        let mut receiver = Xsk {
+            umem,
+            fq: receiver_fq,
+            cq: receiver_cq,
+            tx_q: receiver_tx_q,
+            rx_q: receiver_rx_q,
+            descs: receiver_descs,
+        };
+
+        send_and_receive_pkt(&mut sender, &mut receiver, &ETHERNET_PACKET[..]);
+
+        // Sharing socket wakes its tx queue with its bound address
+        send_and_receive_pkt(&mut receiver, &mut sender, &ETHERNET_PACKET[..]);
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 #[serial]
 async fn shared_umem_does_not_return_new_fq_and_cq_when_sockets_are_bound_to_same_device() {
@@ -120,6 +199,191 @@ async fn shared_umem_does_not_return_new_fq_and_cq_when_sockets_are_bound_to_sam
         .unwrap();
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn every_socket_sharing_a_umem_binds_to_the_first_sockets_fd() {
+    let inner = move |dev1_config: VethDevConfig, dev2_config: VethDevConfig| {
+        let frame_count = 64;
+
+        let (umem, descs) = Umem::new(
+            UmemConfig::default(),
+            frame_count.try_into().unwrap(),
+            false,
+        )
+        .unwrap();
+
+        let create = |if_name: &str, libxdp_flags| unsafe {
+            Socket::new(
+                SocketConfig::builder().libxdp_flags(libxdp_flags).build(),
+                &umem,
+                &if_name.parse().unwrap(),
+                0,
+            )
+            .unwrap()
+        };
+
+        let (first_tx_q, first_rx_q, first_fq_and_cq) =
+            create(dev1_config.if_name(), LibxdpFlags::empty());
+
+        let (second_tx_q, second_rx_q, second_fq_and_cq) =
+            create(dev2_config.if_name(), LibxdpFlags::empty());
+
+        // Veth devices are created here with a single queue, so the
+        // third socket has to go on the same device and queue as the
+        // first. Packets arriving there are only ever redirected to
+        // the first, it being the one in the default program's
+        // `XSKMAP`, so the third can only be checked for tx.
+        let (third_tx_q, third_rx_q, third_fq_and_cq) = create(
+            dev1_config.if_name(),
+            LibxdpFlags::XSK_LIBXDP_FLAGS_INHIBIT_PROG_LOAD,
+        );
+
+        let first_fd = first_tx_q.fd().as_raw_fd();
+
+        assert_eq!(first_tx_q.socket().shared_umem_fd(), None);
+        assert_eq!(second_tx_q.socket().shared_umem_fd(), Some(first_fd));
+        assert_eq!(third_tx_q.socket().shared_umem_fd(), Some(first_fd));
+
+        assert!(third_fq_and_cq.is_none());
+
+        let mut descs = descs;
+        let second_descs = descs.drain((frame_count / 2) as usize..).collect();
+
+        let (first_fq, first_cq) = first_fq_and_cq.unwrap();
+        let (second_fq, second_cq) = second_fq_and_cq.unwrap();
+
+        let mut first = Xsk {
+            umem: umem.clone(),
+            fq: first_fq,
+            cq: first_cq,
+            tx_q: first_tx_q,
+            rx_q: first_rx_q,
+            descs,
+        };
+
+        let mut second = Xsk {
+            umem: umem.clone(),
+            fq: second_fq,
+            cq: second_cq,
+            tx_q: second_tx_q,
+            rx_q: second_rx_q,
+            descs: second_descs,
+        };
+
+        send_and_receive_pkt(&mut first, &mut second, &ETHERNET_PACKET[..]);
+        send_and_receive_pkt(&mut second, &mut first, &ETHERNET_PACKET[..]);
+
+        // Sharing the first's device and queue, the third completes
+        // its sends on the first's completion queue
+        let Xsk {
+            fq,
+            cq,
+            descs,
+            tx_q: _first_tx_q,
+            rx_q: _first_rx_q,
+            ..
+        } = first;
+
+        let mut third = Xsk {
+            umem,
+            fq,
+            cq,
+            tx_q: third_tx_q,
+            rx_q: third_rx_q,
+            descs,
+        };
+
+        send_and_receive_pkt(&mut third, &mut second, &ETHERNET_PACKET[..]);
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn splice_forwards_frames_between_sockets_sharing_a_umem() {
+    let inner = move |dev1_config: VethDevConfig, dev2_config: VethDevConfig| {
+        let (umem, mut descs) =
+            Umem::new(UmemConfig::default(), 64.try_into().unwrap(), false).unwrap();
+
+        let mut b_descs = descs.split_off(32);
+
+        let (mut tx_a, _rx_a, fq_and_cq_a) = unsafe {
+            Socket::new(
+                SocketConfig::default(),
+                &umem,
+                &dev1_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        let (_tx_b, mut rx_b, fq_and_cq_b) = unsafe {
+            Socket::new(
+                SocketConfig::default(),
+                &umem,
+                &dev2_config.if_name().parse().unwrap(),
+                0,
+            )
+        }
+        .unwrap();
+
+        let (_fq_a, mut cq_a) = fq_and_cq_a.unwrap();
+        let (mut fq_b, _cq_b) = fq_and_cq_b.unwrap();
+
+        unsafe {
+            assert_eq!(
+                fq_b.produce_and_wakeup(&b_descs[..4], rx_b.fd_mut(), 100)
+                    .unwrap(),
+                4
+            );
+
+            umem.data_mut(&mut descs[0])
+                .cursor()
+                .write_all(&ETHERNET_PACKET[..])
+                .unwrap();
+
+            assert_eq!(tx_a.produce_and_wakeup(&descs[..1]).unwrap(), 1);
+
+            thread::sleep(Duration::from_millis(50));
+
+            // Reclaim the frame sent directly so it isn't taken as one
+            // of B's
+            assert_eq!(cq_a.consume(&mut descs[..1]), 1);
+
+            // Forward what B received back out of A, which lands on B
+            // again
+            assert_eq!(
+                socket::splice(&mut rx_b, &mut tx_a, &mut cq_a, &mut fq_b, 4).unwrap(),
+                1
+            );
+
+            thread::sleep(Duration::from_millis(50));
+
+            assert_eq!(rx_b.consume(&mut b_descs[4..5]), 1);
+            assert_eq!(umem.data(&b_descs[4]).contents(), &ETHERNET_PACKET[..]);
+
+            // The forwarded frame's completion goes back to B's fill
+            // ring rather than being returned
+            assert_eq!(
+                socket::splice(&mut rx_b, &mut tx_a, &mut cq_a, &mut fq_b, 4).unwrap(),
+                0
+            );
+            assert_eq!(cq_a.consume(&mut descs[..1]), 0);
+        }
+    };
+
+    let (dev1_config, dev2_config) = setup::default_veth_dev_configs();
+
+    veth_setup::run_with_veth_pair(inner, dev1_config, dev2_config)
+        .await
+        .unwrap();
+}
+
 #[tokio::test]
 #[serial]
 async fn writing_to_frame_and_reading_works_as_expected() {
@@ -144,6 +408,158 @@ async fn writing_to_frame_and_reading_works_as_expected() {
     }
 }
 
+#[tokio::test]
+#[serial]
+async fn copy_out_returns_owned_copy_of_frame_data() {
+    let (umem, mut descs) =
+        Umem::new(UmemConfig::default(), 64.try_into().unwrap(), false).unwrap();
+
+    let copy = unsafe {
+        umem.data_mut(&mut descs[0])
+            .cursor()
+            .write_all(b"hello")
+            .unwrap();
+
+        umem.copy_out(&descs[0])
+    };
+
+    // Overwriting the frame doesn't affect the copy
+    unsafe {
+        umem.data_mut(&mut descs[0])
+            .contents_mut()
+            .copy_from_slice(b"world");
+    }
+
+    assert_eq!(copy, b"hello");
+}
+
+#[tokio::test]
+#[serial]
+async fn write_large_frame_splits_payload_and_sets_continuation_bits() {
+    let config = UmemConfig::default();
+    let mtu = config.mtu() as usize;
+
+    let (umem, mut descs) = Umem::new(config, 64.try_into().unwrap(), false).unwrap();
+
+    let src: Vec<u8> = (0..(2 * mtu + 10)).map(|i| i as u8).collect();
+
+    let err = unsafe { umem.write_large_frame(&mut descs[..1], &src) }.unwrap_err();
+    assert_eq!(err.additional(), 2);
+
+    let used = unsafe { umem.write_large_frame(&mut descs[..4], &src) }.unwrap();
+    assert_eq!(used, 3);
+
+    for (i, desc) in descs[..3].iter().enumerate() {
+        let expected = &src[i * mtu..std::cmp::min((i + 1) * mtu, src.len())];
+
+        assert_eq!(unsafe { umem.data(desc) }.contents(), expected);
+        assert_eq!(desc.options() & XDP_PKT_CONTD != 0, i < 2);
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn write_frames_from_writes_a_packet_per_frame() {
+    let config = UmemConfig::default();
+    let mtu = config.mtu() as usize;
+
+    let (umem, mut descs) = Umem::new(config, 64.try_into().unwrap(), false).unwrap();
+
+    let src: Vec<u8> = (0..100).map(|i| i as u8).collect();
+
+    // Too large, even though it also runs past the end of the source
+    assert_eq!(
+        unsafe { umem.write_frames_from(&mut descs[..2], &src, &[10, mtu + 1]) },
+        Err(WriteError::PacketTooLarge {
+            index: 1,
+            len: mtu + 1
+        })
+    );
+    assert_eq!(descs[0].lengths().data(), 0);
+
+    assert_eq!(
+        unsafe { umem.write_frames_from(&mut descs[..1], &src, &[5, 5]) },
+        Err(WriteError::LengthMismatch {
+            descs: 1,
+            lengths: 2
+        })
+    );
+    assert_eq!(descs[0].lengths().data(), 0);
+
+    // The last packet runs past the end of the source
+    let written =
+        unsafe { umem.write_frames_from(&mut descs[..4], &src, &[10, 0, 60, 40]) }.unwrap();
+    assert_eq!(written, 3);
+
+    assert_eq!(unsafe { umem.data(&descs[0]) }.contents(), &src[..10]);
+    assert_eq!(unsafe { umem.data(&descs[1]) }.contents(), &[]);
+    assert_eq!(unsafe { umem.data(&descs[2]) }.contents(), &src[10..70]);
+    assert_eq!(descs[3].lengths().data(), 0);
+}
+
+#[tokio::test]
+#[serial]
+async fn reregister_fails_while_umem_is_shared_and_keeps_memory_otherwise() {
+    let (mut umem, mut descs) =
+        Umem::new(UmemConfig::default(), 64.try_into().unwrap(), false).unwrap();
+
+    unsafe {
+        umem.data_mut(&mut descs[0])
+            .cursor()
+            .write_all(b"hello")
+            .unwrap();
+    }
+
+    let clone = umem.clone();
+
+    assert!(umem.reregister(UmemFlags::empty()).is_err());
+
+    drop(clone);
+
+    umem.reregister(UmemFlags::empty()).unwrap();
+
+    assert_eq!(umem.config().flags(), &UmemFlags::empty());
+    assert_eq!(unsafe { umem.data(&descs[0]) }.contents(), b"hello");
+}
+
+#[tokio::test]
+#[serial]
+async fn prefault_descs_leaves_frame_contents_untouched() {
+    let (umem, mut descs) =
+        Umem::new(UmemConfig::default(), 64.try_into().unwrap(), false).unwrap();
+
+    for desc in descs[..8].iter_mut() {
+        unsafe {
+            umem.data_mut(desc)
+                .cursor()
+                .write_all(&ETHERNET_PACKET[..])
+                .unwrap();
+        }
+    }
+
+    // Including the last frame, whose pages end with the region
+    unsafe { umem.prefault_descs(&descs[..8]) };
+    unsafe { umem.prefault_descs(&descs[63..]) };
+
+    for desc in descs[..8].iter() {
+        assert_eq!(unsafe { umem.data(desc) }.contents(), &ETHERNET_PACKET[..]);
+    }
+}
+
+#[test]
+fn max_frames_without_huge_pages_is_non_zero() {
+    let frame_size = FrameSize::new(XDP_UMEM_MIN_CHUNK_SIZE).unwrap();
+
+    assert!(Umem::max_frames(frame_size, false).unwrap() > 0);
+}
+
+#[test]
+fn umem_without_huge_pages_reports_base_pages() {
+    let (umem, _descs) = Umem::new(UmemConfig::default(), 16.try_into().unwrap(), false).unwrap();
+
+    assert_eq!(umem.huge_page_size().unwrap(), None);
+}
+
 fn send_and_receive_pkt(sender: &mut Xsk, receiver: &mut Xsk, pkt: &[u8]) {
     unsafe {
         assert_eq!(